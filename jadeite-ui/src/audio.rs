@@ -0,0 +1,270 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use jadeite::AudioChannel;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::global_state::GlobalState;
+use crate::runner::Runner;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub sample_rate: u32,
+    /// Target amount of queued audio. Lower values react faster but are more
+    /// prone to crackling.
+    pub latency_ms: u32,
+    /// `0.0..=1.0`
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            latency_ms: 60,
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+/// Audio output device, fed from the emulator through a lock-free ring
+/// buffer read on SDL's audio thread.
+pub struct JAudio {
+    device: AudioDevice<AudioSink>,
+    producer: Producer,
+    controls: Arc<Controls>,
+    /// Queued samples to aim for.
+    target: usize,
+}
+
+impl JAudio {
+    pub fn new(global: &GlobalState, settings: &AudioSettings) -> Result<Self, String> {
+        let target = (settings.sample_rate * settings.latency_ms / 1000) as usize;
+        let (producer, consumer) = ring_buffer((target * 4).max(4096));
+
+        let controls = Arc::new(Controls {
+            volume: AtomicU32::new(settings.volume.to_bits()),
+            muted: AtomicBool::new(settings.muted),
+        });
+
+        // Device buffer of roughly a quarter of the target latency.
+        let samples = (target / 4).max(256).next_power_of_two().min(4096) as u16;
+
+        let spec = AudioSpecDesired {
+            freq: Some(settings.sample_rate as i32),
+            channels: Some(1),
+            samples: Some(samples),
+        };
+
+        let sink_controls = controls.clone();
+        let device = global.audio.open_playback(None, &spec, |_| AudioSink {
+            consumer,
+            controls: sink_controls,
+        })?;
+
+        device.resume();
+
+        Ok(Self {
+            device,
+            producer,
+            controls,
+            target,
+        })
+    }
+
+    /// Sample rate the device was actually opened with.
+    pub fn sample_rate(&self) -> u32 {
+        self.device.spec().freq as u32
+    }
+
+    /// Queue a frame's worth of audio.
+    pub fn queue(&mut self, samples: &[f32]) {
+        // Starting out or after an underrun, e.g. coming out of pause: pad
+        // with silence so rate control has something to work with, instead
+        // of taking seconds to build the queue back up.
+        if self.producer.len() == 0 {
+            let silence = vec![0f32; self.target / 2];
+            self.producer.push(&silence);
+        }
+
+        self.producer.push(samples);
+    }
+
+    /// Queued audio relative to the latency target. `1.0` is on target.
+    pub fn fill_level(&self) -> f32 {
+        self.producer.len() as f32 / self.target as f32
+    }
+
+    /// Rate adjustment to feed [`jadeite::Console::set_audio_rate_adjust`], nudging
+    /// the queue towards its target instead of letting it drift.
+    pub fn rate_adjust(&self) -> f64 {
+        let fill = self.fill_level().min(2.0) as f64;
+        1.0 + 0.005 * (1.0 - fill)
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.controls.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        self.controls.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.controls.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.controls.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Audio hotkeys:
+    /// * `M`: mute
+    /// * `-`/`=`: volume down/up
+    /// * `F1`-`F6`: toggle pulse 1, pulse 2, triangle, noise, DMC and
+    ///   expansion audio
+    pub fn process_event(&mut self, event: &Event, runner: &Runner) -> bool {
+        let key = match event {
+            Event::KeyDown { keycode: Some(key), repeat: false, .. } => *key,
+            _ => return false,
+        };
+
+        let channel = match key {
+            Keycode::M => {
+                self.set_muted(!self.is_muted());
+                return true;
+            },
+            Keycode::Minus => {
+                self.set_volume(self.volume() - 0.1);
+                return true;
+            },
+            Keycode::Equals => {
+                self.set_volume(self.volume() + 0.1);
+                return true;
+            },
+            Keycode::F1 => AudioChannel::Pulse1,
+            Keycode::F2 => AudioChannel::Pulse2,
+            Keycode::F3 => AudioChannel::Triangle,
+            Keycode::F4 => AudioChannel::Noise,
+            Keycode::F5 => AudioChannel::Dmc,
+            Keycode::F6 => AudioChannel::Expansion,
+            _ => return false,
+        };
+
+        runner.with(move |nes| {
+            nes.set_audio_channel_muted(channel, !nes.audio_channel_muted(channel));
+        });
+        true
+    }
+}
+
+struct Controls {
+    /// `f32` bits.
+    volume: AtomicU32,
+    muted: AtomicBool,
+}
+
+struct AudioSink {
+    consumer: Consumer,
+    controls: Arc<Controls>,
+}
+
+impl AudioCallback for AudioSink {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let read = self.consumer.pop(out);
+
+        // Underrun: hold the last sample rather than click to zero.
+        let last = if read > 0 { out[read - 1] } else { 0.0 };
+        out[read..].fill(last);
+
+        let volume = match self.controls.muted.load(Ordering::Relaxed) {
+            true => 0.0,
+            false => f32::from_bits(self.controls.volume.load(Ordering::Relaxed)),
+        };
+
+        for s in out.iter_mut() {
+            *s *= volume;
+        }
+    }
+}
+
+/// Single producer, single consumer ring buffer of samples stored as `f32`
+/// bits. `head` is only advanced by the producer, `tail` by the consumer.
+struct Ring {
+    buf: Box<[AtomicU32]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+struct Producer(Arc<Ring>);
+struct Consumer(Arc<Ring>);
+
+fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let buf = (0..capacity.next_power_of_two())
+        .map(|_| AtomicU32::new(0))
+        .collect();
+
+    let ring = Arc::new(Ring {
+        buf,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+
+    (Producer(ring.clone()), Consumer(ring))
+}
+
+impl Ring {
+    fn mask(&self) -> usize {
+        self.buf.len() - 1
+    }
+}
+
+impl Producer {
+    fn len(&self) -> usize {
+        let head = self.0.head.load(Ordering::Relaxed);
+        let tail = self.0.tail.load(Ordering::Acquire);
+        head.wrapping_sub(tail)
+    }
+
+    /// Push as many samples as fit; the rest are dropped.
+    fn push(&mut self, samples: &[f32]) -> usize {
+        let ring = &self.0;
+        let head = ring.head.load(Ordering::Relaxed);
+        let free = ring.buf.len() - self.len();
+        let count = samples.len().min(free);
+
+        for (i, s) in samples.iter().take(count).enumerate() {
+            let idx = head.wrapping_add(i) & ring.mask();
+            ring.buf[idx].store(s.to_bits(), Ordering::Relaxed);
+        }
+
+        ring.head.store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+impl Consumer {
+    fn pop(&mut self, out: &mut [f32]) -> usize {
+        let ring = &self.0;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        let count = out.len().min(head.wrapping_sub(tail));
+
+        for (i, s) in out.iter_mut().take(count).enumerate() {
+            let idx = tail.wrapping_add(i) & ring.mask();
+            *s = f32::from_bits(ring.buf[idx].load(Ordering::Relaxed));
+        }
+
+        ring.tail.store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
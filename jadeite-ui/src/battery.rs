@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use jadeite::Cart;
+
+/// How often battery RAM is checked for changes and written out, so a
+/// crash loses little progress.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Battery-backed RAM kept in a `.sav` file. Only written when it changed
+/// since it was last read or written.
+pub struct BatterySave {
+    path: PathBuf,
+    saved: Vec<u8>,
+    last_flush: Instant,
+}
+
+impl BatterySave {
+    /// Restore the battery RAM of `cart` from `path`, if it has any. A
+    /// missing file is a first run; an unreadable one is reported and the
+    /// game starts with blank RAM.
+    pub fn load(cart: &mut Cart, path: PathBuf) -> Option<Self> {
+        cart.battery_ram()?;
+
+        match fs::read(&path) {
+            Ok(ram) => {
+                if let Err(e) = cart.set_battery_ram(&ram) {
+                    eprintln!("Couldn't load battery save {}: {}", path.display(), e);
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => eprintln!("Couldn't read battery save {}: {}", path.display(), e),
+        }
+
+        Some(Self {
+            saved: cart.battery_ram()?.to_vec(),
+            path,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Whether it's been [`FLUSH_INTERVAL`] since the last flush.
+    pub fn is_due(&self) -> bool {
+        self.last_flush.elapsed() >= FLUSH_INTERVAL
+    }
+
+    /// Write `ram`, the cart's [`Cart::battery_ram`], if it changed.
+    pub fn flush(&mut self, ram: Option<&[u8]>) {
+        self.last_flush = Instant::now();
+
+        let ram = match ram {
+            Some(ram) if ram != self.saved => ram,
+            _ => return,
+        };
+
+        match fs::write(&self.path, ram) {
+            Ok(()) => self.saved = ram.to_vec(),
+            Err(e) => eprintln!("Couldn't write battery save {}: {}", self.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::{Config, FramePacing};
+
+/// NES emulator.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    /// iNES or UNIF ROM file to run, possibly in a `.zip` or `.gz` archive.
+    pub rom: String,
+
+    /// File to run from a zip archive, instead of the first ROM in it.
+    #[clap(long)]
+    pub member: Option<String>,
+
+    /// IPS, BPS or UPS patch to apply, in the order given. Can be repeated.
+    #[clap(long = "patch", parse(from_os_str))]
+    pub patches: Vec<PathBuf>,
+
+    /// Don't apply patches found next to the ROM.
+    #[clap(long)]
+    pub no_auto_patch: bool,
+
+    /// FDS BIOS (`disksys.rom`) for disk images. Overrides the config file.
+    #[clap(long, parse(from_os_str))]
+    pub fds_bios: Option<PathBuf>,
+
+    /// Start executing at this hex address instead of the reset vector,
+    /// e.g. `c000` for nestest's automated mode.
+    #[clap(long, parse(try_from_str = parse_hex))]
+    pub reset_pc: Option<u16>,
+
+    /// Print a trace line for every CPU instruction.
+    #[clap(long)]
+    pub trace: bool,
+
+    /// Check the CPU trace against a reference log, like nestest.log,
+    /// marking the first mismatch.
+    #[clap(long)]
+    pub compare_log: Option<String>,
+
+    /// Frame rate to run at: auto, ntsc or pal. Overrides the config file.
+    #[clap(long, alias = "region")]
+    pub frame_pacing: Option<FramePacing>,
+
+    /// Trust the ROM header even if `paths.rom_database` knows better.
+    #[clap(long)]
+    pub no_rom_database: bool,
+
+    /// Window size as a multiple of the picture. Overrides the config file.
+    #[clap(long)]
+    pub scale: Option<u32>,
+
+    /// Start in fullscreen.
+    #[clap(long)]
+    pub fullscreen: bool,
+
+    /// Run without a window or audio, for `--frames` frames.
+    #[clap(long, requires = "frames")]
+    pub headless: bool,
+
+    /// Number of frames to run headless, then print a hash of the machine
+    /// state.
+    #[clap(long)]
+    pub frames: Option<u64>,
+
+    /// Save state to load after power-on.
+    #[clap(long)]
+    pub load_state: Option<String>,
+
+    /// Where to write a save state: on exit when headless, or with F9.
+    #[clap(long)]
+    pub save_state: Option<String>,
+}
+
+impl Args {
+    /// Apply command line overrides for this run.
+    pub fn apply_to(&self, config: &mut Config) {
+        if let Some(pacing) = self.frame_pacing {
+            config.frame_pacing = pacing;
+        }
+        if let Some(scale) = self.scale {
+            config.video.scale = scale.clamp(1, 8);
+        }
+        if self.fullscreen {
+            config.video.fullscreen = true;
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|e| format!("{}: {}", s, e))
+}
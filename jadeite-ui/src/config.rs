@@ -1,8 +1,267 @@
 #![allow(dead_code, unused)]
 
-pub struct Config {}
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Default)]
+use serde::{Deserialize, Serialize};
+
+use crate::audio::AudioSettings;
+use crate::input;
+use crate::timing::SpeedSettings;
+
+/// How many ROMs `recent_roms` remembers.
+const MAX_RECENT_ROMS: usize = 10;
+
+/// Frontend settings, stored as `config.toml` in the user's config
+/// directory. Missing sections and settings fall back to their defaults.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(alias = "region")]
+    pub frame_pacing: FramePacing,
+    pub video: VideoConfig,
+    pub audio: AudioSettings,
+    pub speed: SpeedSettings,
+    pub input: InputConfig,
+    pub paths: PathsConfig,
+}
+
+/// Frame rate to run at, NTSC's or PAL's. `Auto` goes by the ROM header.
+/// Only pacing changes: the console itself always has NTSC timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FramePacing { Auto, Ntsc, Pal }
+
+impl FromStr for FramePacing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(FramePacing::Auto),
+            "ntsc" => Ok(FramePacing::Ntsc),
+            "pal" => Ok(FramePacing::Pal),
+            _ => Err(format!("unknown frame pacing \"{}\", expected auto, ntsc or pal", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    /// Window size as a multiple of the picture, `1..=8`.
+    pub scale: u32,
+    /// `.pal` file to use instead of the built-in palette.
+    pub palette: Option<PathBuf>,
+    pub fullscreen: bool,
+    /// Wait for the display's refresh when presenting, avoiding tearing.
+    pub vsync: bool,
+    /// Stretch the picture to the NTSC pixel aspect ratio of 8:7.
+    pub aspect_correction: bool,
+    /// Only scale the picture by whole multiples, for sharp pixels.
+    pub integer_scaling: bool,
+    /// Hide the top and bottom 8 lines, which most TVs didn't show.
+    pub crop_overscan: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InputConfig {
+    #[serde(default = "KeyBindings::new")]
+    pub player_1: KeyBindings,
+    #[serde(default = "KeyBindings::player_2")]
+    pub player_2: KeyBindings,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Where the ROM file dialog starts.
+    pub rom_dir: Option<PathBuf>,
+    /// Where battery saves and save states go, next to the ROM if unset.
+    pub save_dir: Option<PathBuf>,
+    /// NES 2.0 XML database, like the full `nes20db.xml`, to correct known
+    /// bad ROM headers from.
+    pub rom_database: Option<PathBuf>,
+    /// FDS BIOS, `disksys.rom`, needed for disk images.
+    pub fds_bios: Option<PathBuf>,
+    /// Most recent first.
+    pub recent_roms: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// Every problem found, one message each.
+    Invalid(PathBuf, Vec<String>),
+    /// No config directory on this system.
+    NoConfigDir,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(path, problems) => {
+                write!(f, "{}: invalid settings:", path.display())?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            },
+            ConfigError::NoConfigDir => write!(f, "couldn't find the user config directory"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// `<config dir>/jadeite/config.toml`, e.g. `~/.config/jadeite/config.toml`.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("jadeite").join("config.toml"))
+    }
+
+    /// Load the user's config, writing out the defaults on first run.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = Self::path().ok_or(ConfigError::NoConfigDir)?;
+
+        if !path.exists() {
+            let config = Self::default();
+            config.save_to(&path)?;
+            return Ok(config);
+        }
+
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let config: Self = toml::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+
+        let problems = config.validate();
+        match problems.is_empty() {
+            true => Ok(config),
+            false => Err(ConfigError::Invalid(path.to_owned(), problems)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let path = Self::path().ok_or(ConfigError::NoConfigDir)?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), ConfigError> {
+        let io_error = |e| ConfigError::Io(path.to_owned(), e);
+
+        let text = toml::to_string(self)
+            .map_err(|e| io_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        fs::write(path, text).map_err(io_error)
+    }
+
+    /// Move `rom` to the top of `recent_roms`.
+    pub fn add_recent_rom(&mut self, rom: &Path) {
+        let recent = &mut self.paths.recent_roms;
+        recent.retain(|r| r != rom);
+        recent.insert(0, rom.to_owned());
+        recent.truncate(MAX_RECENT_ROMS);
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !(1..=8).contains(&self.video.scale) {
+            problems.push(format!("video.scale must be 1-8, got {}", self.video.scale));
+        }
+        if let Some(palette) = &self.video.palette {
+            if !palette.is_file() {
+                problems.push(format!("video.palette: no such file {}", palette.display()));
+            }
+        }
+
+        if let Some(db) = &self.paths.rom_database {
+            if !db.is_file() {
+                problems.push(format!("paths.rom_database: no such file {}", db.display()));
+            }
+        }
+        if let Some(bios) = &self.paths.fds_bios {
+            if !bios.is_file() {
+                problems.push(format!("paths.fds_bios: no such file {}", bios.display()));
+            }
+        }
+
+        let audio = &self.audio;
+        if !(8_000..=192_000).contains(&audio.sample_rate) {
+            problems.push(format!("audio.sample_rate must be 8000-192000, got {}", audio.sample_rate));
+        }
+        if !(1..=1000).contains(&audio.latency_ms) {
+            problems.push(format!("audio.latency_ms must be 1-1000, got {}", audio.latency_ms));
+        }
+        if !(0.0..=1.0).contains(&audio.volume) {
+            problems.push(format!("audio.volume must be 0.0-1.0, got {}", audio.volume));
+        }
+
+        let speed = &self.speed;
+        if !(speed.fast_forward == 0.0 || (1.0..=16.0).contains(&speed.fast_forward)) {
+            problems.push(format!("speed.fast_forward must be 0 (uncapped) or 1-16, got {}", speed.fast_forward));
+        }
+        if !(0.1..=1.0).contains(&speed.slow_motion) {
+            problems.push(format!("speed.slow_motion must be 0.1-1.0, got {}", speed.slow_motion));
+        }
+
+        self.input.player_1.validate("input.player_1", &mut problems);
+        self.input.player_2.validate("input.player_2", &mut problems);
+
+        problems
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frame_pacing: FramePacing::Auto,
+            video: VideoConfig::default(),
+            audio: AudioSettings::default(),
+            speed: SpeedSettings::default(),
+            input: InputConfig::default(),
+            paths: PathsConfig::default(),
+        }
+    }
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            scale: 3,
+            palette: None,
+            fullscreen: false,
+            vsync: true,
+            aspect_correction: true,
+            integer_scaling: true,
+            crop_overscan: false,
+        }
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self { player_1: KeyBindings::new(), player_2: KeyBindings::player_2() }
+    }
+}
+
+/// A key name, then a gamepad input. Stored as `["key", "pad"]` with `""`
+/// for an unbound slot, since TOML has no null.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(from = "[String; 2]", into = "[String; 2]")]
 pub struct Binding(Option<String>, Option<String>);
 
 impl Binding {
@@ -15,7 +274,41 @@ impl Binding {
         let key = key.map(|s| s.to_owned());
         Self(key, None)
     }
+
+    /// Also bind a gamepad button or axis, see [`Binding::pad`].
+    pub fn and_pad<'a, P: Into<Option<&'a str>>>(mut self, pad: P) -> Self {
+        let pad: Option<&str> = pad.into();
+        self.1 = pad.map(|s| s.to_owned());
+        self
+    }
+
+    /// Bound key name.
+    pub fn key(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Bound gamepad input: an SDL game controller button name like `a` or
+    /// `dpup`, or an axis name with a direction like `leftx-`. Triggers
+    /// need no direction.
+    pub fn pad(&self) -> Option<&str> {
+        self.1.as_deref()
+    }
+}
+
+impl From<[String; 2]> for Binding {
+    fn from([key, pad]: [String; 2]) -> Self {
+        let slot = |s: String| Some(s).filter(|s| !s.is_empty());
+        Self(slot(key), slot(pad))
+    }
 }
+
+impl From<Binding> for [String; 2] {
+    fn from(binding: Binding) -> Self {
+        [binding.0.unwrap_or_default(), binding.1.unwrap_or_default()]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct KeyBindings {
     pub up: Binding,
     pub left: Binding,
@@ -27,21 +320,70 @@ pub struct KeyBindings {
     pub y: Binding,
     pub select: Binding,
     pub start: Binding,
+    /// A and B, repeatedly pressed while held.
+    pub turbo_a: Binding,
+    pub turbo_b: Binding,
 }
 
 impl KeyBindings {
     pub fn new() -> Self {
         Self {
-            up: Binding::with("W"),
-            left: Binding::with("A"),
-            down: Binding::with("S"),
-            right: Binding::with("D"),
-            a: Binding::with("RIGHT"),
-            b: Binding::with("LEFT"),
+            up: Binding::with("W").and_pad("dpup"),
+            left: Binding::with("A").and_pad("dpleft"),
+            down: Binding::with("S").and_pad("dpdown"),
+            right: Binding::with("D").and_pad("dpright"),
+            a: Binding::with("RIGHT").and_pad("b"),
+            b: Binding::with("LEFT").and_pad("a"),
             x: Binding::with("UP"),
             y: Binding::with("DOWN"),
-            select: Binding::with("TAB"),
-            start: Binding::with("ENTER"),
+            select: Binding::with("TAB").and_pad("back"),
+            start: Binding::with("ENTER").and_pad("start"),
+            turbo_a: Binding::new().and_pad("x"),
+            turbo_b: Binding::new().and_pad("y"),
+        }
+    }
+
+    /// Second player: gamepad only by default.
+    pub fn player_2() -> Self {
+        Self {
+            up: Binding::new().and_pad("dpup"),
+            left: Binding::new().and_pad("dpleft"),
+            down: Binding::new().and_pad("dpdown"),
+            right: Binding::new().and_pad("dpright"),
+            a: Binding::new().and_pad("b"),
+            b: Binding::new().and_pad("a"),
+            x: Binding::new(),
+            y: Binding::new(),
+            select: Binding::new().and_pad("back"),
+            start: Binding::new().and_pad("start"),
+            turbo_a: Binding::new().and_pad("x"),
+            turbo_b: Binding::new().and_pad("y"),
+        }
+    }
+
+    fn validate(&self, section: &str, problems: &mut Vec<String>) {
+        let bindings = [
+            ("up", &self.up),
+            ("left", &self.left),
+            ("down", &self.down),
+            ("right", &self.right),
+            ("a", &self.a),
+            ("b", &self.b),
+            ("x", &self.x),
+            ("y", &self.y),
+            ("select", &self.select),
+            ("start", &self.start),
+            ("turbo_a", &self.turbo_a),
+            ("turbo_b", &self.turbo_b),
+        ];
+
+        for (name, binding) in bindings.iter() {
+            if let Some(key) = binding.key().filter(|k| !input::is_valid_key(k)) {
+                problems.push(format!("{}.{}: unknown key \"{}\"", section, name, key));
+            }
+            if let Some(pad) = binding.pad().filter(|p| !input::is_valid_pad_input(p)) {
+                problems.push(format!("{}.{}: unknown gamepad input \"{}\"", section, name, pad));
+            }
         }
     }
 }
@@ -1,18 +1,18 @@
 use std::io::{ErrorKind, Read, Write};
-use std::str::Lines;
+use std::vec;
 
-pub(crate) struct DebugOut<'a> {
-    log: Lines<'a>,
+pub(crate) struct DebugOut {
+    log: vec::IntoIter<String>,
     accum: Vec<u8>,
     last: usize,
     line: String,
     error_state: bool,
 }
 
-impl<'a> DebugOut<'a> {
-    pub(crate) fn new(s: &'a str) -> Self {
+impl DebugOut {
+    pub(crate) fn new(s: &str) -> Self {
         Self {
-            log: s.lines(),
+            log: s.lines().map(str::to_owned).collect::<Vec<_>>().into_iter(),
             accum: Vec::new(),
             last: 0,
             line: String::new(),
@@ -90,7 +90,7 @@ impl<'a> DebugOut<'a> {
     }
 }
 
-impl<'a> Write for DebugOut<'a> {
+impl Write for DebugOut {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         // let s = from_utf8(buf).unwrap();
         // self.match_next(s);
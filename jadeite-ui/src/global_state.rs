@@ -1,6 +1,8 @@
 pub struct GlobalState {
     pub context: sdl2::Sdl,
     pub video: sdl2::VideoSubsystem,
+    pub audio: sdl2::AudioSubsystem,
+    pub controller: sdl2::GameControllerSubsystem,
     pub event_pump: sdl2::EventPump,
 }
 
@@ -8,7 +10,9 @@ impl GlobalState {
     pub fn init() -> Self {
         let context = sdl2::init().unwrap();
         let video = context.video().unwrap();
+        let audio = context.audio().unwrap();
+        let controller = context.game_controller().unwrap();
         let event_pump = context.event_pump().unwrap();
-        Self { context, video, event_pump }
+        Self { context, video, audio, controller, event_pump }
     }
 }
@@ -0,0 +1,167 @@
+use jadeite::Button;
+use sdl2::controller::{self, Axis, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
+use sdl2::GameControllerSubsystem;
+
+use crate::config::{Binding, KeyBindings};
+use crate::global_state::GlobalState;
+
+/// How far a stick has to be pushed to count as pressed.
+const AXIS_THRESHOLD: i16 = i16::MAX / 2;
+/// Turbo buttons alternate between pressed and released every this many
+/// frames.
+const TURBO_PERIOD: u64 = 2;
+
+/// A gamepad button, or an axis pushed past the threshold in one direction.
+enum PadInput {
+    Button(controller::Button),
+    Axis(Axis, bool),
+}
+
+struct Mapping {
+    button: Button,
+    turbo: bool,
+    keys: Vec<Scancode>,
+    pad: Vec<PadInput>,
+}
+
+/// Keyboard and gamepad to controller mapping for both players.
+///
+/// Gamepads are picked up as they're plugged in, SDL reports those present
+/// at startup the same way. Each one goes to the first player without a
+/// gamepad; extra ones are left unused.
+pub struct JInput {
+    subsystem: GameControllerSubsystem,
+    players: Vec<Vec<Mapping>>,
+    pads: Vec<Option<GameController>>,
+}
+
+impl JInput {
+    pub fn new(global: &GlobalState, players: &[&KeyBindings]) -> Self {
+        let players = players.iter().map(|keys| mappings(keys)).collect::<Vec<_>>();
+        let pads = players.iter().map(|_| None).collect();
+
+        Self { subsystem: global.controller.clone(), players, pads }
+    }
+
+    pub fn process_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(slot) = self.pads.iter_mut().find(|p| p.is_none()) {
+                    match self.subsystem.open(which) {
+                        Ok(pad) => *slot = Some(pad),
+                        Err(e) => eprintln!("Couldn't open gamepad {}: {}", which, e),
+                    }
+                }
+                true
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                for slot in self.pads.iter_mut() {
+                    if slot.as_ref().map(|p| p.instance_id()) == Some(which) {
+                        *slot = None;
+                    }
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Controller buttons from the keys and gamepad buttons currently
+    /// held, one byte per player. Turbo buttons toggle by `frame`, the
+    /// console's frame count.
+    pub fn buttons(&self, keyboard: &KeyboardState, frame: u64) -> Vec<u8> {
+        let turbo_on = (frame / TURBO_PERIOD) & 1 == 0;
+
+        self.players.iter().zip(&self.pads).map(|(mappings, pad)| {
+            let mut buttons = 0;
+
+            for mapping in mappings.iter() {
+                let held = mapping.keys.iter().any(|c| keyboard.is_scancode_pressed(*c))
+                    || pad.as_ref().is_some_and(|pad| {
+                        mapping.pad.iter().any(|input| is_pad_pressed(pad, input))
+                    });
+
+                if held && (!mapping.turbo || turbo_on) {
+                    buttons |= mapping.button.bit();
+                }
+            }
+
+            buttons
+        }).collect()
+    }
+}
+
+fn mappings(keys: &KeyBindings) -> Vec<Mapping> {
+    let bindings = [
+        (Button::A, false, &keys.a),
+        (Button::B, false, &keys.b),
+        (Button::Select, false, &keys.select),
+        (Button::Start, false, &keys.start),
+        (Button::Up, false, &keys.up),
+        (Button::Down, false, &keys.down),
+        (Button::Left, false, &keys.left),
+        (Button::Right, false, &keys.right),
+        (Button::A, true, &keys.turbo_a),
+        (Button::B, true, &keys.turbo_b),
+    ];
+
+    bindings.iter()
+        .map(|&(button, turbo, binding)| Mapping {
+            button,
+            turbo,
+            keys: scancodes(binding),
+            pad: binding.pad().and_then(pad_from_name).into_iter().collect(),
+        })
+        .collect()
+}
+
+fn is_pad_pressed(pad: &GameController, input: &PadInput) -> bool {
+    match *input {
+        PadInput::Button(button) => pad.button(button),
+        PadInput::Axis(axis, true) => pad.axis(axis) > AXIS_THRESHOLD,
+        PadInput::Axis(axis, false) => pad.axis(axis) < -AXIS_THRESHOLD,
+    }
+}
+
+fn scancodes(binding: &Binding) -> Vec<Scancode> {
+    binding.key()
+        .and_then(key_from_name)
+        .and_then(Scancode::from_keycode)
+        .into_iter()
+        .collect()
+}
+
+pub fn is_valid_key(name: &str) -> bool {
+    key_from_name(name).and_then(Scancode::from_keycode).is_some()
+}
+
+pub fn is_valid_pad_input(name: &str) -> bool {
+    pad_from_name(name).is_some()
+}
+
+/// SDL key names, plus a few common aliases SDL doesn't know.
+fn key_from_name(name: &str) -> Option<Keycode> {
+    match name.to_ascii_uppercase().as_str() {
+        "ENTER" => Some(Keycode::Return),
+        "ESC" => Some(Keycode::Escape),
+        _ => Keycode::from_name(name),
+    }
+}
+
+/// SDL game controller button or axis names, axes suffixed with `+` or `-`.
+fn pad_from_name(name: &str) -> Option<PadInput> {
+    let name = name.to_ascii_lowercase();
+
+    if let Some(button) = controller::Button::from_string(&name) {
+        return Some(PadInput::Button(button));
+    }
+
+    let (axis, positive) = match name.strip_suffix('-') {
+        Some(axis) => (axis, false),
+        None => (name.strip_suffix('+').unwrap_or(&name), true),
+    };
+
+    Axis::from_string(axis).map(|axis| PadInput::Axis(axis, positive))
+}
@@ -1,82 +1,375 @@
+mod audio;
+mod battery;
+mod cli;
 mod debug;
 mod window;
 mod config;
 mod global_state;
+mod input;
+mod runner;
 mod text;
+mod timing;
 
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
-use jadeite::{Console, Cart};
+use clap::Parser;
+use jadeite::{Console, Cart, LoadOptions, Palette, RomDb, FRAME_RATE_NTSC, FRAME_RATE_PAL};
+use audio::JAudio;
+use battery::BatterySave;
+use cli::Args;
+use config::{Config, FramePacing};
 use debug::DebugOut;
 use text::TextRenderer;
 use window::{JWindow, PixelBuffer};
 use global_state::GlobalState;
+use input::JInput;
+use runner::{Frame, FrameInput, Runner};
+use timing::Pacer;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::messagebox::{self, MessageBoxFlag};
 
-const WIDTH: u32 = 960;
-const HEIGHT: u32 = 540;
+fn main() {
+    let args = Args::parse();
+
+    let loaded = Config::load();
+    let config_ok = loaded.is_ok();
+    let mut config = loaded.unwrap_or_else(|e| {
+        eprintln!("Using default settings. {}", e);
+        Config::default()
+    });
+
+    let rom_db = match &config.paths.rom_database {
+        Some(path) if !args.no_rom_database => RomDb::from_file(path).map_err(|e| {
+            eprintln!("Trusting the ROM header. {}", e);
+        }).ok(),
+        _ => None,
+    };
+
+    let options = LoadOptions {
+        rom_db: rom_db.as_ref(),
+        archive_member: args.member.as_deref(),
+        patches: &args.patches,
+        find_patches: !args.no_auto_patch,
+        fds_bios: args.fds_bios.as_deref().or(config.paths.fds_bios.as_deref()),
+    };
+
+    let mut cart = Cart::read_file_with(&args.rom, &options).unwrap_or_else(|e| {
+        let message = format!("Couldn't load {}: {}", args.rom, e);
+        show_error(&message, args.headless);
+        process::exit(1);
+    });
+
+    for patch in &cart.data.patches {
+        eprintln!("Applied patch {}", patch.display());
+    }
+    if !cart.data.header_fixes.is_empty() {
+        eprintln!("ROM header corrected from the game database:");
+        for fix in &cart.data.header_fixes {
+            eprintln!("  {}", fix);
+        }
+    }
+
+    let disk_path = save_path(&args.rom, "fdsdiff", &config);
+    if let Some(disk) = cart.disk_mut() {
+        match fs::read(&disk_path) {
+            Ok(diff) => {
+                if let Err(e) = disk.apply_diff(&diff) {
+                    eprintln!("Couldn't restore disk changes from {}: {}", disk_path.display(), e);
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => eprintln!("Couldn't read disk changes {}: {}", disk_path.display(), e),
+        }
+    }
+
+    let mut battery = BatterySave::load(&mut cart, save_path(&args.rom, "sav", &config));
+
+    // Don't overwrite a config file the user has to fix first.
+    config.add_recent_rom(Path::new(&args.rom));
+    if config_ok {
+        if let Err(e) = config.save() {
+            eprintln!("Couldn't save settings. {}", e);
+        }
+    }
+    args.apply_to(&mut config);
+
+    let log = args.compare_log.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Error reading log file {}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let frame_rate = match config.frame_pacing {
+        FramePacing::Ntsc => FRAME_RATE_NTSC,
+        FramePacing::Pal => FRAME_RATE_PAL,
+        FramePacing::Auto => cart.data.tv_system.frame_rate(),
+    };
 
-fn main() -> Result<(), ()> {
-    let mut cart = Cart::read_file("resources/nestest.nes").map_err(|_| ())?;
     let mut nes = Console::new();
-    nes.insert_cart(&mut cart);
-    nes.reset_to(0xc000);
-    // nes.reset();
+    nes.insert_cart(Box::new(cart));
+    match args.reset_pc {
+        Some(pc) => nes.reset_to(pc),
+        None => nes.reset(),
+    }
+
+    if let Some(path) = &config.video.palette {
+        match Palette::from_file(path) {
+            Ok(palette) => nes.set_palette(palette),
+            Err(e) => eprintln!("Couldn't load palette, using the default. {}", e),
+        }
+    }
+
+    if let Some(log) = &log {
+        nes.cpu.debug_to(Box::new(DebugOut::new(log)));
+    } else if args.trace {
+        nes.cpu.debug_to(Box::new(io::stdout()));
+    }
+
+    if let Some(path) = &args.load_state {
+        let loaded = match fs::read(path) {
+            Ok(state) => nes.load_state(&state).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = loaded {
+            eprintln!("Couldn't load save state {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
+    let state_path = match &args.save_state {
+        Some(path) => PathBuf::from(path),
+        None => save_path(&args.rom, "state", &config),
+    };
+
+    let mut nes = match args.frames {
+        Some(frames) if args.headless => {
+            run_headless(&mut nes, frames, args.save_state.as_ref());
+            nes
+        },
+        _ => run_windowed(nes, config, frame_rate, &state_path, battery.as_mut()),
+    };
+
+    if let Some(battery) = &mut battery {
+        battery.flush(nes.cart().and_then(|c| c.battery_ram()));
+    }
+    save_disk(&mut nes, &disk_path);
+}
+
+/// Keep what an FDS game wrote to its disk, as a diff against the image.
+fn save_disk(nes: &mut Console, path: &Path) {
+    let disk = match nes.cart_mut().and_then(|c| c.disk_mut()) {
+        Some(disk) if disk.is_modified() => disk,
+        _ => return,
+    };
+
+    match fs::write(path, disk.diff()) {
+        Ok(()) => disk.mark_saved(),
+        Err(e) => eprintln!("Couldn't save disk changes {}: {}", path.display(), e),
+    }
+}
 
-    let mut f = File::open("resources/nestest.log").unwrap();
-    let mut s = String::new();
-    f.read_to_string(&mut s).map_err(|_| panic!("hurr durr!"))?;
+/// Report an error on stderr, and in a message box unless headless since
+/// there may be no terminal to see it.
+fn show_error(message: &str, headless: bool) {
+    eprintln!("{}", message);
 
-    // let mut out = DebugOut::new(&s);
-    let mut out = &mut std::io::stdout();
-    nes.cpu.debug_to(&mut out);
+    if !headless {
+        // Already on stderr, nothing more to do if this fails too.
+        let _ = messagebox::show_simple_message_box(
+            MessageBoxFlag::ERROR,
+            "Jadeite",
+            message,
+            None::<&sdl2::video::Window>,
+        );
+    }
+}
 
-    // println!("{}", nes);
+/// Run `frames` frames as fast as possible, then print the picture's hash.
+fn run_headless(nes: &mut Console, frames: u64, save_state: Option<&String>) {
+    let mut scratch = vec![0f32; 4096];
 
+    while nes.frame_count() < frames {
+        nes.run_frame();
+
+        // Nobody's listening, don't let samples pile up.
+        while nes.drain_audio(&mut scratch) > 0 {}
+    }
+
+    println!("frames: {} hash: {:016x}", nes.frame_count(), nes.state_hash());
+
+    if let Some(path) = save_state {
+        if let Err(e) = fs::write(path, nes.save_state()) {
+            eprintln!("Couldn't write save state {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Run in a window, with the console on an emulation thread. Hands the
+/// console back when the window is closed.
+fn run_windowed(
+    mut nes: Console,
+    config: Config,
+    frame_rate: f64,
+    state_path: &Path,
+    mut battery: Option<&mut BatterySave>,
+) -> Console {
     let mut global_state = GlobalState::init();
 
-    let mut overlay = PixelBuffer::new(WIDTH, HEIGHT);
-    let mut win = JWindow::new(&global_state, WIDTH, HEIGHT);
+    let mut win = JWindow::new(&global_state, &config.video);
+    let mut overlay = PixelBuffer::new(win.buffer().width(), win.buffer().height());
     let text_renderer = TextRenderer::new("resources/OpenSans-Regular.ttf");
 
+    let mut audio = JAudio::new(&global_state, &config.audio).unwrap_or_else(|e| {
+        eprintln!("Couldn't open audio device: {}", e);
+        process::exit(1);
+    });
+    nes.set_audio_sample_rate(audio.sample_rate());
+
+    let mut input = JInput::new(&global_state, &[&config.input.player_1, &config.input.player_2]);
+    let mut pacer = Pacer::new(frame_rate, config.speed);
+
+    // The console always runs NTSC timing, so at another frame rate it gets
+    // through fewer or more cycles per second. Stretch the audio to match.
+    let audio_ratio = FRAME_RATE_NTSC / frame_rate;
 
-    let cpf = 29833;
-    let cpf = cpf/100;
+    // The palette is set before starting, only pictures need sending over.
+    let palette = nes.ppu().palette().clone();
+    let mut shown = Frame {
+        number: nes.frame_count(),
+        picture: nes.ppu().frame().to_vec(),
+        audio: Vec::new(),
+        clock_count: nes.cpu.clock_count,
+    };
+    let mut runner = Runner::spawn(nes);
 
     loop {
         // Input
         for event in global_state.event_pump.poll_iter() {
-            let _processed = win.process_event(&event);
+            let _processed = win.process_event(&event)
+                || audio.process_event(&event, &runner)
+                || pacer.process_event(&event)
+                || input.process_event(&event)
+                || process_state_event(&event, &runner, state_path)
+                || process_disk_event(&event, &runner);
         }
 
         // Update
-        for _ in 0..cpf {
-            nes.step();
-            // let mut s = String::new();
-            // nes.bus.print_page(&mut s, 0x0100).unwrap();
-            // println!("{}", s);
-            // print!("{}", nes.cpu);
+        pacer.begin();
+        while pacer.next_frame() {
+            let frame_input = FrameInput {
+                buttons: input.buttons(&global_state.event_pump.keyboard_state(), shown.number),
+                audio_rate_adjust: audio.rate_adjust() * audio_ratio,
+                keep_audio: pacer.is_normal_speed(),
+            };
+
+            if let Some(frame) = runner.run_frame(frame_input) {
+                if !frame.audio.is_empty() {
+                    audio.queue(&frame.audio);
+                }
+                shown = frame;
+            }
+        }
+
+        if let Some(battery) = battery.as_mut().filter(|b| b.is_due()) {
+            let ram = runner.with(|nes| nes.cart().and_then(|c| c.battery_ram()).map(<[u8]>::to_vec));
+            battery.flush(ram.as_deref());
         }
 
 
-        // Draw
+        // Draw, while the frame last started runs.
         win.clear();
+        win.set_picture(&shown.picture, &palette);
 
-        update_overlay(&mut overlay, &text_renderer, &nes);
+        // The window may have been resized since the last frame.
+        let (w, h) = (win.buffer().width(), win.buffer().height());
+        if (overlay.width(), overlay.height()) != (w, h) {
+            overlay = PixelBuffer::new(w, h);
+        }
+        update_overlay(&mut overlay, &text_renderer, shown.clock_count, pacer.status());
         overlay.blit_to_buffer(win.buffer().pixels_mut());
 
         win.draw();
 
+        if !config.video.vsync {
+            pacer.wait();
+        }
+
 
         if win.is_done() {
-            break Ok(())
+            break;
         }
     }
 
-    // Ok(())
+    runner.stop()
 }
 
-fn update_overlay(pb: &mut PixelBuffer, tr: &TextRenderer, nes: &Console) {
+/// Save state hotkeys: `F9` saves to `path`, `F10` loads it back.
+fn process_state_event(event: &Event, runner: &Runner, path: &Path) -> bool {
+    let key = match event {
+        Event::KeyDown { keycode: Some(key), repeat: false, .. } => *key,
+        _ => return false,
+    };
+
+    match key {
+        Keycode::F9 => {
+            let state = runner.with(|nes| nes.save_state());
+            if let Err(e) = fs::write(path, state) {
+                eprintln!("Couldn't write save state {}: {}", path.display(), e);
+            }
+            true
+        },
+        Keycode::F10 => {
+            let loaded = match fs::read(path) {
+                Ok(state) => runner.with(move |nes| nes.load_state(&state)).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = loaded {
+                eprintln!("Couldn't load save state {}: {}", path.display(), e);
+            }
+            true
+        },
+        _ => false,
+    }
+}
+
+/// FDS hotkey: `F7` flips to the next disk side.
+fn process_disk_event(event: &Event, runner: &Runner) -> bool {
+    match event {
+        Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } => {},
+        _ => return false,
+    }
+
+    let side = runner.with(|nes| {
+        let disk = nes.cart_mut()?.disk_mut()?;
+        let side = disk.inserted_side().map_or(0, |side| (side + 1) % disk.side_count());
+        disk.insert_side(side);
+        Some(side)
+    });
+    if let Some(side) = side {
+        eprintln!("Disk {} side {}", side / 2 + 1, if side % 2 == 0 { 'A' } else { 'B' });
+    }
+    true
+}
+
+/// `<rom name>.<ext>`, in the configured save directory or next to the ROM.
+fn save_path(rom: &str, ext: &str, config: &Config) -> PathBuf {
+    let rom = Path::new(rom);
+    let name = rom.with_extension(ext);
+    let name = name.file_name().unwrap_or_default();
+
+    match &config.paths.save_dir {
+        Some(dir) => dir.join(name),
+        None => rom.with_extension(ext),
+    }
+}
+
+fn update_overlay(pb: &mut PixelBuffer, tr: &TextRenderer, clock_count: usize, status: Option<&str>) {
     pb.clear();
 
     // nes.bus.print_page(&mut s, 0x00).unwrap();
@@ -89,7 +382,11 @@ fn update_overlay(pb: &mut PixelBuffer, tr: &TextRenderer, nes: &Console) {
     // );
     // tr.render_text(&s, pb, 100, 150);
 
-    let ss = format!("{}", nes.cpu.clock_count);
+    let ss = format!("{}", clock_count);
     tr.render_text(&ss, pb, 300, 50);
 
+    if let Some(status) = status {
+        tr.render_text(status, pb, 20, 20);
+    }
+
 }
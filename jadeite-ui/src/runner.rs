@@ -0,0 +1,160 @@
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use jadeite::Console;
+
+/// What to run the next frame with.
+pub struct FrameInput {
+    /// Controller buttons, one byte per port from port 1.
+    pub buttons: Vec<u8>,
+    /// See [`Console::set_audio_rate_adjust`].
+    pub audio_rate_adjust: f64,
+    /// Whether to send the frame's audio back. Off when not running in
+    /// real time, to throw it away on the emulation thread instead.
+    pub keep_audio: bool,
+}
+
+/// A finished frame.
+pub struct Frame {
+    /// Frames started since power-on, see [`Console::frame_count`].
+    pub number: u64,
+    /// Palette indices, as in [`jadeite::Ppu::frame`].
+    pub picture: Vec<u8>,
+    pub audio: Vec<f32>,
+    pub clock_count: usize,
+}
+
+enum Command {
+    RunFrame(FrameInput),
+    /// Anything else, like saving a state or flipping a disk.
+    With(Box<dyn FnOnce(&mut Console) + Send>),
+}
+
+/// Runs a console on an emulation thread, exchanging input, frames and
+/// audio with it over channels.
+///
+/// At most one frame is in flight: starting a frame waits for the one
+/// before it, so the main thread can present a picture while the next
+/// frame is emulated without the two drifting apart.
+pub struct Runner {
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    thread: JoinHandle<Console>,
+    in_flight: bool,
+}
+
+impl Runner {
+    pub fn spawn(nes: Console) -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (frame_tx, frames) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("emulation".to_owned())
+            .spawn(move || emulate(nes, command_rx, frame_tx))
+            .expect("couldn't start the emulation thread");
+
+        Self { commands, frames, thread, in_flight: false }
+    }
+
+    /// Start the next frame, returning the one before it if that was still
+    /// running.
+    pub fn run_frame(&mut self, input: FrameInput) -> Option<Frame> {
+        let previous = self.finish_frame();
+        self.send(Command::RunFrame(input));
+        self.in_flight = true;
+        previous
+    }
+
+    /// Wait for the frame in flight, if any.
+    pub fn finish_frame(&mut self) -> Option<Frame> {
+        if !std::mem::take(&mut self.in_flight) {
+            return None;
+        }
+
+        match self.frames.recv() {
+            Ok(frame) => Some(frame),
+            Err(_) => self.crashed(),
+        }
+    }
+
+    /// Run `f` on the console between frames and wait for its result.
+    pub fn with<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Console) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.send(Command::With(Box::new(move |nes| {
+            // Only fails if the caller is gone, which it can't be.
+            let _ = reply.send(f(nes));
+        })));
+
+        match result.recv() {
+            Ok(result) => result,
+            Err(_) => self.crashed(),
+        }
+    }
+
+    /// Stop the emulation thread and take the console back.
+    pub fn stop(self) -> Console {
+        drop(self.commands);
+        self.thread.join().unwrap_or_else(|e| panic::resume_unwind(e))
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            self.crashed();
+        }
+    }
+
+    /// The thread only goes away by panicking, which it has reported.
+    fn crashed(&self) -> ! {
+        panic!("emulation thread stopped");
+    }
+}
+
+/// Emulation thread: handle commands until the runner is dropped, then
+/// hand the console back.
+fn emulate(mut nes: Console, commands: Receiver<Command>, frames: Sender<Frame>) -> Console {
+    let mut scratch = vec![0f32; 2048];
+
+    for command in commands {
+        let input = match command {
+            Command::RunFrame(input) => input,
+            Command::With(f) => {
+                f(&mut nes);
+                continue;
+            },
+        };
+
+        for (port, buttons) in input.buttons.iter().enumerate() {
+            nes.set_buttons(port, *buttons);
+        }
+        nes.set_audio_rate_adjust(input.audio_rate_adjust);
+        nes.run_frame();
+
+        let mut audio = Vec::new();
+        loop {
+            let count = nes.drain_audio(&mut scratch);
+            if count == 0 {
+                break;
+            }
+            if input.keep_audio {
+                audio.extend_from_slice(&scratch[..count]);
+            }
+        }
+
+        let frame = Frame {
+            number: nes.frame_count(),
+            picture: nes.ppu().frame().to_vec(),
+            audio,
+            clock_count: nes.cpu.clock_count,
+        };
+        if frames.send(frame).is_err() {
+            break;
+        }
+    }
+
+    nes
+}
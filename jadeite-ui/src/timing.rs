@@ -0,0 +1,175 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use serde::{Deserialize, Serialize};
+
+/// Most frames run to catch up after a stall, e.g. the window being
+/// dragged. Anything beyond that is dropped rather than fast-forwarded.
+const MAX_CATCH_UP: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedSettings {
+    /// Fast-forward speed as a multiple of normal, `0` for as fast as
+    /// possible.
+    pub fast_forward: f64,
+    /// Slow-motion speed as a fraction of normal.
+    pub slow_motion: f64,
+}
+
+impl Default for SpeedSettings {
+    fn default() -> Self {
+        Self { fast_forward: 0.0, slow_motion: 0.5 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Speed { Normal, FastForward, SlowMotion }
+
+/// Frame pacing by frame-time accumulation: real time passed is added up,
+/// scaled by the current speed, and paid out one frame at a time.
+///
+/// With vsync the wait happens when presenting; without it the pacer sleeps
+/// until the next frame is due.
+pub struct Pacer {
+    frame_time: Duration,
+    settings: SpeedSettings,
+    speed: Speed,
+    paused: bool,
+    /// Frame advance pressed while paused.
+    advance: bool,
+    /// Emulated time owed, in frame-time units at normal speed.
+    lag: Duration,
+    last: Instant,
+    /// Start of the current main loop iteration.
+    started: Instant,
+}
+
+impl Pacer {
+    pub fn new(frame_rate: f64, settings: SpeedSettings) -> Self {
+        let now = Instant::now();
+
+        Self {
+            frame_time: Duration::from_secs_f64(1.0 / frame_rate),
+            settings,
+            speed: Speed::Normal,
+            paused: false,
+            advance: false,
+            lag: Duration::ZERO,
+            last: now,
+            started: now,
+        }
+    }
+
+    /// Whether frames run in real time, so audio should be played.
+    pub fn is_normal_speed(&self) -> bool {
+        self.speed == Speed::Normal && !self.paused
+    }
+
+    /// Shown on screen while not running normally.
+    pub fn status(&self) -> Option<&'static str> {
+        match (self.paused, self.speed) {
+            (true, _) => Some("Paused"),
+            (false, Speed::FastForward) => Some("Fast forward"),
+            (false, Speed::SlowMotion) => Some("Slow motion"),
+            (false, Speed::Normal) => None,
+        }
+    }
+
+    /// Speed hotkeys:
+    /// * `` ` ``: fast-forward while held
+    /// * `\`: toggle slow motion
+    /// * `P`: pause
+    /// * `.`: advance one frame, pausing first if needed
+    pub fn process_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::KeyDown { keycode: Some(Keycode::Backquote), repeat: false, .. } => {
+                self.speed = Speed::FastForward;
+                true
+            },
+            Event::KeyUp { keycode: Some(Keycode::Backquote), .. } => {
+                self.speed = Speed::Normal;
+                true
+            },
+            Event::KeyDown { keycode: Some(Keycode::Backslash), repeat: false, .. } => {
+                self.speed = match self.speed {
+                    Speed::SlowMotion => Speed::Normal,
+                    _ => Speed::SlowMotion,
+                };
+                true
+            },
+            Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                self.paused = !self.paused;
+                true
+            },
+            Event::KeyDown { keycode: Some(Keycode::Period), .. } => {
+                self.paused = true;
+                self.advance = true;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Start a main loop iteration: account for the time since the last one.
+    pub fn begin(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        self.started = now;
+
+        if self.paused {
+            self.lag = Duration::ZERO;
+            return;
+        }
+
+        let scale = match self.speed {
+            Speed::Normal => 1.0,
+            Speed::FastForward => self.settings.fast_forward,
+            Speed::SlowMotion => self.settings.slow_motion,
+        };
+        self.lag += elapsed.mul_f64(scale);
+        self.lag = self.lag.min(self.frame_time * MAX_CATCH_UP);
+    }
+
+    /// Whether to run another frame this iteration.
+    pub fn next_frame(&mut self) -> bool {
+        if self.paused {
+            return std::mem::take(&mut self.advance);
+        }
+
+        // Uncapped: as many frames as fit in one frame's time, so the
+        // window still gets redrawn and events handled.
+        if self.speed == Speed::FastForward && self.settings.fast_forward == 0.0 {
+            return self.started.elapsed() < self.frame_time;
+        }
+
+        match self.lag.checked_sub(self.frame_time) {
+            Some(lag) => {
+                self.lag = lag;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Sleep until the next frame is due. Not needed with vsync.
+    pub fn wait(&self) {
+        let scale = match (self.paused, self.speed) {
+            (true, _) => 1.0,
+            (false, Speed::Normal) => 1.0,
+            (false, Speed::FastForward) if self.settings.fast_forward == 0.0 => return,
+            (false, Speed::FastForward) => self.settings.fast_forward,
+            (false, Speed::SlowMotion) => self.settings.slow_motion,
+        };
+
+        let owed = self.frame_time.saturating_sub(self.lag).div_f64(scale);
+        let until = self.last + owed;
+        let now = Instant::now();
+        if until > now {
+            thread::sleep(until - now);
+        }
+    }
+}
@@ -1,30 +1,58 @@
 #![allow(dead_code, unused)]
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture};
-use sdl2::video::Window;
+use sdl2::video::{FullscreenType, Window};
 
+use jadeite::{Palette, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::config::VideoConfig;
 use crate::global_state::GlobalState;
 
+/// Lines hidden at the top and bottom with overscan cropping.
+const OVERSCAN: u32 = 8;
+
+/// Shows the emulated picture, scaled to fit the window, with the overlay
+/// drawn on top at the window's own resolution.
 pub struct JWindow {
     canvas: Canvas<Window>,
+    picture_tex: Texture,
     screen_tex: Texture,
     screen_buf: PixelBuffer,
     bg_color: Color,
+    fullscreen: bool,
+    aspect_correction: bool,
+    integer_scaling: bool,
+    crop_overscan: bool,
     done: bool,
 }
 
 impl JWindow {
-    pub fn new(global: &GlobalState, w: u32, h: u32) -> Self {
+    pub fn new(global: &GlobalState, config: &VideoConfig) -> Self {
         let video = &global.video;
 
-        let win = video.window("Jadeite", w, h).build().unwrap();
-        let canvas = win.into_canvas().accelerated().build().unwrap();
+        let (pw, ph) = picture_size(config.aspect_correction, config.crop_overscan);
+        let (w, h) = (pw * config.scale, ph * config.scale);
+
+        let win = video.window("Jadeite", w, h).resizable().build().unwrap();
+        let canvas = match config.vsync {
+            true => win.into_canvas().accelerated().present_vsync().build().unwrap(),
+            false => win.into_canvas().accelerated().build().unwrap(),
+        };
         let screen_buf = PixelBuffer::new(w, h);
 
+        let picture_tex = canvas
+            .create_texture(
+                PixelFormatEnum::RGBA8888,
+                sdl2::render::TextureAccess::Streaming,
+                SCREEN_WIDTH as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .unwrap();
+
         let mut screen_tex = canvas
             .create_texture(
                 PixelFormatEnum::RGBA8888,
@@ -42,12 +70,32 @@ impl JWindow {
 
         let bg_color = Color::RGBA(0x3B, 0x3B, 0x3A, 0xFF);
 
-        Self {
+        let mut win = Self {
             canvas,
+            picture_tex,
             screen_tex,
             screen_buf,
             bg_color,
+            fullscreen: false,
+            aspect_correction: config.aspect_correction,
+            integer_scaling: config.integer_scaling,
+            crop_overscan: config.crop_overscan,
             done: false,
+        };
+        win.set_fullscreen(config.fullscreen);
+        win
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+
+        let mode = match fullscreen {
+            true => FullscreenType::Desktop,
+            false => FullscreenType::Off,
+        };
+
+        if let Err(e) = self.canvas.window_mut().set_fullscreen(mode) {
+            eprintln!("Couldn't switch fullscreen mode: {}", e);
         }
     }
 
@@ -65,6 +113,20 @@ impl JWindow {
                 self.done = true;
                 true
             }
+            Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                self.set_fullscreen(!self.fullscreen);
+                true
+            }
+            Event::KeyDown { keycode: Some(Keycode::Return), keymod, repeat: false, .. }
+                if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) =>
+            {
+                self.set_fullscreen(!self.fullscreen);
+                true
+            }
+            Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                self.resize();
+                true
+            }
             _ => false,
         }
     }
@@ -81,6 +143,13 @@ impl JWindow {
     }
 
     pub fn draw(&mut self) {
+        let src = match self.crop_overscan {
+            true => Rect::new(0, OVERSCAN as i32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32 - 2 * OVERSCAN),
+            false => Rect::new(0, 0, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32),
+        };
+        let dst = self.picture_rect();
+        self.canvas.copy(&self.picture_tex, src, dst);
+
         self.screen_buf.blit_to_texture(&mut self.screen_tex);
 
         let q = self.screen_tex.query();
@@ -90,9 +159,88 @@ impl JWindow {
         self.canvas.present();
     }
 
+    /// Convert a frame of palette indices, as [`jadeite::Ppu::frame`]
+    /// gives them, to the picture shown by the next [`JWindow::draw`].
+    pub fn set_picture(&mut self, indices: &[u8], palette: &Palette) {
+        let rect = Rect::new(0, 0, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        let result = self.picture_tex.with_lock(rect, |buf, pitch| {
+            for (row, line) in indices.chunks(SCREEN_WIDTH).enumerate() {
+                let dst = &mut buf[row * pitch..row * pitch + SCREEN_WIDTH * 4];
+                for (pixel, &index) in dst.chunks_exact_mut(4).zip(line) {
+                    let c = palette[(index & 0x3F) as usize];
+                    pixel.copy_from_slice(&[0xFF, c.b, c.g, c.r]);
+                }
+            }
+        });
+
+        if let Err(e) = result {
+            eprintln!("Couldn't update picture: {}", e);
+        }
+    }
+
+    /// Overlay drawn over the picture, the size of the window.
     pub fn buffer(&mut self) -> &mut PixelBuffer {
         &mut self.screen_buf
     }
+
+    /// Where the picture goes: as large as fits the window, centered.
+    fn picture_rect(&self) -> Rect {
+        let (win_w, win_h) = self.canvas.output_size().unwrap_or((1, 1));
+        let (pic_w, pic_h) = picture_size(self.aspect_correction, self.crop_overscan);
+
+        let mut scale = f64::min(win_w as f64 / pic_w as f64, win_h as f64 / pic_h as f64);
+        if self.integer_scaling && scale >= 1.0 {
+            scale = scale.floor();
+        }
+
+        let w = (pic_w as f64 * scale).round() as u32;
+        let h = (pic_h as f64 * scale).round() as u32;
+        let x = (win_w as i32 - w as i32) / 2;
+        let y = (win_h as i32 - h as i32) / 2;
+
+        Rect::new(x, y, w.max(1), h.max(1))
+    }
+
+    /// Match the overlay to the new window size.
+    fn resize(&mut self) {
+        let (w, h) = match self.canvas.output_size() {
+            Ok(size) => size,
+            Err(_) => return,
+        };
+        if (w, h) == (self.screen_buf.width(), self.screen_buf.height()) || w == 0 || h == 0 {
+            return;
+        }
+
+        let tex = self.canvas.create_texture(
+            PixelFormatEnum::RGBA8888,
+            sdl2::render::TextureAccess::Streaming,
+            w,
+            h,
+        );
+
+        match tex {
+            Ok(mut tex) => {
+                tex.set_blend_mode(sdl2::render::BlendMode::Blend);
+                self.screen_tex = tex;
+                self.screen_buf = PixelBuffer::new(w, h);
+            },
+            Err(e) => eprintln!("Couldn't resize overlay: {}", e),
+        }
+    }
+}
+
+/// Picture size at 1x, with the 8:7 pixel aspect ratio applied.
+fn picture_size(aspect_correction: bool, crop_overscan: bool) -> (u32, u32) {
+    let w = SCREEN_WIDTH as u32;
+    let h = match crop_overscan {
+        true => SCREEN_HEIGHT as u32 - 2 * OVERSCAN,
+        false => SCREEN_HEIGHT as u32,
+    };
+
+    match aspect_correction {
+        true => (w * 8 / 7, h),
+        false => (w, h),
+    }
 }
 
 pub struct PixelBuffer {
@@ -0,0 +1,141 @@
+use std::f64::consts::PI;
+
+/// Fractional positions the band-limited kernel is precomputed for.
+const PHASES: usize = 32;
+const HALF_WIDTH: usize = 8;
+const WIDTH: usize = HALF_WIDTH * 2;
+/// Kernel cutoff as a fraction of the output Nyquist frequency.
+const CUTOFF: f64 = 0.9;
+
+/// Band-limited synthesis buffer.
+///
+/// Instead of producing a sample every clock and resampling afterwards,
+/// amplitude changes are recorded as deltas at their exact clock time. Each
+/// delta is spread over neighbouring output samples with a windowed sinc
+/// impulse and integrated back into a waveform when read. This avoids the
+/// aliasing a naive resampler would cause with the APU's hard-edged waves.
+pub(crate) struct BlipBuf {
+    kernel: Box<[[f32; WIDTH]; PHASES]>,
+    buf: Vec<f32>,
+    /// Output samples per clock.
+    factor: f64,
+    /// Position of the current frame's start, in output samples.
+    offset: f64,
+    integrator: f32,
+}
+
+impl BlipBuf {
+    /// `capacity` is the number of samples that can be buffered without
+    /// being read.
+    pub fn new(capacity: usize) -> Self {
+        let mut kernel = Box::new([[0f32; WIDTH]; PHASES]);
+
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let frac = phase as f64 / PHASES as f64;
+            let mut sum = 0.0;
+            let mut tmp = [0f64; WIDTH];
+
+            for (k, tap) in tmp.iter_mut().enumerate() {
+                let x = k as f64 - (HALF_WIDTH - 1) as f64 - frac;
+                *tap = sinc(x * CUTOFF) * blackman(x);
+                sum += *tap;
+            }
+
+            // Normalize so a delta always adds up to its full height.
+            for (dst, src) in taps.iter_mut().zip(tmp.iter()) {
+                *dst = (src / sum) as f32;
+            }
+        }
+
+        Self {
+            kernel,
+            buf: vec![0f32; capacity + WIDTH],
+            factor: 1.0,
+            offset: 0.0,
+            integrator: 0.0,
+        }
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = sample_rate / clock_rate;
+    }
+
+    /// Samples the buffer can hold before deltas start being dropped.
+    pub fn capacity(&self) -> usize {
+        self.buf.len() - WIDTH
+    }
+
+    /// Record an amplitude change `time` clocks after the current frame's
+    /// start.
+    pub fn add_delta(&mut self, time: u32, delta: f32) {
+        let pos = self.offset + time as f64 * self.factor;
+        let idx = pos as usize;
+        let phase = (((pos - idx as f64) * PHASES as f64) as usize).min(PHASES - 1);
+
+        if let Some(window) = self.buf.get_mut(idx..idx + WIDTH) {
+            for (dst, k) in window.iter_mut().zip(self.kernel[phase].iter()) {
+                *dst += delta * k;
+            }
+        }
+    }
+
+    /// Close the current frame, `clocks` clocks long. Its samples become
+    /// available for reading.
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += clocks as f64 * self.factor;
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.offset as usize
+    }
+
+    /// Read up to `out.len()` samples, returning how many were written.
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.samples_available());
+
+        for (dst, src) in out.iter_mut().zip(self.buf.iter()).take(count) {
+            self.integrator += *src;
+            *dst = self.integrator;
+        }
+
+        self.remove(count);
+        count
+    }
+
+    /// Discard the `count` oldest samples.
+    pub fn skip_samples(&mut self, count: usize) {
+        let count = count.min(self.samples_available());
+
+        for src in self.buf.iter().take(count) {
+            self.integrator += *src;
+        }
+
+        self.remove(count);
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.fill(0.0);
+        self.offset = self.offset.fract();
+        self.integrator = 0.0;
+    }
+
+    fn remove(&mut self, count: usize) {
+        let len = self.buf.len();
+        self.buf.copy_within(count.., 0);
+        self.buf[len - count..].fill(0.0);
+        self.offset -= count as f64;
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= HALF_WIDTH as f64 {
+        return 0.0;
+    }
+
+    let t = PI * x / HALF_WIDTH as f64;
+    0.42 + 0.5 * t.cos() + 0.08 * (2.0 * t).cos()
+}
@@ -0,0 +1,195 @@
+use crate::Bus;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles (NTSC).
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// Delta modulation channel, `$4010-$4013`. Plays 1-bit delta encoded samples
+/// fetched from CPU memory.
+#[derive(Debug)]
+pub(super) struct Dmc {
+    irq_enable: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+
+    pub irq_flag: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            irq_enable: false,
+            looping: false,
+            timer_period: RATE_TABLE[0],
+            timer: RATE_TABLE[0],
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+            irq_flag: false,
+        }
+    }
+
+    /// `reg` is the register offset within the channel, `0..=3`.
+    pub fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // IL-- RRRR
+            0 => {
+                self.irq_enable = (value & 0b1000_0000) != 0;
+                self.looping = (value & 0b0100_0000) != 0;
+                self.timer_period = RATE_TABLE[(value & 0x0f) as usize];
+                if !self.irq_enable {
+                    self.irq_flag = false;
+                }
+            },
+            // -DDD DDDD
+            1 => self.level = value & 0x7f,
+            // AAAA AAAA: $C000 + A * 64
+            2 => self.sample_address = 0xc000 | ((value as u16) << 6),
+            // LLLL LLLL: L * 16 + 1 bytes
+            3 => self.sample_length = ((value as u16) << 4) | 1,
+            _ => unreachable!()
+        }
+    }
+
+    /// Bit 4 of `$4015`.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Clocked every CPU cycle. Sample bytes are fetched through `bus`.
+    pub fn clock_timer(&mut self, bus: &Bus) {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            self.fetch(bus);
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if (self.shift & 0x01) != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(b) => {
+                    self.silence = false;
+                    self.shift = b;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn fetch(&mut self, bus: &Bus) {
+        self.sample_buffer = Some(bus.read(self.current_address));
+
+        self.current_address = match self.current_address {
+            0xffff => 0x8000,
+            a => a + 1,
+        };
+
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enable {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// Channel output in `0..=127`.
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+impl SaveState for Dmc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.irq_enable);
+        w.bool(self.looping);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u16(self.sample_address);
+        w.u16(self.sample_length);
+        w.u16(self.current_address);
+        w.u16(self.bytes_remaining);
+        w.bool(self.sample_buffer.is_some());
+        w.u8(self.sample_buffer.unwrap_or(0));
+        w.u8(self.shift);
+        w.u8(self.bits_remaining);
+        w.bool(self.silence);
+        w.u8(self.level);
+        w.bool(self.irq_flag);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enable = r.bool()?;
+        self.looping = r.bool()?;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.sample_address = r.u16()?;
+        self.sample_length = r.u16()?;
+        self.current_address = r.u16()?;
+        self.bytes_remaining = r.u16()?;
+        let buffered = r.bool()?;
+        let sample = r.u8()?;
+        self.sample_buffer = Some(sample).filter(|_| buffered);
+        self.shift = r.u8()?;
+        self.bits_remaining = r.u8()?;
+        self.silence = r.bool()?;
+        self.level = r.u8()?;
+        self.irq_flag = r.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use super::{ExpansionAudio, PULSE_STEP};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Full scale output is roughly 2.4 times a full volume APU pulse.
+const FULL_SCALE: f32 = PULSE_STEP * 15.0 * 2.4;
+
+/// Largest raw output: wave `63` at volume gain `32`.
+const MAX_LEVEL: f32 = 63.0 * 32.0;
+
+/// `$4089` master volume: 2/2, 2/3, 2/4 and 2/5.
+const MASTER_VOLUME: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+
+/// Modulation table entries are deltas for the mod counter, with 4 resetting
+/// it instead.
+const MOD_DELTA: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+/// Output is low-passed at around 2kHz by the RAM adapter. One-pole
+/// coefficient at the CPU clock.
+const LOWPASS: f32 = 0.007;
+
+/// Famicom Disk System sound: a single 64-step wavetable channel with
+/// volume envelope and a frequency modulator.
+/// See: <https://www.nesdev.org/wiki/FDS_audio>
+///
+/// Registers are at `$4040-$4092`.
+#[derive(Debug)]
+pub struct FdsAudio {
+    wave: [u8; 64],
+    /// `$4089` bit 7. Halts the wave and makes the table writable.
+    wave_write: bool,
+    master_volume: u8,
+
+    /// `$4082-$4083`.
+    pitch: u16,
+    /// `$4083` bit 7.
+    wave_halt: bool,
+    /// `$4083` bit 6.
+    envelopes_halt: bool,
+    wave_acc: u32,
+    wave_pos: u8,
+
+    volume: Envelope,
+    mod_env: Envelope,
+    /// `$408A`, scales every envelope's period.
+    envelope_speed: u8,
+
+    /// `$4086-$4087`.
+    mod_pitch: u16,
+    /// `$4087` bit 7. Halts the modulator and makes the table writable.
+    mod_halt: bool,
+    mod_acc: u32,
+    mod_table: [u8; 64],
+    mod_pos: u8,
+    /// 7-bit signed.
+    mod_counter: i8,
+
+    /// Output held while the wave is being written.
+    last_level: u16,
+    filtered: f32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        Self {
+            wave: [0; 64],
+            wave_write: false,
+            master_volume: 0,
+            pitch: 0,
+            wave_halt: true,
+            envelopes_halt: true,
+            wave_acc: 0,
+            wave_pos: 0,
+            volume: Envelope::default(),
+            mod_env: Envelope::default(),
+            envelope_speed: 0xe8,
+            mod_pitch: 0,
+            mod_halt: true,
+            mod_acc: 0,
+            mod_table: [0; 64],
+            mod_pos: 0,
+            mod_counter: 0,
+            last_level: 0,
+            filtered: 0.0,
+        }
+    }
+
+    fn clock_envelopes(&mut self) {
+        if self.envelopes_halt || self.wave_halt || self.envelope_speed == 0 {
+            return;
+        }
+
+        let speed = self.envelope_speed;
+        self.volume.clock(speed);
+        self.mod_env.clock(speed);
+    }
+
+    fn clock_modulator(&mut self) {
+        if self.mod_halt || self.mod_pitch == 0 {
+            return;
+        }
+
+        self.mod_acc += self.mod_pitch as u32;
+        if self.mod_acc < 0x10000 {
+            return;
+        }
+        self.mod_acc &= 0xffff;
+
+        let entry = self.mod_table[self.mod_pos as usize];
+        self.mod_pos = (self.mod_pos + 1) & 0x3f;
+
+        self.mod_counter = match entry {
+            4 => 0,
+            _ => wrap_7bit(self.mod_counter as i16 + MOD_DELTA[entry as usize] as i16),
+        };
+    }
+
+    /// Wave pitch after modulation, as described on the wiki.
+    fn modulated_pitch(&self) -> u32 {
+        let pitch = self.pitch as i32;
+        let counter = self.mod_counter as i32;
+
+        let mut temp = counter * self.mod_env.gain as i32;
+        let remainder = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (pitch + temp).max(0) as u32
+    }
+
+    fn clock_wave(&mut self) {
+        if self.wave_halt || self.wave_write {
+            return;
+        }
+
+        self.wave_acc += self.modulated_pitch();
+        if self.wave_acc >= 0x10000 {
+            self.wave_acc &= 0xffff;
+            self.wave_pos = (self.wave_pos + 1) & 0x3f;
+        }
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpansionAudio for FdsAudio {
+    fn clock(&mut self) {
+        self.clock_envelopes();
+        self.clock_modulator();
+        self.clock_wave();
+
+        if !self.wave_write {
+            let gain = self.volume.gain.min(32) as u16;
+            self.last_level = self.wave[self.wave_pos as usize] as u16 * gain;
+        }
+
+        let target = self.last_level as f32 * MASTER_VOLUME[self.master_volume as usize];
+        self.filtered += (target - self.filtered) * LOWPASS;
+    }
+
+    fn output(&self) -> f32 {
+        self.filtered / MAX_LEVEL * FULL_SCALE
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4040..=0x407f if self.wave_write => {
+                self.wave[(addr - 0x4040) as usize] = value & 0x3f;
+            },
+            0x4080 => self.volume.write(value),
+            // FFFF FFFF
+            0x4082 => self.pitch = (self.pitch & 0x0f00) | value as u16,
+            // MEDC FFFF
+            0x4083 => {
+                self.pitch = (self.pitch & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.wave_halt = (value & 0b1000_0000) != 0;
+                self.envelopes_halt = (value & 0b0100_0000) != 0;
+                if self.wave_halt {
+                    self.wave_acc = 0;
+                    self.wave_pos = 0;
+                }
+                if self.envelopes_halt {
+                    self.volume.reset_timer(self.envelope_speed);
+                    self.mod_env.reset_timer(self.envelope_speed);
+                }
+            },
+            0x4084 => self.mod_env.write(value),
+            // -BBB BBBB
+            0x4085 => self.mod_counter = wrap_7bit((value & 0x7f) as i16),
+            // FFFF FFFF
+            0x4086 => self.mod_pitch = (self.mod_pitch & 0x0f00) | value as u16,
+            // H--- FFFF
+            0x4087 => {
+                self.mod_pitch = (self.mod_pitch & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.mod_halt = (value & 0b1000_0000) != 0;
+                if self.mod_halt {
+                    self.mod_acc = 0;
+                }
+            },
+            // ---- -MMM, two consecutive entries per write.
+            0x4088 if self.mod_halt => {
+                let pos = self.mod_pos as usize;
+                self.mod_table[pos] = value & 0x07;
+                self.mod_table[(pos + 1) & 0x3f] = value & 0x07;
+                self.mod_pos = (self.mod_pos + 2) & 0x3f;
+            },
+            // W--- --VV
+            0x4089 => {
+                self.wave_write = (value & 0b1000_0000) != 0;
+                self.master_volume = value & 0x03;
+            },
+            0x408a => self.envelope_speed = value,
+            _ => {},
+        }
+    }
+
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407f => Some(match self.wave_write {
+                true => self.wave[(addr - 0x4040) as usize],
+                false => self.wave[self.wave_pos as usize],
+            } | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.mod_env.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for table in [&self.wave, &self.mod_table] {
+            w.bytes(table);
+        }
+        for flag in [self.wave_write, self.wave_halt, self.envelopes_halt, self.mod_halt] {
+            w.bool(flag);
+        }
+        w.u8(self.master_volume);
+        w.u16(self.pitch);
+        w.u32(self.wave_acc);
+        w.u8(self.wave_pos);
+        self.volume.save_state(w);
+        self.mod_env.save_state(w);
+        w.u8(self.envelope_speed);
+        w.u16(self.mod_pitch);
+        w.u32(self.mod_acc);
+        w.u8(self.mod_pos);
+        w.u8(self.mod_counter as u8);
+        w.u16(self.last_level);
+        w.f32(self.filtered);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes_into(&mut self.wave)?;
+        r.bytes_into(&mut self.mod_table)?;
+        for flag in [
+            &mut self.wave_write, &mut self.wave_halt, &mut self.envelopes_halt, &mut self.mod_halt,
+        ] {
+            *flag = r.bool()?;
+        }
+        self.master_volume = r.u8()?;
+        self.pitch = r.u16()?;
+        self.wave_acc = r.u32()?;
+        self.wave_pos = r.u8()?;
+        self.volume.load_state(r)?;
+        self.mod_env.load_state(r)?;
+        self.envelope_speed = r.u8()?;
+        self.mod_pitch = r.u16()?;
+        self.mod_acc = r.u32()?;
+        self.mod_pos = r.u8()?;
+        self.mod_counter = r.u8()? as i8;
+        self.last_level = r.u16()?;
+        self.filtered = r.f32()?;
+        Ok(())
+    }
+}
+
+/// Volume and modulation envelopes, `$4080` and `$4084`.
+#[derive(Debug, Default)]
+struct Envelope {
+    /// Bit 7. Gain is set directly when disabled.
+    disabled: bool,
+    /// Bit 6.
+    increase: bool,
+    /// Bits 0-5.
+    speed: u8,
+    /// `0..=63`, only `0..=32` is audible.
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    // MDSS SSSS
+    fn write(&mut self, value: u8) {
+        self.disabled = (value & 0b1000_0000) != 0;
+        self.increase = (value & 0b0100_0000) != 0;
+        self.speed = value & 0x3f;
+
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn period(&self, master: u8) -> u32 {
+        8 * (self.speed as u32 + 1) * master as u32
+    }
+
+    fn reset_timer(&mut self, master: u8) {
+        self.timer = self.period(master);
+    }
+
+    fn clock(&mut self, master: u8) {
+        if self.disabled {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period(master);
+
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.disabled);
+        w.bool(self.increase);
+        w.u8(self.speed);
+        w.u8(self.gain);
+        w.u32(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.disabled = r.bool()?;
+        self.increase = r.bool()?;
+        self.speed = r.u8()?;
+        self.gain = r.u8()?;
+        self.timer = r.u32()?;
+        Ok(())
+    }
+}
+
+fn wrap_7bit(value: i16) -> i8 {
+    // Sign extend from bit 6.
+    (((value & 0x7f) << 9) >> 9) as i8
+}
@@ -0,0 +1,90 @@
+use super::{ExpansionAudio, PULSE_STEP};
+use crate::apu::pulse::Pulse;
+
+/// The MMC5 runs its envelopes and length counters off its own 240Hz timer
+/// instead of the APU frame counter.
+const FRAME_PERIOD: u16 = 7457;
+
+/// PCM output scale. Full scale is about as loud as the DMC at full scale.
+const PCM_STEP: f32 = PULSE_STEP * 0.11;
+
+/// MMC5 sound: two APU-style pulse channels without sweep and a raw 8-bit
+/// PCM channel. See: <https://www.nesdev.org/wiki/MMC5_audio>
+///
+/// Registers are at `$5000-$5015`. Only the PCM write mode is emulated; read
+/// mode and its IRQ aren't used by anything known.
+#[derive(Debug)]
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    frame_timer: u16,
+    /// Pulse timers run at half the CPU clock, same as the APU's.
+    odd_cycle: bool,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm: 0,
+            frame_timer: FRAME_PERIOD,
+            odd_cycle: false,
+        }
+    }
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpansionAudio for Mmc5Audio {
+    fn clock(&mut self) {
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.frame_timer -= 1;
+        if self.frame_timer == 0 {
+            self.frame_timer = FRAME_PERIOD;
+            for pulse in [&mut self.pulse1, &mut self.pulse2] {
+                pulse.clock_quarter();
+                pulse.clock_half();
+            }
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let pulses = (self.pulse1.output() + self.pulse2.output()) as f32 * PULSE_STEP;
+        pulses + self.pcm as f32 * PCM_STEP
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulse1.write(addr - 0x5000, value),
+            0x5004..=0x5007 => self.pulse2.write(addr - 0x5004, value),
+            // Writes of zero are ignored.
+            0x5011 if value != 0 => self.pcm = value,
+            // ---- --21
+            0x5015 => {
+                self.pulse1.length.set_enabled((value & 0b0000_0001) != 0);
+                self.pulse2.length.set_enabled((value & 0b0000_0010) != 0);
+            },
+            _ => {},
+        }
+    }
+
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5015 => Some(
+                (self.pulse1.length.active() as u8) | ((self.pulse2.length.active() as u8) << 1)
+            ),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+mod fds;
+mod mmc5;
+mod n163;
+mod sunsoft5b;
+mod vrc6;
+mod vrc7;
+
+pub use self::fds::FdsAudio;
+pub use self::mmc5::Mmc5Audio;
+pub use self::n163::N163Audio;
+pub use self::sunsoft5b::Sunsoft5bAudio;
+pub use self::vrc6::Vrc6Audio;
+pub use self::vrc7::Vrc7Audio;
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// One APU pulse volume step in the mix, using the linear approximation from
+/// <https://www.nesdev.org/wiki/APU_Mixer>. Expansion chips scale their
+/// output by this so they sit at the right level next to the APU.
+pub(super) const PULSE_STEP: f32 = 0.00752;
+
+/// Sound chip on the cartridge, mixed into the APU output.
+///
+/// Chips are handed the CPU address of every register write on the
+/// cartridge bus and ignore the ones they don't decode. Mappers with
+/// different register mirroring should pass the canonical address.
+pub trait ExpansionAudio: Send {
+    /// Advance one CPU clock.
+    fn clock(&mut self);
+
+    /// Current output, on the same scale as the APU mix.
+    fn output(&self) -> f32;
+
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Readable registers, `None` if `addr` isn't one of them.
+    fn read(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Channel state for save states. Only the FDS keeps it so far, chips
+    /// left at these defaults play on from wherever they are on loading.
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+/// Several chips mixed together. NSF tunes can use any combination.
+#[derive(Default)]
+pub struct ExpansionMix {
+    chips: Vec<Box<dyn ExpansionAudio>>,
+}
+
+impl ExpansionMix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chip: Box<dyn ExpansionAudio>) {
+        self.chips.push(chip);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chips.is_empty()
+    }
+}
+
+impl ExpansionAudio for ExpansionMix {
+    fn clock(&mut self) {
+        for chip in self.chips.iter_mut() {
+            chip.clock();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        self.chips.iter().map(|c| c.output()).sum()
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        for chip in self.chips.iter_mut() {
+            chip.write(addr, value);
+        }
+    }
+
+    fn read(&self, addr: u16) -> Option<u8> {
+        self.chips.iter().find_map(|c| c.read(addr))
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for chip in self.chips.iter() {
+            chip.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for chip in self.chips.iter_mut() {
+            chip.load_state(r)?;
+        }
+        Ok(())
+    }
+}
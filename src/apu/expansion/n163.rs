@@ -0,0 +1,137 @@
+use std::cell::Cell;
+
+use super::{ExpansionAudio, PULSE_STEP};
+
+/// CPU cycles per channel update.
+const UPDATE_PERIOD: u8 = 15;
+
+/// A single channel at full volume is a bit louder than a full volume APU
+/// pulse.
+const STEP: f32 = PULSE_STEP * 2.0 / 15.0;
+
+/// Namco 163 sound: up to 8 wavetable channels reading 4-bit samples from
+/// 128 bytes of internal RAM. See: <https://www.nesdev.org/wiki/Namco_163_audio>
+///
+/// RAM is accessed through the address port at `$F800` and the data port at
+/// `$4800`. Channel registers live in the top of RAM, 8 bytes per channel
+/// from `$78` down.
+///
+/// The hardware updates one channel at a time and outputs it on its own,
+/// which whines audibly with many channels enabled. The channels are
+/// averaged here instead, as most players do.
+#[derive(Debug)]
+pub struct N163Audio {
+    ram: [u8; 128],
+    /// `$F800`: `IAAA AAAA`, auto-increment and address. Reads through
+    /// the data port increment it too.
+    addr: Cell<u8>,
+    timer: u8,
+    /// Channel to update next, `0..8`.
+    channel: u8,
+    outputs: [i16; 8],
+}
+
+impl N163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 128],
+            addr: Cell::new(0),
+            timer: UPDATE_PERIOD,
+            channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    /// `$7F` bits 4-6, plus one.
+    fn active_channels(&self) -> u8 {
+        ((self.ram[0x7f] >> 4) & 0x07) + 1
+    }
+
+    fn advance_addr(&self) {
+        let addr = self.addr.get();
+        if (addr & 0x80) != 0 {
+            self.addr.set(0x80 | (addr.wrapping_add(1) & 0x7f));
+        }
+    }
+
+    fn sample(&self, index: u32) -> u8 {
+        let byte = self.ram[((index >> 1) & 0x7f) as usize];
+        match index & 1 {
+            0 => byte & 0x0f,
+            _ => byte >> 4,
+        }
+    }
+
+    /// Step `channel`'s phase and latch its output.
+    fn update(&mut self, channel: u8) {
+        let base = 0x40 + 8 * channel as usize;
+        let regs = &self.ram[base..base + 8];
+
+        let freq = regs[0] as u32 | (regs[2] as u32) << 8 | ((regs[4] & 0x03) as u32) << 16;
+        let length = 256 - (regs[4] & 0xfc) as u32;
+        let offset = regs[6] as u32;
+        let volume = (regs[7] & 0x0f) as i16;
+        let mut phase = regs[1] as u32 | (regs[3] as u32) << 8 | (regs[5] as u32) << 16;
+
+        phase = (phase + freq) % (length << 16);
+
+        let sample = self.sample((phase >> 16) + offset) as i16;
+        self.outputs[channel as usize] = (sample - 8) * volume;
+
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+    }
+}
+
+impl Default for N163Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpansionAudio for N163Audio {
+    fn clock(&mut self) {
+        self.timer -= 1;
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = UPDATE_PERIOD;
+
+        self.update(self.channel);
+
+        let lowest = 8 - self.active_channels();
+        self.channel = match self.channel <= lowest {
+            true => 7,
+            false => self.channel - 1,
+        };
+    }
+
+    fn output(&self) -> f32 {
+        let active = self.active_channels();
+        let sum: i16 = self.outputs[(8 - active) as usize..].iter().sum();
+        sum as f32 / active as f32 * STEP
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800 => {
+                self.ram[(self.addr.get() & 0x7f) as usize] = value;
+                self.advance_addr();
+            },
+            0xf800 => self.addr.set(value),
+            _ => {},
+        }
+    }
+
+    fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800 => {
+                let value = self.ram[(self.addr.get() & 0x7f) as usize];
+                self.advance_addr();
+                Some(value)
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use super::{ExpansionAudio, PULSE_STEP};
+
+/// Tone, noise and envelope generators tick every 16 CPU cycles.
+const PRESCALER: u8 = 16;
+
+/// A channel at full volume is somewhat louder than a full volume APU pulse.
+const FULL_SCALE: f32 = PULSE_STEP * 20.0;
+
+/// Sunsoft 5B sound, a YM2149F (AY-3-8910) variant: three square wave tone
+/// channels, a noise generator and a shared envelope generator.
+/// See: <https://www.nesdev.org/wiki/Sunsoft_5B_audio>
+///
+/// Registers are selected by writing their number to `$C000` and written
+/// through `$E000`.
+#[derive(Debug)]
+pub struct Sunsoft5bAudio {
+    select: u8,
+    regs: [u8; 16],
+    /// Volume levels, 1.5dB apart. Indexed by 5-bit envelope level; fixed
+    /// volumes use every other entry.
+    levels: [f32; 32],
+    prescaler: u8,
+
+    tone_timers: [u16; 3],
+    tone_high: [bool; 3],
+
+    noise_timer: u8,
+    /// 17-bit LFSR.
+    noise_shift: u32,
+
+    envelope_timer: u16,
+    /// `0..=31`, counting up or down depending on the shape.
+    envelope_step: u8,
+    envelope_rising: bool,
+    envelope_holding: bool,
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        let mut levels = [0f32; 32];
+        for (i, v) in levels.iter_mut().enumerate().skip(1) {
+            *v = 10f32.powf((i as f32 - 31.0) * 1.5 / 20.0);
+        }
+
+        Self {
+            select: 0,
+            regs: [0; 16],
+            levels,
+            prescaler: PRESCALER,
+            tone_timers: [0; 3],
+            tone_high: [false; 3],
+            noise_timer: 0,
+            noise_shift: 1,
+            envelope_timer: 0,
+            envelope_step: 0,
+            envelope_rising: false,
+            envelope_holding: true,
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let lo = self.regs[2 * channel] as u16;
+        let hi = (self.regs[2 * channel + 1] & 0x0f) as u16;
+        (hi << 8 | lo).max(1)
+    }
+
+    fn envelope_period(&self) -> u16 {
+        (self.regs[12] as u16) << 8 | self.regs[11] as u16
+    }
+
+    fn envelope_level(&self) -> u8 {
+        match self.envelope_rising {
+            true => self.envelope_step,
+            false => 31 - self.envelope_step,
+        }
+    }
+
+    /// `$0D`: `CAtH`, continue, attack, alternate, hold.
+    fn restart_envelope(&mut self) {
+        self.envelope_timer = 0;
+        self.envelope_step = 0;
+        self.envelope_rising = (self.regs[13] & 0b0100) != 0;
+        self.envelope_holding = false;
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        let shape = self.regs[13];
+        let cont = (shape & 0b1000) != 0;
+        let alternate = (shape & 0b0010) != 0;
+        let hold = (shape & 0b0001) != 0;
+
+        if !cont {
+            // Settle at silence.
+            self.envelope_rising = false;
+            self.envelope_holding = true;
+        } else if hold {
+            // Stay at the last level, or its opposite when alternating.
+            if alternate {
+                self.envelope_rising = !self.envelope_rising;
+            }
+            self.envelope_holding = true;
+        } else {
+            if alternate {
+                self.envelope_rising = !self.envelope_rising;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    fn tick(&mut self) {
+        for ch in 0..3 {
+            self.tone_timers[ch] += 1;
+            if self.tone_timers[ch] >= self.tone_period(ch) {
+                self.tone_timers[ch] = 0;
+                self.tone_high[ch] = !self.tone_high[ch];
+            }
+        }
+
+        self.noise_timer += 1;
+        if self.noise_timer >= (self.regs[6] & 0x1f).max(1) {
+            self.noise_timer = 0;
+            let bit = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (bit << 16);
+        }
+
+        self.envelope_timer += 1;
+        if self.envelope_timer >= self.envelope_period().max(1) {
+            self.envelope_timer = 0;
+            self.clock_envelope();
+        }
+    }
+}
+
+impl Default for Sunsoft5bAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpansionAudio for Sunsoft5bAudio {
+    fn clock(&mut self) {
+        self.prescaler -= 1;
+        if self.prescaler == 0 {
+            self.prescaler = PRESCALER;
+            self.tick();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        // $07: --CB Acba, noise and tone disable bits per channel.
+        let mixer = self.regs[7];
+        let noise_high = (self.noise_shift & 1) != 0;
+
+        let mut sum = 0.0;
+        for ch in 0..3 {
+            let tone_off = (mixer & (1 << ch)) != 0;
+            let noise_off = (mixer & (8 << ch)) != 0;
+
+            if (self.tone_high[ch] || tone_off) && (noise_high || noise_off) {
+                // $08-$0A: ---E VVVV
+                let volume = self.regs[8 + ch];
+                let level = match volume & 0x10 {
+                    0 if volume & 0x0f == 0 => 0,
+                    0 => 2 * (volume & 0x0f) + 1,
+                    _ => self.envelope_level(),
+                };
+                sum += self.levels[level as usize];
+            }
+        }
+
+        sum * FULL_SCALE
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xc000 => self.select = value & 0x0f,
+            0xe000 => {
+                self.regs[self.select as usize] = value;
+                if self.select == 13 {
+                    self.restart_envelope();
+                }
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use super::{ExpansionAudio, PULSE_STEP};
+
+/// Konami VRC6 sound: two pulse channels with 8 duty settings and a
+/// sawtooth. See: <https://www.nesdev.org/wiki/VRC6_audio>
+///
+/// Registers are at `$9000-$9003`, `$A000-$A002` and `$B000-$B002`. Boards
+/// with swapped address lines (mapper 26) have to swap them back first.
+#[derive(Debug, Default)]
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    saw: Vrc6Saw,
+    /// `$9003` bit 0.
+    halt: bool,
+    /// Right shift applied to all periods by `$9003` bits 1-2.
+    period_shift: u8,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ExpansionAudio for Vrc6Audio {
+    fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+
+        self.pulse1.clock(self.period_shift);
+        self.pulse2.clock(self.period_shift);
+        self.saw.clock(self.period_shift);
+    }
+
+    fn output(&self) -> f32 {
+        let level = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+        level as f32 * PULSE_STEP
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x9000..=0x9002 => self.pulse1.write(addr - 0x9000, value),
+            // ---- -ABH
+            0x9003 => {
+                self.halt = (value & 0b0000_0001) != 0;
+                self.period_shift = match value & 0b0000_0110 {
+                    0 => 0,
+                    0b0000_0010 => 4,
+                    _ => 8,
+                };
+            },
+            0xa000..=0xa002 => self.pulse2.write(addr - 0xa000, value),
+            0xb000..=0xb002 => self.saw.write(addr - 0xb000, value),
+            _ => {},
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Vrc6Pulse {
+    /// Ignore duty and output the volume constantly.
+    mode: bool,
+    duty: u8,
+    volume: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    /// Counts down from 15. The output is high while it's `<= duty`.
+    step: u8,
+}
+
+impl Default for Vrc6Pulse {
+    fn default() -> Self {
+        Self { mode: false, duty: 0, volume: 0, enabled: false, period: 0, timer: 0, step: 15 }
+    }
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // MDDD VVVV
+            0 => {
+                self.mode = (value & 0b1000_0000) != 0;
+                self.duty = (value >> 4) & 0x07;
+                self.volume = value & 0x0f;
+            },
+            // LLLL LLLL
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            // E--- HHHH
+            2 => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = (value & 0b1000_0000) != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0f;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.mode || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Vrc6Saw {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    /// `0..14`. The rate is added on every even step, and the accumulator
+    /// starts over after the seventh addition.
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // --AA AAAA
+            0 => self.rate = value & 0x3f,
+            // LLLL LLLL
+            1 => self.period = (self.period & 0x0f00) | value as u16,
+            // E--- HHHH
+            2 => {
+                self.period = (self.period & 0x00ff) | ((value as u16 & 0x0f) << 8);
+                self.enabled = (value & 0b1000_0000) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period >> shift;
+        self.step += 1;
+
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// `0..=31`.
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
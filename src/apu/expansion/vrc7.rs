@@ -0,0 +1,418 @@
+use std::f32::consts::TAU;
+
+use super::{ExpansionAudio, PULSE_STEP};
+
+/// The OPLL core produces a sample every 72 cycles of its 3.58MHz clock,
+/// i.e. every 36 CPU cycles.
+const SAMPLE_PERIOD: u8 = 36;
+const SAMPLE_RATE: f32 = 49_716.0;
+
+/// Peak level of a single channel. The FM output swings both ways, so this
+/// puts its full range close to a full volume APU pulse.
+const FULL_SCALE: f32 = PULSE_STEP * 8.0;
+
+/// Envelope attenuation range in dB. Anything below is silent.
+const MAX_ATTENUATION: f32 = 48.0;
+
+const MULTIPLIER: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+/// Key scale level attenuation in dB for block 7, by the top 4 bits of the
+/// F-number. Each block below drops it by 6dB.
+const KSL_TABLE: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25,
+    36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25, 42.0,
+];
+
+/// Fraction of `KSL_TABLE` applied for KSL 0-3: 0, 1.5, 3 and 6dB/octave.
+const KSL_SCALE: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+/// Tremolo: 4.8dB deep at 3.7Hz.
+const AM_DEPTH: f32 = 4.8;
+const AM_RATE: f32 = 3.7;
+/// Vibrato: 14 cents deep at 6.4Hz.
+const PM_DEPTH: f32 = 14.0 / 1200.0;
+const PM_RATE: f32 = 6.4;
+
+/// Built-in instruments 1-15, dumped from a VRC7 die.
+/// See: <https://www.nesdev.org/wiki/VRC7_audio>
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12],
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4],
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02],
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6],
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06],
+];
+
+/// Konami VRC7 sound: six 2-operator FM channels, a cut down YM2413 (OPLL).
+/// See: <https://www.nesdev.org/wiki/VRC7_audio>
+///
+/// Registers are selected through `$9010` and written through `$9030`.
+///
+/// This is a floating point approximation of the chip rather than a bit
+/// exact model of its log-sin and exponent tables, but envelopes, key
+/// scaling, feedback and the LFOs all behave as documented.
+#[derive(Debug)]
+pub struct Vrc7Audio {
+    select: u8,
+    /// Instrument 0, set through `$00-$07`.
+    custom: [u8; 8],
+    channels: [Channel; 6],
+    timer: u8,
+    am_phase: f32,
+    pm_phase: f32,
+    output: f32,
+}
+
+impl Vrc7Audio {
+    pub fn new() -> Self {
+        Self {
+            select: 0,
+            custom: [0; 8],
+            channels: Default::default(),
+            timer: SAMPLE_PERIOD,
+            am_phase: 0.0,
+            pm_phase: 0.0,
+            output: 0.0,
+        }
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u8) {
+        let ch = (reg & 0x0f) as usize;
+
+        match reg {
+            0x00..=0x07 => self.custom[reg as usize] = value,
+            // FFFF FFFF
+            0x10..=0x15 => {
+                let channel = &mut self.channels[ch];
+                channel.fnum = (channel.fnum & 0x100) | value as u16;
+            },
+            // --SK BBBF
+            0x20..=0x25 => {
+                let channel = &mut self.channels[ch];
+                channel.fnum = (channel.fnum & 0x0ff) | ((value as u16 & 0x01) << 8);
+                channel.block = (value >> 1) & 0x07;
+                channel.sustain = (value & 0b0010_0000) != 0;
+                channel.set_key((value & 0b0001_0000) != 0);
+            },
+            // IIII VVVV
+            0x30..=0x35 => {
+                let channel = &mut self.channels[ch];
+                channel.instrument = value >> 4;
+                channel.volume = value & 0x0f;
+            },
+            _ => {},
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        self.am_phase = (self.am_phase + AM_RATE / SAMPLE_RATE).fract();
+        self.pm_phase = (self.pm_phase + PM_RATE / SAMPLE_RATE).fract();
+
+        let lfo = Lfo {
+            am: AM_DEPTH * (1.0 - (TAU * self.am_phase).cos()) / 2.0,
+            pm: 2f32.powf((TAU * self.pm_phase).sin() * PM_DEPTH),
+        };
+
+        let custom = Patch::new(&self.custom);
+        let mut sum = 0.0;
+
+        for channel in self.channels.iter_mut() {
+            let patch = match channel.instrument {
+                0 => custom,
+                i => Patch::new(&PATCHES[i as usize - 1]),
+            };
+            sum += channel.sample(&patch, &lfo);
+        }
+
+        sum
+    }
+}
+
+impl Default for Vrc7Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpansionAudio for Vrc7Audio {
+    fn clock(&mut self) {
+        self.timer -= 1;
+        if self.timer == 0 {
+            self.timer = SAMPLE_PERIOD;
+            self.output = self.sample();
+        }
+    }
+
+    fn output(&self) -> f32 {
+        self.output * FULL_SCALE
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x9010 => self.select = value,
+            0x9030 => self.write_reg(self.select, value),
+            _ => {},
+        }
+    }
+}
+
+struct Lfo {
+    /// Tremolo attenuation in dB.
+    am: f32,
+    /// Vibrato frequency ratio.
+    pm: f32,
+}
+
+/// Operator settings from an instrument.
+#[derive(Debug, Clone, Copy, Default)]
+struct OperatorPatch {
+    am: bool,
+    vib: bool,
+    /// Hold at the sustain level while keyed on, instead of decaying
+    /// through it.
+    sustained: bool,
+    ksr: bool,
+    mult: u8,
+    ksl: u8,
+    /// Rectify the sine, silencing its negative half.
+    half_wave: bool,
+    ar: u8,
+    dr: u8,
+    sl: u8,
+    rr: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Patch {
+    /// Modulator, carrier.
+    ops: [OperatorPatch; 2],
+    /// Modulator total level, 0.75dB steps.
+    tl: u8,
+    feedback: u8,
+}
+
+impl Patch {
+    fn new(b: &[u8; 8]) -> Self {
+        let mut ops = [OperatorPatch::default(); 2];
+
+        for (i, op) in ops.iter_mut().enumerate() {
+            // AVEK MMMM
+            op.am = (b[i] & 0b1000_0000) != 0;
+            op.vib = (b[i] & 0b0100_0000) != 0;
+            op.sustained = (b[i] & 0b0010_0000) != 0;
+            op.ksr = (b[i] & 0b0001_0000) != 0;
+            op.mult = b[i] & 0x0f;
+            // KK-- ----
+            op.ksl = b[2 + i] >> 6;
+            // AAAA DDDD
+            op.ar = b[4 + i] >> 4;
+            op.dr = b[4 + i] & 0x0f;
+            // SSSS RRRR
+            op.sl = b[6 + i] >> 4;
+            op.rr = b[6 + i] & 0x0f;
+        }
+
+        // KK-Q qFFF: carrier KSL, carrier and modulator rectification,
+        // modulator feedback.
+        ops[1].half_wave = (b[3] & 0b0001_0000) != 0;
+        ops[0].half_wave = (b[3] & 0b0000_1000) != 0;
+
+        Self {
+            ops,
+            tl: b[2] & 0x3f,
+            feedback: b[3] & 0x07,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    /// Release slowly on key off.
+    sustain: bool,
+    key: bool,
+    instrument: u8,
+    /// Carrier attenuation, 3dB steps.
+    volume: u8,
+    /// Modulator, carrier.
+    slots: [Slot; 2],
+    /// Last two modulator outputs.
+    feedback: [f32; 2],
+}
+
+impl Channel {
+    fn set_key(&mut self, key: bool) {
+        if key && !self.key {
+            for slot in self.slots.iter_mut() {
+                slot.key_on();
+            }
+        } else if !key && self.key {
+            for slot in self.slots.iter_mut() {
+                slot.key_off();
+            }
+        }
+        self.key = key;
+    }
+
+    fn sample(&mut self, patch: &Patch, lfo: &Lfo) -> f32 {
+        // Cycles per sample at multiplier 1.
+        let base = self.fnum as f32 * (1u32 << self.block) as f32 / 524_288.0;
+        let ksr_base = (self.block << 1) | (self.fnum >> 8) as u8;
+        let ksl_base = (KSL_TABLE[(self.fnum >> 5) as usize & 0x0f] - 6.0 * (7 - self.block) as f32).max(0.0);
+
+        for (slot, op) in self.slots.iter_mut().zip(patch.ops.iter()) {
+            let release = match (self.sustain, op.sustained) {
+                (true, _) => 5,
+                (false, true) => op.rr,
+                (false, false) => 7,
+            };
+            slot.clock_envelope(op, ksr_base, release);
+
+            let pm = if op.vib { lfo.pm } else { 1.0 };
+            slot.phase = (slot.phase + base * MULTIPLIER[op.mult as usize] * pm).fract();
+        }
+
+        let [modulator, carrier] = &patch.ops;
+
+        let feedback = match patch.feedback {
+            0 => 0.0,
+            fb => (self.feedback[0] + self.feedback[1]) / 2.0 * 2f32.powi(fb as i32 - 6),
+        };
+
+        let mod_attenuation = patch.tl as f32 * 0.75 + ksl_base * KSL_SCALE[modulator.ksl as usize];
+        let mod_out = self.slots[0].output(modulator, feedback, mod_attenuation, lfo);
+        self.feedback = [mod_out, self.feedback[0]];
+
+        let car_attenuation = self.volume as f32 * 3.0 + ksl_base * KSL_SCALE[carrier.ksl as usize];
+        self.slots[1].output(carrier, 2.0 * mod_out, car_attenuation, lfo)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage { Attack, Decay, Sustain, Release, Off }
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// In cycles, `0.0..1.0`.
+    phase: f32,
+    /// Envelope attenuation in dB.
+    env: f32,
+    stage: Stage,
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self { phase: 0.0, env: MAX_ATTENUATION, stage: Stage::Off }
+    }
+}
+
+impl Slot {
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.stage = Stage::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.stage != Stage::Off {
+            self.stage = Stage::Release;
+        }
+    }
+
+    fn clock_envelope(&mut self, op: &OperatorPatch, ksr_base: u8, release: u8) {
+        let ksr = if op.ksr { ksr_base } else { ksr_base >> 2 };
+
+        match self.stage {
+            Stage::Attack => {
+                // Exponential approach towards 0dB.
+                let k = attack_coefficient(effective_rate(op.ar, ksr));
+                self.env -= self.env * k;
+                if self.env < 0.1 {
+                    self.env = 0.0;
+                    self.stage = Stage::Decay;
+                }
+            },
+            Stage::Decay => {
+                let sustain_level = op.sl as f32 * 3.0;
+                self.env += decay_step(effective_rate(op.dr, ksr));
+                if self.env >= sustain_level {
+                    self.env = sustain_level;
+                    self.stage = Stage::Sustain;
+                }
+            },
+            Stage::Sustain if !op.sustained => {
+                self.env += decay_step(effective_rate(op.rr, ksr));
+            },
+            Stage::Release => self.env += decay_step(effective_rate(release, ksr)),
+            _ => {},
+        }
+
+        if self.env >= MAX_ATTENUATION {
+            self.env = MAX_ATTENUATION;
+            if self.stage != Stage::Attack {
+                self.stage = Stage::Off;
+            }
+        }
+    }
+
+    /// Output in `-1.0..=1.0`, with the phase offset by `modulation` cycles.
+    fn output(&self, op: &OperatorPatch, modulation: f32, attenuation: f32, lfo: &Lfo) -> f32 {
+        if self.stage == Stage::Off {
+            return 0.0;
+        }
+
+        let am = if op.am { lfo.am } else { 0.0 };
+        let level = 10f32.powf(-(self.env + attenuation + am) / 20.0);
+
+        let s = (TAU * (self.phase + modulation)).sin();
+        match op.half_wave && s < 0.0 {
+            true => 0.0,
+            false => s * level,
+        }
+    }
+}
+
+/// `rate * 4` plus key scaling, `0..=63`. Rate 0 never moves.
+fn effective_rate(rate: u8, ksr: u8) -> u8 {
+    match rate {
+        0 => 0,
+        _ => (rate * 4 + ksr).min(63),
+    }
+}
+
+/// Per-sample fraction of the remaining attenuation removed while
+/// attacking. Rate 4 attacks in about 2.8s, halving every 4 rates.
+fn attack_coefficient(rate: u8) -> f32 {
+    match rate {
+        0 => 0.0,
+        60..=63 => 1.0,
+        _ => {
+            let seconds = 2.826 * 2f32.powf(-(rate as f32 - 4.0) / 4.0);
+            1.0 - (0.1 / MAX_ATTENUATION).powf(1.0 / (seconds * SAMPLE_RATE))
+        },
+    }
+}
+
+/// Per-sample attenuation increase while decaying or releasing. Rate 4 goes
+/// through the full range in about 19.6s, halving every 4 rates.
+fn decay_step(rate: u8) -> f32 {
+    match rate {
+        0 => 0.0,
+        _ => {
+            let seconds = 19.64 * 2f32.powf(-(rate as f32 - 4.0) / 4.0);
+            MAX_ATTENUATION / (seconds * SAMPLE_RATE)
+        },
+    }
+}
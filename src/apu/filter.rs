@@ -0,0 +1,58 @@
+use std::f32::consts::PI;
+
+/// First order filter, as found between the NES's DAC and its output jack.
+/// See: <https://www.nesdev.org/wiki/APU_Mixer#Emulation>
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Filter {
+    HighPass { alpha: f32, prev_in: f32, prev_out: f32 },
+    LowPass { alpha: f32, prev_out: f32 },
+}
+
+impl Filter {
+    pub fn high_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Filter::HighPass { alpha: rc / (rc + dt), prev_in: 0.0, prev_out: 0.0 }
+    }
+
+    pub fn low_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Filter::LowPass { alpha: dt / (rc + dt), prev_out: 0.0 }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        match self {
+            Filter::HighPass { alpha, prev_in, prev_out } => {
+                let y = *alpha * (*prev_out + x - *prev_in);
+                *prev_in = x;
+                *prev_out = y;
+                y
+            },
+            Filter::LowPass { alpha, prev_out } => {
+                let y = *prev_out + *alpha * (x - *prev_out);
+                *prev_out = y;
+                y
+            },
+        }
+    }
+}
+
+/// The NES's output filter chain: two high-pass filters at 90Hz and 440Hz
+/// followed by a 14kHz low-pass.
+#[derive(Debug, Clone)]
+pub(crate) struct FilterChain([Filter; 3]);
+
+impl FilterChain {
+    pub fn nes(sample_rate: f32) -> Self {
+        Self([
+            Filter::high_pass(sample_rate, 90.0),
+            Filter::high_pass(sample_rate, 440.0),
+            Filter::low_pass(sample_rate, 14000.0),
+        ])
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        self.0.iter_mut().fold(x, |acc, f| f.process(acc))
+    }
+}
@@ -0,0 +1,34 @@
+/// Nonlinear NES mixer, approximated with the two lookup tables described at
+/// <https://www.nesdev.org/wiki/APU_Mixer>.
+///
+/// Output lies in `0.0..1.0`.
+pub(super) struct Mixer {
+    /// Indexed by `pulse1 + pulse2`.
+    pulse_table: [f32; 31],
+    /// Indexed by `3 * triangle + 2 * noise + dmc`.
+    tnd_table: [f32; 203],
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        let mut pulse_table = [0f32; 31];
+        for (n, v) in pulse_table.iter_mut().enumerate().skip(1) {
+            *v = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+
+        let mut tnd_table = [0f32; 203];
+        for (n, v) in tnd_table.iter_mut().enumerate().skip(1) {
+            *v = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        Self { pulse_table, tnd_table }
+    }
+
+    pub fn pulse(&self, pulse1: u8, pulse2: u8) -> f32 {
+        self.pulse_table[(pulse1 + pulse2) as usize]
+    }
+
+    pub fn tnd(&self, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        self.tnd_table[3 * triangle as usize + 2 * noise as usize + dmc as usize]
+    }
+}
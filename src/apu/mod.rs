@@ -0,0 +1,432 @@
+mod blip;
+mod dmc;
+mod expansion;
+mod filter;
+mod mixer;
+mod noise;
+mod output;
+mod pulse;
+mod recorder;
+mod triangle;
+mod units;
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Bus, WavFormat};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+use self::dmc::Dmc;
+use self::mixer::Mixer;
+use self::noise::Noise;
+use self::output::AudioOutput;
+use self::pulse::Pulse;
+use self::recorder::{RecordSource, Recorder};
+use self::triangle::Triangle;
+
+pub use self::expansion::{
+    ExpansionAudio, ExpansionMix,
+    FdsAudio, Mmc5Audio, N163Audio, Sunsoft5bAudio, Vrc6Audio, Vrc7Audio,
+};
+
+/// NTSC CPU clock rate in Hz.
+pub const CPU_CLOCK_NTSC: f64 = 1_789_773.0;
+/// PAL CPU clock rate in Hz.
+pub const CPU_CLOCK_PAL: f64 = 1_662_607.0;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// APU sound channels, e.g. for muting them individually while debugging.
+/// `Expansion` is the cartridge's sound chip, if it has one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel { Pulse1, Pulse2, Triangle, Noise, Dmc, Expansion }
+
+impl AudioChannel {
+    pub const ALL: [AudioChannel; 6] = [
+        AudioChannel::Pulse1,
+        AudioChannel::Pulse2,
+        AudioChannel::Triangle,
+        AudioChannel::Noise,
+        AudioChannel::Dmc,
+        AudioChannel::Expansion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AudioChannel::Pulse1 => "pulse1",
+            AudioChannel::Pulse2 => "pulse2",
+            AudioChannel::Triangle => "triangle",
+            AudioChannel::Noise => "noise",
+            AudioChannel::Dmc => "dmc",
+            AudioChannel::Expansion => "expansion",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    /// Output of the cartridge's sound chip, latched every clock.
+    expansion: f32,
+
+    /// `$4017` bit 7: 5-step sequence when set.
+    five_step: bool,
+    /// `$4017` bit 6.
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_clock: u32,
+    /// Pulse timers run at half the CPU clock.
+    odd_cycle: bool,
+
+    /// One bit per [`AudioChannel`]. Muted channels are left out of the mix.
+    muted: u8,
+    mixer: Mixer,
+    output: AudioOutput,
+    recorders: Vec<Recorder>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            expansion: 0.0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_clock: 0,
+            odd_cycle: false,
+            muted: 0,
+            mixer: Mixer::new(),
+            output: AudioOutput::new(CPU_CLOCK_NTSC, DEFAULT_SAMPLE_RATE),
+            recorders: Vec::new(),
+        }
+    }
+
+    /// Advance one CPU clock. DMC sample fetches go through `bus`, and
+    /// `expansion` is the output of the cartridge's sound chip this clock.
+    pub fn step(&mut self, bus: &Bus, expansion: f32) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer(bus);
+        self.expansion = expansion;
+
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.clock_frame_counter();
+
+        let amplitude = self.mix();
+        self.output.clock(amplitude);
+
+        if !self.recorders.is_empty() {
+            self.clock_recorders(amplitude);
+        }
+    }
+
+    fn clock_recorders(&mut self, mix: f32) {
+        let channels = [
+            self.mixer.pulse(self.pulse1.output(), 0),
+            self.mixer.pulse(0, self.pulse2.output()),
+            self.mixer.tnd(self.triangle.output(), 0, 0),
+            self.mixer.tnd(0, self.noise.output(), 0),
+            self.mixer.tnd(0, 0, self.dmc.output()),
+            self.expansion,
+        ];
+
+        for r in self.recorders.iter_mut() {
+            let amplitude = match r.source {
+                RecordSource::Mix => mix,
+                RecordSource::Channel(c) => channels[c as usize],
+            };
+            r.clock(amplitude);
+        }
+    }
+
+    /// Frame counter sequencer.
+    /// See: <https://www.nesdev.org/wiki/APU_Frame_Counter>
+    fn clock_frame_counter(&mut self) {
+        self.frame_clock += 1;
+
+        match (self.frame_clock, self.five_step) {
+            (7457, _) | (22371, _) => self.clock_quarter(),
+            (14913, _) => {
+                self.clock_quarter();
+                self.clock_half();
+            },
+            (29829, false) => {
+                self.clock_quarter();
+                self.clock_half();
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+                self.frame_clock = 0;
+            },
+            (37281, true) => {
+                self.clock_quarter();
+                self.clock_half();
+                self.frame_clock = 0;
+            },
+            _ => {},
+        }
+    }
+
+    fn clock_quarter(&mut self) {
+        self.pulse1.clock_quarter();
+        self.pulse2.clock_quarter();
+        self.triangle.clock_quarter();
+        self.noise.clock_quarter();
+    }
+
+    fn clock_half(&mut self) {
+        self.pulse1.clock_half();
+        self.pulse2.clock_half();
+        self.triangle.clock_half();
+        self.noise.clock_half();
+    }
+
+    fn mix(&self) -> f32 {
+        let pulse1 = self.audible(AudioChannel::Pulse1, self.pulse1.output());
+        let pulse2 = self.audible(AudioChannel::Pulse2, self.pulse2.output());
+        let triangle = self.audible(AudioChannel::Triangle, self.triangle.output());
+        let noise = self.audible(AudioChannel::Noise, self.noise.output());
+        let dmc = self.audible(AudioChannel::Dmc, self.dmc.output());
+
+        let expansion = match self.is_muted(AudioChannel::Expansion) {
+            true => 0.0,
+            false => self.expansion,
+        };
+
+        self.mixer.pulse(pulse1, pulse2) + self.mixer.tnd(triangle, noise, dmc) + expansion
+    }
+
+    fn audible(&self, channel: AudioChannel, output: u8) -> u8 {
+        if self.is_muted(channel) { 0 } else { output }
+    }
+
+    pub fn is_muted(&self, channel: AudioChannel) -> bool {
+        (self.muted & channel.bit()) != 0
+    }
+
+    pub fn set_muted(&mut self, channel: AudioChannel, muted: bool) {
+        match muted {
+            true => self.muted |= channel.bit(),
+            false => self.muted &= !channel.bit(),
+        }
+    }
+
+    /// IRQ line state, from either the frame counter or the DMC.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq_flag
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // IF-D NT21
+            0x4015 => {
+                let status =
+                    (self.pulse1.length.active()   as u8)       |
+                    ((self.pulse2.length.active()  as u8) << 1) |
+                    ((self.triangle.length.active() as u8) << 2) |
+                    ((self.noise.length.active()   as u8) << 3) |
+                    ((self.dmc.active()            as u8) << 4) |
+                    ((self.frame_irq               as u8) << 6) |
+                    ((self.dmc.irq_flag            as u8) << 7);
+
+                self.frame_irq = false;
+                status
+            },
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, value),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, value),
+            0x4008..=0x400b => self.triangle.write(addr - 0x4008, value),
+            0x400c..=0x400f => self.noise.write(addr - 0x400c, value),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, value),
+            // ---D NT21
+            0x4015 => {
+                self.pulse1.length.set_enabled((value & 0b0000_0001) != 0);
+                self.pulse2.length.set_enabled((value & 0b0000_0010) != 0);
+                self.triangle.length.set_enabled((value & 0b0000_0100) != 0);
+                self.noise.length.set_enabled((value & 0b0000_1000) != 0);
+                self.dmc.set_enabled((value & 0b0001_0000) != 0);
+            },
+            // MI-- ----
+            0x4017 => {
+                self.five_step = (value & 0b1000_0000) != 0;
+                self.irq_inhibit = (value & 0b0100_0000) != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+
+                self.frame_clock = 0;
+                if self.five_step {
+                    self.clock_quarter();
+                    self.clock_half();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.output.sample_rate()
+    }
+
+    /// Changing the sample rate discards any buffered audio.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.output.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: f64) {
+        self.output.set_clock_rate(clock_rate);
+    }
+
+    /// See [`crate::Console::set_audio_rate_adjust`].
+    pub fn set_rate_adjust(&mut self, ratio: f64) {
+        self.output.set_rate_adjust(ratio);
+    }
+
+    pub fn samples_available(&mut self) -> usize {
+        self.output.end_frame();
+        self.output.samples_available()
+    }
+
+    /// Move up to `out.len()` samples into `out`, returning how many were
+    /// written.
+    pub fn drain(&mut self, out: &mut [f32]) -> usize {
+        self.output.end_frame();
+        self.output.read(out)
+    }
+
+    /// Discard any buffered audio.
+    pub fn clear_audio(&mut self) {
+        self.output.clear();
+    }
+
+    /// Start recording the mixed output to a WAV file at `path`, at the
+    /// current sample rate. With `per_channel`, every channel is also
+    /// recorded on its own next to it, e.g. `song.wav` gets `song.pulse1.wav`
+    /// through `song.expansion.wav`. Any recording in progress is finished first.
+    pub fn start_recording(&mut self, path: &Path, format: WavFormat, per_channel: bool) -> io::Result<()> {
+        self.stop_recording()?;
+
+        let mut sources = vec![(RecordSource::Mix, path.to_owned())];
+        if per_channel {
+            for c in AudioChannel::ALL.iter() {
+                sources.push((RecordSource::Channel(*c), channel_path(path, *c)));
+            }
+        }
+
+        let clock_rate = self.output.clock_rate();
+        let sample_rate = self.output.sample_rate();
+
+        // All or nothing: a file that can't be created records none.
+        let recorders = sources.into_iter()
+            .map(|(source, path)| {
+                let file = File::create(path)?;
+                Recorder::new(source, file, clock_rate, sample_rate, format)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        self.recorders = recorders;
+
+        Ok(())
+    }
+
+    /// Finish all recordings, reporting the first error any of them ran into.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+
+        for mut r in self.recorders.drain(..) {
+            let finished = r.finish();
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+
+        result
+    }
+
+    pub fn is_recording(&self) -> bool {
+        !self.recorders.is_empty()
+    }
+}
+
+/// `dir/song.wav` => `dir/song.<channel>.wav`
+fn channel_path(path: &Path, channel: AudioChannel) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| e.to_string_lossy()).unwrap_or_else(|| "wav".into());
+    path.with_file_name(format!("{}.{}.{}", stem, channel.name(), ext))
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Apu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Apu")
+            .field("pulse1", &self.pulse1)
+            .field("pulse2", &self.pulse2)
+            .field("triangle", &self.triangle)
+            .field("noise", &self.noise)
+            .field("dmc", &self.dmc)
+            .field("five_step", &self.five_step)
+            .field("irq_inhibit", &self.irq_inhibit)
+            .field("frame_irq", &self.frame_irq)
+            .field("sample_rate", &self.output.sample_rate())
+            .finish()
+    }
+}
+
+/// Channel and frame counter state. Output filters and resampling aren't
+/// saved; they settle within a few samples.
+impl SaveState for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.f32(self.expansion);
+        w.bool(self.five_step);
+        w.bool(self.irq_inhibit);
+        w.bool(self.frame_irq);
+        w.u32(self.frame_clock);
+        w.bool(self.odd_cycle);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.expansion = r.f32()?;
+        self.five_step = r.bool()?;
+        self.irq_inhibit = r.bool()?;
+        self.frame_irq = r.bool()?;
+        self.frame_clock = r.u32()?;
+        self.odd_cycle = r.bool()?;
+        Ok(())
+    }
+}
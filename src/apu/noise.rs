@@ -0,0 +1,105 @@
+use super::units::{Envelope, LengthCounter};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+/// Timer periods in CPU cycles (NTSC).
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// Pseudo-random noise channel, `$400C-$400F`.
+#[derive(Debug)]
+pub(super) struct Noise {
+    /// Short mode taps bit 6 instead of bit 1, giving a 93-step sequence.
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            short_mode: false,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    /// `reg` is the register offset within the channel, `0..=3`.
+    pub fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // --LC VVVV
+            0 => {
+                self.length.halt = (value & 0b0010_0000) != 0;
+                self.envelope.write(value);
+            },
+            1 => {},
+            // M--- PPPP
+            2 => {
+                self.short_mode = (value & 0b1000_0000) != 0;
+                self.timer_period = PERIOD_TABLE[(value & 0x0f) as usize];
+            },
+            // llll l---
+            3 => {
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half(&mut self) {
+        self.length.clock();
+    }
+
+    /// Channel output in `0..=15`.
+    pub fn output(&self) -> u8 {
+        if (self.shift & 0x01) != 0 || !self.length.active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl SaveState for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.short_mode);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u16(self.shift);
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.short_mode = r.bool()?;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.shift = r.u16()?;
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)
+    }
+}
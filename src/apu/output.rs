@@ -0,0 +1,116 @@
+use super::blip::BlipBuf;
+use super::filter::FilterChain;
+
+/// Clocks between automatic frame ends. Keeps the band-limited buffer's time
+/// span short so it never overflows between reads.
+const FRAME_CLOCKS: u32 = 4096;
+/// How much audio is kept when nobody drains the output.
+const BUFFER_MS: u32 = 500;
+
+/// Turns the per-clock mixer output into filtered samples at the host rate.
+pub(crate) struct AudioOutput {
+    blip: BlipBuf,
+    filters: FilterChain,
+    clock_rate: f64,
+    sample_rate: u32,
+    rate_adjust: f64,
+    amplitude: f32,
+    time: u32,
+}
+
+impl AudioOutput {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let mut output = Self {
+            blip: BlipBuf::new(0),
+            filters: FilterChain::nes(sample_rate as f32),
+            clock_rate,
+            sample_rate,
+            rate_adjust: 1.0,
+            amplitude: 0.0,
+            time: 0,
+        };
+
+        output.set_sample_rate(sample_rate);
+        output
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Changing the sample rate discards any buffered audio.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let capacity = (sample_rate * BUFFER_MS / 1000) as usize;
+        self.sample_rate = sample_rate;
+        self.blip = BlipBuf::new(capacity * 2);
+        self.filters = FilterChain::nes(sample_rate as f32);
+        self.time = 0;
+        self.update_rates();
+    }
+
+    pub fn clock_rate(&self) -> f64 {
+        self.clock_rate
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: f64) {
+        self.clock_rate = clock_rate;
+        self.update_rates();
+    }
+
+    /// Dynamic rate control hook. `ratio` above `1.0` produces slightly more
+    /// samples per emulated second, below `1.0` slightly fewer.
+    pub fn set_rate_adjust(&mut self, ratio: f64) {
+        self.rate_adjust = ratio.clamp(0.5, 2.0);
+        self.update_rates();
+    }
+
+    fn update_rates(&mut self) {
+        self.blip.set_rates(self.clock_rate, self.sample_rate as f64 * self.rate_adjust);
+    }
+
+    /// Called once per CPU clock with the current mixer output.
+    pub fn clock(&mut self, amplitude: f32) {
+        if amplitude != self.amplitude {
+            self.blip.add_delta(self.time, amplitude - self.amplitude);
+            self.amplitude = amplitude;
+        }
+
+        self.time += 1;
+
+        if self.time >= FRAME_CLOCKS {
+            self.end_frame();
+        }
+    }
+
+    /// Make every clock so far available as samples.
+    pub fn end_frame(&mut self) {
+        self.blip.end_frame(self.time);
+        self.time = 0;
+
+        // Drop the oldest samples rather than overflow.
+        let limit = self.blip.capacity() / 2;
+        let available = self.blip.samples_available();
+        if available > limit {
+            self.blip.skip_samples(available - limit);
+        }
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.blip.samples_available()
+    }
+
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let count = self.blip.read_samples(out);
+
+        for s in out[..count].iter_mut() {
+            *s = self.filters.process(*s);
+        }
+
+        count
+    }
+
+    pub fn clear(&mut self) {
+        self.blip.clear();
+        self.time = 0;
+    }
+}
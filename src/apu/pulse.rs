@@ -0,0 +1,194 @@
+use super::units::{Envelope, LengthCounter};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Square wave channel. `$4000-$4003` for pulse 1, `$4004-$4007` for pulse 2.
+#[derive(Debug)]
+pub(super) struct Pulse {
+    /// Pulse 1 negates its sweep with one's complement, pulse 2 with two's.
+    ones_complement: bool,
+    /// MMC5 pulses have no sweep unit, and so no sweep muting either.
+    has_sweep: bool,
+    duty: u8,
+    duty_pos: u8,
+    timer_period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    sweep: Sweep,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Self {
+            ones_complement,
+            has_sweep: true,
+            duty: 0,
+            duty_pos: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+            sweep: Sweep::default(),
+        }
+    }
+
+    /// Pulse channel without a sweep unit, as found on the MMC5.
+    pub fn without_sweep() -> Self {
+        Self { has_sweep: false, ..Self::new(false) }
+    }
+
+    /// `reg` is the register offset within the channel, `0..=3`.
+    pub fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // DDLC VVVV
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = (value & 0b0010_0000) != 0;
+                self.envelope.write(value);
+            },
+            // EPPP NSSS
+            1 => if self.has_sweep { self.sweep.write(value) },
+            // LLLL LLLL
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            // llll lHHH
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+                self.duty_pos = 0;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_pos = (self.duty_pos + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half(&mut self) {
+        self.length.clock();
+        if !self.has_sweep {
+            return;
+        }
+
+        let target = self.sweep.target(self.timer_period, self.ones_complement);
+        if let Some(period) = self.sweep.clock(self.timer_period, target) {
+            self.timer_period = period;
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < 8
+            || (self.has_sweep && self.sweep.target(self.timer_period, self.ones_complement) > 0x7ff)
+    }
+
+    /// Channel output in `0..=15`.
+    pub fn output(&self) -> u8 {
+        let high = DUTY_TABLE[self.duty as usize][self.duty_pos as usize] != 0;
+
+        if !high || !self.length.active() || self.muted() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+}
+
+impl Sweep {
+    fn write(&mut self, value: u8) {
+        self.enabled = (value & 0b1000_0000) != 0;
+        self.period = (value >> 4) & 0x07;
+        self.negate = (value & 0b0000_1000) != 0;
+        self.shift = value & 0x07;
+        self.reload = true;
+    }
+
+    fn target(&self, period: u16, ones_complement: bool) -> u16 {
+        let change = period >> self.shift;
+
+        if self.negate {
+            period.saturating_sub(change + ones_complement as u16)
+        } else {
+            period + change
+        }
+    }
+
+    /// Returns the new timer period when the sweep unit adjusts it.
+    fn clock(&mut self, period: u16, target: u16) -> Option<u16> {
+        let muting = period < 8 || target > 0x7ff;
+        let adjust = self.divider == 0 && self.enabled && self.shift > 0 && !muting;
+
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+
+        if adjust { Some(target) } else { None }
+    }
+}
+
+impl SaveState for Pulse {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.duty);
+        w.u8(self.duty_pos);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+
+        let sweep = &self.sweep;
+        w.bool(sweep.enabled);
+        w.u8(sweep.period);
+        w.bool(sweep.negate);
+        w.u8(sweep.shift);
+        w.bool(sweep.reload);
+        w.u8(sweep.divider);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.duty = r.u8()?;
+        self.duty_pos = r.u8()?;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)?;
+
+        let sweep = &mut self.sweep;
+        sweep.enabled = r.bool()?;
+        sweep.period = r.u8()?;
+        sweep.negate = r.bool()?;
+        sweep.shift = r.u8()?;
+        sweep.reload = r.bool()?;
+        sweep.divider = r.u8()?;
+        Ok(())
+    }
+}
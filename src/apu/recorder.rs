@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+use crate::{AudioChannel, WavFormat, WavWriter};
+use super::output::AudioOutput;
+
+/// Samples are written out in chunks of this size.
+const CHUNK: usize = 1024;
+
+/// What a recording captures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RecordSource {
+    /// The mixed APU output, as played back.
+    Mix,
+    /// A single channel on its own, regardless of muting.
+    Channel(AudioChannel),
+}
+
+/// Records one audio stream to a WAV file.
+///
+/// Each recorder resamples its source independently, so recording never
+/// takes samples away from `Console::drain_audio`.
+pub(super) struct Recorder {
+    pub source: RecordSource,
+    output: AudioOutput,
+    /// Taken once the recording is finished.
+    writer: Option<WavWriter<BufWriter<File>>>,
+    buf: Vec<f32>,
+    /// First write error. Recording stops at that point and the error is
+    /// reported when it's finished.
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    pub fn new(
+        source: RecordSource,
+        file: File,
+        clock_rate: f64,
+        sample_rate: u32,
+        format: WavFormat
+    ) -> io::Result<Self> {
+        let writer = WavWriter::new(BufWriter::new(file), sample_rate, format)?;
+
+        Ok(Self {
+            source,
+            output: AudioOutput::new(clock_rate, sample_rate),
+            writer: Some(writer),
+            buf: vec![0f32; CHUNK],
+            error: None,
+        })
+    }
+
+    /// Called once per CPU clock with the source's current amplitude.
+    pub fn clock(&mut self, amplitude: f32) {
+        self.output.clock(amplitude);
+
+        if self.output.samples_available() >= CHUNK {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        let writer = match &mut self.writer {
+            Some(w) if self.error.is_none() => w,
+            _ => return,
+        };
+
+        loop {
+            let count = self.output.read(&mut self.buf);
+            if count == 0 {
+                break;
+            }
+
+            if let Err(e) = writer.write_samples(&self.buf[..count]) {
+                self.error = Some(e);
+                break;
+            }
+        }
+    }
+
+    /// Write out any pending audio and close the file.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.output.end_frame();
+        self.flush();
+
+        let writer = match self.writer.take() {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => writer.finalize().map(|_| ()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
@@ -0,0 +1,108 @@
+use super::units::LengthCounter;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Triangle wave channel, `$4008-$400B`.
+#[derive(Debug, Default)]
+pub(super) struct Triangle {
+    /// Doubles as the length counter halt flag.
+    control: bool,
+    linear_reload_value: u8,
+    linear_reload: bool,
+    linear: u8,
+    timer_period: u16,
+    timer: u16,
+    step: u8,
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    /// `reg` is the register offset within the channel, `0..=3`.
+    pub fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            // CRRR RRRR
+            0 => {
+                self.control = (value & 0b1000_0000) != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0b0111_1111;
+            },
+            1 => {},
+            // LLLL LLLL
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            // llll lHHH
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((value as u16 & 0x07) << 8);
+                self.length.load(value >> 3);
+                self.linear_reload = true;
+            },
+            _ => unreachable!()
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear > 0 && self.length.active() {
+                self.step = (self.step + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half(&mut self) {
+        self.length.clock();
+    }
+
+    /// Channel output in `0..=15`.
+    pub fn output(&self) -> u8 {
+        // Ultrasonic periods are inaudible on hardware but alias badly when
+        // resampled. Hold the sequencer at its midpoint instead.
+        if self.timer_period < 2 {
+            return 7;
+        }
+
+        SEQUENCE[self.step as usize]
+    }
+}
+
+impl SaveState for Triangle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.control);
+        w.u8(self.linear_reload_value);
+        w.bool(self.linear_reload);
+        w.u8(self.linear);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u8(self.step);
+        self.length.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.control = r.bool()?;
+        self.linear_reload_value = r.u8()?;
+        self.linear_reload = r.bool()?;
+        self.linear = r.u8()?;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.step = r.u8()?;
+        self.length.load_state(r)
+    }
+}
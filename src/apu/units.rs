@@ -0,0 +1,125 @@
+//! Building blocks shared between APU channels.
+
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+
+/// Length counter load values, indexed by the upper 5 bits written to the
+/// channel's length register.
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Volume envelope used by the pulse and noise channels.
+#[derive(Debug, Default)]
+pub(super) struct Envelope {
+    pub start: bool,
+    pub looping: bool,
+    pub constant: bool,
+    /// Constant volume, or envelope divider period.
+    pub volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// `--LC VVVV` as written to `$4000`, `$4004` and `$400C`.
+    pub fn write(&mut self, value: u8) {
+        self.looping = (value & 0b0010_0000) != 0;
+        self.constant = (value & 0b0001_0000) != 0;
+        self.volume = value & 0b0000_1111;
+    }
+
+    /// Clocked by the frame counter on quarter frames.
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+/// Length counter shared by every channel except the DMC.
+#[derive(Debug, Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    pub halt: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    /// Load from the length table. Ignored while the channel is disabled.
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[(index & 0x1f) as usize];
+        }
+    }
+
+    /// Clocked by the frame counter on half frames.
+    pub fn clock(&mut self) {
+        if !self.halt && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+
+    /// Controlled through `$4015`. Disabling a channel clears its counter.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.value > 0
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.start);
+        w.bool(self.looping);
+        w.bool(self.constant);
+        w.u8(self.volume);
+        w.u8(self.divider);
+        w.u8(self.decay);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.start = r.bool()?;
+        self.looping = r.bool()?;
+        self.constant = r.bool()?;
+        self.volume = r.u8()?;
+        self.divider = r.u8()?;
+        self.decay = r.u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.bool(self.halt);
+        w.u8(self.value);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        self.halt = r.bool()?;
+        self.value = r.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! Unpacking ROMs from zip and gzip files.
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::checksum;
+use crate::LoadError;
+
+/// Extensions of files loaded from a zip, in no particular order.
+const ROM_EXTENSIONS: [&str; 4] = ["nes", "unf", "fds", "qd"];
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+/// If `data` is a zip or gzip file, return what's in it: `member` of a
+/// zip, or its first ROM. Anything else is returned as is.
+pub(crate) fn unpack(data: Vec<u8>, member: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    match data.get(..4) {
+        Some([0x1F, 0x8B, ..]) => gunzip(&data),
+        Some(b"PK\x03\x04") => unzip(&data, member),
+        _ => Ok(data),
+    }
+}
+
+fn bad(msg: &str) -> LoadError {
+    LoadError::BadArchive(msg.to_owned())
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16, LoadError> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| bad("cut short"))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, LoadError> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| bad("cut short"))
+}
+
+fn inflate(compressed: &[u8], size: usize, crc32: u32) -> Result<Vec<u8>, LoadError> {
+    let data = decompress_to_vec_with_limit(compressed, size)
+        .map_err(|e| LoadError::BadArchive(format!("corrupt data ({:?})", e.status)))?;
+
+    if data.len() != size || checksum::crc32(&[&data]) != crc32 {
+        return Err(bad("checksum mismatch"));
+    }
+    Ok(data)
+}
+
+/// A single member gzip file, see RFC 1952.
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, LoadError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if data.len() < 18 || data[2] != 8 {
+        return Err(bad("not deflate compressed"));
+    }
+
+    let flags = data[3];
+    let mut at = 10;
+    if flags & FEXTRA != 0 {
+        at += 2 + u16_at(data, at)? as usize;
+    }
+    // Zero-terminated file name and comment.
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let len = data.get(at..).and_then(|d| d.iter().position(|&b| b == 0));
+            at += len.ok_or_else(|| bad("cut short"))? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        at += 2;
+    }
+
+    let trailer = data.len() - 8;
+    let compressed = data.get(at..trailer).ok_or_else(|| bad("cut short"))?;
+    let crc32 = u32_at(data, trailer)?;
+    let size = u32_at(data, trailer + 4)? as usize;
+
+    inflate(compressed, size, crc32)
+}
+
+/// Zip files, read through the central directory. No zip64 or encryption.
+fn unzip(data: &[u8], member: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    // The end of directory record is last, before a comment of up to 64k.
+    let search_from = data.len().saturating_sub(22 + 0xFFFF);
+    let end = (search_from..data.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(data, at).ok() == Some(ZIP_END_OF_DIRECTORY))
+        .ok_or_else(|| bad("no zip directory"))?;
+
+    let count = u16_at(data, end + 10)? as usize;
+    let mut at = u32_at(data, end + 16)? as usize;
+
+    let mut found = None;
+    for _ in 0..count {
+        if u32_at(data, at)? != ZIP_CENTRAL_HEADER {
+            return Err(bad("corrupt zip directory"));
+        }
+
+        let name_len = u16_at(data, at + 28)? as usize;
+        let extra_len = u16_at(data, at + 30)? as usize;
+        let comment_len = u16_at(data, at + 32)? as usize;
+        let name = data.get(at + 46..at + 46 + name_len).ok_or_else(|| bad("cut short"))?;
+        let name = String::from_utf8_lossy(name);
+
+        let wanted = match member {
+            Some(member) => name == member,
+            None => is_rom_name(&name),
+        };
+        if wanted {
+            found = Some(at);
+            break;
+        }
+
+        at += 46 + name_len + extra_len + comment_len;
+    }
+
+    let entry = match (found, member) {
+        (Some(entry), _) => entry,
+        (None, Some(member)) => return Err(LoadError::MissingArchiveMember(member.to_owned())),
+        (None, None) => return Err(LoadError::NoRomInArchive),
+    };
+
+    if u16_at(data, entry + 8)? & 0x0001 != 0 {
+        return Err(bad("encrypted"));
+    }
+    let method = u16_at(data, entry + 10)?;
+    let crc32 = u32_at(data, entry + 16)?;
+    let compressed_size = u32_at(data, entry + 20)? as usize;
+    let size = u32_at(data, entry + 24)? as usize;
+    let local = u32_at(data, entry + 42)? as usize;
+
+    if u32_at(data, local)? != ZIP_LOCAL_HEADER {
+        return Err(bad("corrupt zip entry"));
+    }
+    let start = local + 30 + u16_at(data, local + 26)? as usize + u16_at(data, local + 28)? as usize;
+    let compressed = data.get(start..start + compressed_size).ok_or_else(|| bad("cut short"))?;
+
+    match method {
+        0 if checksum::crc32(&[compressed]) == crc32 => Ok(compressed.to_vec()),
+        0 => Err(bad("checksum mismatch")),
+        8 => inflate(compressed, size, crc32),
+        _ => Err(LoadError::BadArchive(format!("unsupported compression method {}", method))),
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let ext = match name.rsplit_once('.') {
+        Some((_, ext)) => ext.to_ascii_lowercase(),
+        None => return false,
+    };
+    !name.ends_with('/') && ROM_EXTENSIONS.contains(&ext.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    const ROM: &[u8] = b"NES\x1a not much of a ROM, but it will do";
+
+    /// A zip holding `members`, each `(name, method, data)`.
+    fn zip(members: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+
+        for &(name, method, data) in members {
+            let stored = match method {
+                8 => compress_to_vec(data, 6),
+                _ => data.to_vec(),
+            };
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&[20, 0, 0, 0]);
+            fields.extend_from_slice(&method.to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            fields.extend_from_slice(&checksum::crc32(&[data]).to_le_bytes());
+            fields.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&[0; 2]);
+
+            directory.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+            directory.extend_from_slice(&[20, 0]);
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&(zip.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            zip.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+            zip.extend_from_slice(&fields);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(&stored);
+        }
+
+        let directory_at = zip.len() as u32;
+        zip.extend_from_slice(&directory);
+        zip.extend_from_slice(&ZIP_END_OF_DIRECTORY.to_le_bytes());
+        zip.extend_from_slice(&[0; 4]);
+        for _ in 0..2 {
+            zip.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        }
+        zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&directory_at.to_le_bytes());
+        zip.extend_from_slice(&[0; 2]);
+        zip
+    }
+
+    #[test]
+    fn zip_stored_and_deflated() {
+        let data = zip(&[
+            ("readme.txt", 0, b"hello"),
+            ("game.nes", 0, ROM),
+            ("game.fds", 8, ROM),
+        ]);
+
+        assert_eq!(unpack(data.clone(), None).unwrap(), ROM);
+        assert_eq!(unpack(data.clone(), Some("readme.txt")).unwrap(), b"hello");
+        assert_eq!(unpack(data.clone(), Some("game.fds")).unwrap(), ROM);
+        assert!(matches!(
+            unpack(data, Some("other.nes")),
+            Err(LoadError::MissingArchiveMember(_))
+        ));
+
+        let data = zip(&[("readme.txt", 8, b"hello")]);
+        assert!(matches!(unpack(data, None), Err(LoadError::NoRomInArchive)));
+    }
+
+    #[test]
+    fn gzip_with_name() {
+        let mut data = vec![0x1F, 0x8B, 8, 0x08, 0, 0, 0, 0, 0, 3];
+        data.extend_from_slice(b"game.nes\0");
+        data.extend_from_slice(&compress_to_vec(ROM, 6));
+        data.extend_from_slice(&checksum::crc32(&[ROM]).to_le_bytes());
+        data.extend_from_slice(&(ROM.len() as u32).to_le_bytes());
+
+        assert_eq!(unpack(data.clone(), None).unwrap(), ROM);
+
+        // Corrupting the stored size fails the check.
+        let at = data.len() - 4;
+        data[at] ^= 1;
+        assert!(unpack(data, None).is_err());
+    }
+
+    #[test]
+    fn not_an_archive() {
+        assert_eq!(unpack(ROM.to_vec(), None).unwrap(), ROM);
+    }
+}
@@ -0,0 +1,110 @@
+use std::process;
+
+use clap::Parser;
+use jadeite::{Nsf, NsfPlayer, WavFormat};
+
+/// Length used when neither the command line nor the file gives one.
+const DEFAULT_SECONDS: f64 = 150.0;
+
+/// Render an NSF/NSFe track to a WAV file.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// NSF or NSFe file to play.
+    input_file: String,
+
+    /// WAV file to write.
+    #[clap(short, long, required_unless_present = "list")]
+    output_file: Option<String>,
+
+    /// Track to render, starting at 1. Defaults to the file's starting track.
+    #[clap(short, long)]
+    track: Option<u8>,
+
+    /// Length in seconds. Defaults to the NSFe track time plus fade, if any.
+    #[clap(short, long)]
+    seconds: Option<f64>,
+
+    /// Output sample rate.
+    #[clap(short='r', long, default_value="44100")]
+    sample_rate: u32,
+
+    /// Write 32-bit float samples instead of 16-bit PCM.
+    #[clap(long)]
+    float: bool,
+
+    /// Also write each APU channel to its own file.
+    #[clap(long)]
+    per_channel: bool,
+
+    /// List the tracks and exit.
+    #[clap(short, long)]
+    list: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let nsf = Nsf::read_file(&args.input_file).unwrap_or_else(|e| {
+        eprintln!("Error reading NSF file: {}", e);
+        process::exit(1);
+    });
+
+    let output_file = match args.output_file {
+        Some(f) if !args.list => f,
+        _ => {
+            print_info(&nsf);
+            return;
+        },
+    };
+
+    let track = args.track.unwrap_or(nsf.starting_song).max(1) - 1;
+    if track >= nsf.total_songs {
+        eprintln!("Track {} out of range: {} tracks.", track + 1, nsf.total_songs);
+        process::exit(1);
+    }
+
+    let seconds = args.seconds.unwrap_or_else(|| track_length(&nsf, track as usize));
+    let format = match args.float {
+        true => WavFormat::Float32,
+        false => WavFormat::Pcm16,
+    };
+
+    let mut player = NsfPlayer::new(&nsf, nsf.to_cart());
+    player.console.set_audio_sample_rate(args.sample_rate);
+    player.select_song(track);
+
+    player.console
+        .start_audio_recording(&output_file, format, args.per_channel)
+        .expect("Error writing to output file.");
+
+    player.run_for(seconds);
+
+    player.console
+        .stop_audio_recording()
+        .expect("Error writing to output file.");
+}
+
+/// NSFe time + fade, in seconds.
+fn track_length(nsf: &Nsf, track: usize) -> f64 {
+    let time = nsf.track_times.get(track).copied().filter(|t| *t >= 0);
+    let fade = nsf.track_fades.get(track).copied().filter(|t| *t >= 0).unwrap_or(0);
+
+    match time {
+        Some(ms) => (ms + fade) as f64 / 1000.0,
+        None => DEFAULT_SECONDS,
+    }
+}
+
+fn print_info(nsf: &Nsf) {
+    println!("Name:      {}", nsf.name);
+    println!("Artist:    {}", nsf.artist);
+    println!("Copyright: {}", nsf.copyright);
+    println!("Region:    {:?}", nsf.region);
+    println!("Tracks:    {}", nsf.total_songs);
+
+    for i in 0..nsf.total_songs as usize {
+        let label = nsf.track_labels.get(i).map(|s| s.as_str()).unwrap_or("");
+        println!("{:>4}  {:<32} {:>6.1}s", i + 1, label, track_length(nsf, i));
+    }
+}
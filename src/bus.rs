@@ -1,29 +1,49 @@
-use std::{cell::RefCell, fmt::{Debug, Write}, rc::Rc};
+use std::{cell::{Ref, RefCell}, fmt::{Debug, Write}};
 
-use crate::{Cart, Ppu};
+use crate::{Apu, Cart, Controller, DeviceInput, InputDevice, Ppu, EXPANSION_PORT};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 
-pub struct Bus <'a> {
+pub struct Bus {
     ram: Box<[u8]>,
-    cart: Option<&'a mut Cart>,
-    ppu: Rc<RefCell<Ppu>>,
+    cart: Option<Box<Cart>>,
+    /// Register reads have side effects, like clearing vblank or the frame
+    /// IRQ, so these sit behind a `RefCell` for `read` too.
+    ppu: RefCell<Ppu>,
+    apu: RefCell<Apu>,
+    /// Controller ports 1-2 and the expansion port. Reads shift the
+    /// devices, so they sit behind a `RefCell` for `read` to get at them.
+    ports: RefCell<[Option<Box<dyn InputDevice>>; 3]>,
 }
 
-impl <'a> Bus <'a> {
-    pub fn new(ppu: Rc<RefCell<Ppu>>) -> Self {
+impl Bus {
+    pub fn new() -> Self {
+        Self::with_ppu(Ppu::new())
+    }
+
+    pub fn with_ppu(ppu: Ppu) -> Self {
         Self {
             ram: vec![0x0u8; 0x800].into_boxed_slice(),
             cart: None,
-            ppu,
+            ppu: RefCell::new(ppu),
+            apu: RefCell::new(Apu::new()),
+            ports: RefCell::new([
+                Some(Box::new(Controller::new())),
+                Some(Box::new(Controller::new())),
+                None,
+            ]),
         }
     }
 
-    pub fn attach_cart(&mut self, cart: &'a mut Cart) {
-        self.cart = Some(cart);
+    /// Plug in `cart`, handing back the one that was in.
+    pub fn attach_cart(&mut self, cart: Box<Cart>) -> Option<Box<Cart>> {
+        self.cart.replace(cart)
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
-        let cart = self.cart.as_ref().unwrap();
+    pub fn detach_cart(&mut self) -> Option<Box<Cart>> {
+        self.cart.take()
+    }
 
+    pub fn read(&self, addr: u16) -> u8 {
         let value = match addr {
             // RAM & Mirros
             0x0000..=0x01fff => {
@@ -33,23 +53,33 @@ impl <'a> Bus <'a> {
             // PPU Registers & Mirrors
             0x2000..=0x3fff => {
                 let addr_adj = 0x2000 | (addr&0x0007);
-                let value = (*self.ppu).borrow_mut().read(addr_adj);
+                let value = self.ppu.borrow_mut().read(addr_adj);
                 // println!("= Read: @{:04X} (ADJ: {:04X}) = {:02X}", addr, addr_adj, value);
                 value
             },
 
-            // Cartridge ROM
-            0x8000..=0xffff => {
-                cart.cpu_read(addr)
+            // Cartridge Space, reading 0 without a cart.
+            0x4020..=0xffff => {
+                self.cart.as_ref().map_or(0, |cart| cart.cpu_read(addr))
             },
 
-            // APU & IO Registers
-            0x4000..=0x4017 => {
-                // TODO: Implement APU & IO.
-                0
+            // APU Status
+            0x4015 => {
+                self.apu.borrow_mut().read(addr)
+            },
+
+            // Controller Ports
+            // Only the low bits are driven, the rest is open bus. That's
+            // the address's high byte for the usual absolute reads.
+            0x4016..=0x4017 => {
+                0x40 | self.read_ports(addr)
             },
 
-            a => panic!("Bus: Adressing nowhere: {:#06X}", a),
+            // APU Write-only Registers, and the CPU test mode registers
+            // that are disabled on retail consoles.
+            0x4000..=0x4014 | 0x4018..=0x401f => {
+                0
+            },
         };
 
         // println!("= Read: @{:04X} = {:02X}", addr, value);
@@ -57,7 +87,6 @@ impl <'a> Bus <'a> {
     }
 
     pub fn write(&mut self, addr: u16, value: u8) {
-        let cart = self.cart.as_mut().unwrap();
         // println!("+ WRITE: @{:04X} = {:02X}", addr, value);
 
         match addr {
@@ -69,22 +98,130 @@ impl <'a> Bus <'a> {
             // PPU Registers & Mirrors
             0x2000..=0x3fff => {
                 let addr_adj = 0x2000 | (addr&0x0007);
-                (*self.ppu).borrow_mut().write(addr_adj, value);
+                self.ppu.get_mut().write(addr_adj, value);
                 // println!("= Write: @{:04X} (ADJ: {:04X}) = {:02X}", addr, addr_adj, value);
             },
 
-            // Cartridge
-            0x8000..=0xffff => {
-                cart.cpu_write(addr, value);
+            // Cartridge Space
+            0x4020..=0xffff => {
+                if let Some(cart) = &mut self.cart {
+                    cart.cpu_write(addr, value);
+                }
+            },
+
+            // APU Registers
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.apu.get_mut().write(addr, value);
             },
 
-            // PPU Registers
-            0x4000..=0x4017 => {
+            // Controller Strobe & Expansion Port Outputs
+            0x4016 => {
+                for device in self.ports.get_mut().iter_mut().flatten() {
+                    device.write(value);
+                }
+            },
+
+            // OAM DMA
+            0x4014 => {
                 // Does nothing.
-                // TODO: Implement audio.
+                // TODO: Implement OAM DMA.
+            }
+
+            // CPU test mode, disabled on retail consoles.
+            0x4018..=0x401f => {},
+        }
+    }
+
+    /// Bits 0-4 of a `$4016`/`$4017` read, from the controller port and
+    /// the expansion port.
+    fn read_ports(&self, addr: u16) -> u8 {
+        let ppu = self.ppu.borrow();
+        let mut ports = self.ports.borrow_mut();
+        let port = (addr - 0x4016) as usize;
+
+        let mut value = 0;
+        for p in [port, EXPANSION_PORT] {
+            if let Some(device) = &mut ports[p] {
+                value |= device.read(addr, &ppu);
             }
+        }
+
+        value & 0x1f
+    }
+
+    /// Plug `device` into `port`, see [`crate::PORT_1`], [`crate::PORT_2`]
+    /// and [`EXPANSION_PORT`]. `None` leaves it empty.
+    pub fn set_device(&mut self, port: usize, device: Option<Box<dyn InputDevice>>) {
+        self.ports.get_mut()[port] = device;
+    }
+
+    pub fn device_name(&self, port: usize) -> Option<&'static str> {
+        self.ports.borrow()[port].as_ref().map(|d| d.name())
+    }
+
+    pub fn input(&self, port: usize) -> Option<DeviceInput> {
+        self.ports.borrow()[port].as_ref().map(|d| d.input())
+    }
 
-            _ => unimplemented!()
+    pub fn set_input(&mut self, port: usize, input: DeviceInput) {
+        if let Some(device) = &mut self.ports.get_mut()[port] {
+            device.set_input(input);
+        }
+    }
+
+    pub fn ppu(&self) -> Ref<'_, Ppu> {
+        self.ppu.borrow()
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        self.ppu.get_mut()
+    }
+
+    pub fn apu(&self) -> Ref<'_, Apu> {
+        self.apu.borrow()
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        self.apu.get_mut()
+    }
+
+    /// Advance the APU, and the cartridge's sound chip, one CPU clock.
+    pub fn clock_apu(&mut self) {
+        let expansion = self.clock_expansion_audio();
+        // DMC fetches only read cartridge space, never the APU itself.
+        self.apu.borrow_mut().step(self, expansion);
+    }
+
+    pub fn cart(&self) -> Option<&Cart> {
+        self.cart.as_deref()
+    }
+
+    pub fn cart_mut(&mut self) -> Option<&mut Cart> {
+        self.cart.as_deref_mut()
+    }
+
+    /// Advance the cartridge's timers, or the FDS disk drive, one CPU clock.
+    pub fn clock_cart(&mut self) {
+        if let Some(cart) = &mut self.cart {
+            cart.clock();
+        }
+    }
+
+    /// Whether the cartridge is asserting IRQ.
+    pub fn cart_irq(&self) -> bool {
+        self.cart.as_ref().is_some_and(|c| c.irq())
+    }
+
+    /// Clock the cartridge's sound chip, if any, and return its output.
+    fn clock_expansion_audio(&mut self) -> f32 {
+        let audio = self.cart.as_mut().and_then(|c| c.expansion_audio());
+
+        match audio {
+            Some(audio) => {
+                audio.clock();
+                audio.output()
+            },
+            None => 0.0,
         }
     }
 
@@ -112,7 +249,32 @@ impl <'a> Bus <'a> {
     }
 }
 
-impl <'a> Debug for Bus <'a> {
+/// RAM and the cartridge. Input devices aren't saved, frontends set their
+/// input every frame anyway.
+impl SaveState for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        if let Some(cart) = &self.cart {
+            cart.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes_into(&mut self.ram)?;
+        match &mut self.cart {
+            Some(cart) => cart.load_state(r),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Bus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Bus")
         .field("ram", &format!("RAM: {} bytes of memory", self.ram.len()))
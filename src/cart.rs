@@ -1,8 +1,14 @@
 use std::io::Read;
-use std::fs::File;
+use std::fs;
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 
-use crate::mapper::{Mapper, Mapper000};
+use crate::{ExpansionAudio, FdsDisk, HeaderFix, LoadError, RomDb, FRAME_RATE_NTSC, FRAME_RATE_PAL};
+use crate::{archive, checksum, fds, patch, unif};
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+use crate::mapper::{self, Mapper, Mapper000, Mapper001, Mmc1Board};
+
+const INES_MAGIC: &[u8; 4] = b"NES\x1A";
 
 pub struct Cart {
     pub data: CartData,
@@ -10,48 +16,201 @@ pub struct Cart {
 }
 
 pub struct CartData {
-    pub prg_rom_page_count: u8,             // 4 - N x 16kb
-    pub chr_rom_page_count: u8,             // 5 - N x 8kb
+    pub is_nes2: bool,                      // 7(2:3) == 0b10
+
+    pub prg_rom_page_count: u16,            // 4, NES 2.0: 9(0:3) - N x 16kb
+    pub chr_rom_page_count: u16,            // 5, NES 2.0: 9(4:7) - N x 8kb
 
     pub mirroring: Mirroring,               // 6(0)
     pub sram_enable: bool,                  // 6(1)
     pub trainer_present: bool,              // 6(2)
     pub four_screen_vram_layout: bool,      // 6(3)
 
-    pub mapper_id: u16,                      // low: 6(4:7), high: 7(4:7)
+    pub mapper_id: u16,                     // low: 6(4:7), mid: 7(4:7), NES 2.0 high: 8(0:3)
+    pub submapper_id: u8,                   // NES 2.0: 8(4:7)
 
     pub is_vs_system: bool,                 // 7(0)
+    pub console_type: ConsoleType,          // 7(0:1), NES 2.0: 13
     pub ram_banks: u8,                      // 8 - 0: assume 1x8kb
-    pub tv_system: TVSystem,                // 9(0)
+    pub tv_system: TVSystem,                // 9(0), NES 2.0: 12(0:1)
+    pub timing: Timing,                     // NES 2.0: 12(0:1)
+
+    // Sizes in bytes. iNES 1.0 only knows PRG RAM, which counts as NVRAM
+    // with a battery.
+    pub prg_ram_size: usize,                // NES 2.0: 10(0:3)
+    pub prg_nvram_size: usize,              // NES 2.0: 10(4:7)
+    pub chr_ram_size: usize,                // NES 2.0: 11(0:3)
+    pub chr_nvram_size: usize,              // NES 2.0: 11(4:7)
+
+    pub misc_rom_count: u8,                 // NES 2.0: 14(0:1)
+    /// See <https://www.nesdev.org/wiki/NES_2.0#Default_Expansion_Device>,
+    /// e.g. `1` for standard controllers, `8` for a Zapper. `0` if unknown.
+    pub expansion_device: u8,               // NES 2.0: 15(0:5)
 
     // memory
     pub trainer: Option<Vec<u8>>,
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
+    /// Anything after CHR ROM, the misc ROMs if `misc_rom_count` isn't 0.
     pub extra_bytes: Vec<u8>,
     pub chr_ram: Vec<u8>,
-    
+    /// Work RAM at `$6000`, `prg_nvram_size` bytes of battery-backed RAM
+    /// first, then `prg_ram_size` bytes without.
+    pub prg_ram: Vec<u8>,
+
+    /// Of PRG and CHR ROM together, or the disk sides of an FDS game,
+    /// identifying the dump.
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    /// Header fields the ROM database corrected.
+    pub header_fixes: Vec<HeaderFix>,
+    /// Soft patches applied on load, in order.
+    pub patches: Vec<PathBuf>,
+}
+
+impl CartData {
+    /// Index in `prg_ram` of `offset` in 8KB `bank` of the `$6000` window.
+    /// Bank 0 is the plain RAM on boards with both kinds, the rest wrap
+    /// around into the battery-backed part. `None` without PRG RAM.
+    pub fn prg_ram_index(&self, bank: usize, offset: usize) -> Option<usize> {
+        match self.prg_ram.len() {
+            0 => None,
+            len => Some((bank*0x2000 + self.prg_ram_size + offset) % len),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub enum Mirroring { Vertical, Horizontal }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring { Vertical, Horizontal, OneScreenLower, OneScreenUpper }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TVSystem { NTSC, PAL }
 
+impl TVSystem {
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            TVSystem::NTSC => FRAME_RATE_NTSC,
+            TVSystem::PAL => FRAME_RATE_PAL,
+        }
+    }
+}
+
+/// CPU/PPU timing the ROM was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    /// Works on either.
+    MultiRegion,
+    /// Famiclone timing: PAL frame rate, NTSC-like CPU speed.
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    /// Vs. System PPU and hardware type: byte 13's low and high nibble.
+    VsSystem { ppu: u8, hardware: u8 },
+    Playchoice10,
+    /// Extended console type from byte 13, e.g. `3` for VT01 famiclones.
+    Extended(u8),
+}
+
+
+/// How to load a ROM. The default trusts the header, loads the first ROM
+/// found in a zip and applies patches found next to the ROM file.
+#[derive(Clone, Copy)]
+pub struct LoadOptions<'a> {
+    /// Database to fix the header from, see [`RomDb::from_file`]. `None` to
+    /// trust it as is.
+    pub rom_db: Option<&'a RomDb>,
+    /// File to load from a zip instead of the first ROM in it.
+    pub archive_member: Option<&'a str>,
+    /// IPS, BPS or UPS patches to apply in order, after any found next to
+    /// the ROM.
+    pub patches: &'a [PathBuf],
+    /// Look for `game.ips`, `.bps` or `.ups` next to `game.nes`, then
+    /// `game.ips1`, `game.ips2` and so on to stack on top. Only when
+    /// loading from a file.
+    pub find_patches: bool,
+    /// The FDS BIOS, `disksys.rom`, for loading disk images.
+    pub fds_bios: Option<&'a Path>,
+}
+
+impl Default for LoadOptions<'_> {
+    fn default() -> Self {
+        Self {
+            rom_db: None,
+            archive_member: None,
+            patches: &[],
+            find_patches: true,
+            fds_bios: None,
+        }
+    }
+}
 
 impl Cart {
-    pub fn read_file(fname: &str) -> Result<Self, ()> {
-        let mut src = File::open(fname).map_err(|_| ())?;
-        Self::read_from(&mut src)
+    /// Load an iNES file, possibly zipped or gzipped, with the default
+    /// [`LoadOptions`].
+    pub fn read_file<P: AsRef<Path>>(fname: P) -> Result<Self, LoadError> {
+        Self::read_file_with(fname, &LoadOptions::default())
     }
 
-    pub fn read_from<T: Read>(src: &mut T) -> Result<Self, ()> {
-        let mut header_buf = vec![0u8; 16];
-        src.read_exact(&mut header_buf).map_err(|_| ())?;
+    /// Patches are applied to the ROM in memory, the file is never changed.
+    pub fn read_file_with<P: AsRef<Path>>(fname: P, options: &LoadOptions) -> Result<Self, LoadError> {
+        let path = fname.as_ref();
+        let data = fs::read(path)
+            .map_err(|e| LoadError::Io(Some(path.to_owned()), e))?;
 
-        let prg_rom_page_count = header_buf[4];
-        let chr_rom_page_count = header_buf[5];
+        let mut patches = match options.find_patches {
+            true => patch::find_patches(path),
+            false => Vec::new(),
+        };
+        patches.extend_from_slice(options.patches);
+
+        Self::load(data, &patches, options).map_err(|e| e.with_path(path.to_owned()))
+    }
+
+    pub fn read_from<T: Read>(src: &mut T) -> Result<Self, LoadError> {
+        Self::read_from_with(src, &LoadOptions::default())
+    }
+
+    pub fn read_from_with<T: Read>(src: &mut T, options: &LoadOptions) -> Result<Self, LoadError> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Self::load(data, options.patches, options)
+    }
+
+    fn load(data: Vec<u8>, patches: &[PathBuf], options: &LoadOptions) -> Result<Self, LoadError> {
+        let mut data = archive::unpack(data, options.archive_member)?;
+
+        for path in patches {
+            let patch_data = fs::read(path)
+                .map_err(|e| LoadError::Io(Some(path.clone()), e))?;
+            data = patch::apply(&data, &patch_data, path)?;
+        }
+
+        if fds::is_disk_image(&data) {
+            let mut cart = fds::read_fds(&data, options.fds_bios)?;
+            cart.data.patches = patches.to_vec();
+            return Ok(cart);
+        }
+
+        let mut cart_data = match data.get(..4) {
+            Some(unif::UNIF_MAGIC) => unif::read_unif(&data)?,
+            _ => Self::read_ines(&mut &data[..])?,
+        };
+        cart_data.patches = patches.to_vec();
+        Self::from_data(cart_data, options.rom_db)
+    }
+
+    fn read_ines<T: Read>(src: &mut T) -> Result<CartData, LoadError> {
+        let mut header_buf = read_section(src, 16, |_, _| LoadError::TruncatedHeader)?;
+        if &header_buf[0..4] != INES_MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+
+        let is_nes2 = header_buf[7] & 0b0000_1100 == 0b0000_1000;
 
         let mirroring = match header_buf[6] & 0b0000_0001 {
             0 => Mirroring::Horizontal,
@@ -63,66 +222,191 @@ impl Cart {
         let trainer_present = (header_buf[6] & 0b0000_0100) >> 2 == 1;
         let four_screen_vram_layout = (header_buf[6] & 0b0000_1000) >> 3 == 1;
 
-        let mapper_id_lo = (header_buf[6] & 0b1111_0000) as u16;
-        let mapper_id_hi = (header_buf[7] & 0b1111_0000) as u16;
-        let mapper_id = mapper_id_lo & (mapper_id_hi << 4);
-        let mapper = Self::create_mapper(mapper_id);
+        // Old iNES dumps often have junk like "DiskDude!" from byte 7 on,
+        // which would give bogus upper mapper bits, RAM size and region.
+        if !is_nes2 && header_buf[12..16].iter().any(|&b| b != 0) {
+            header_buf[7..16].fill(0);
+        }
+        let byte_7 = header_buf[7];
 
-        let is_vs_system = (header_buf[7] & 0b0000_0001) == 1;
-        let ram_banks = header_buf[8];
-        let tv_system = match header_buf[9] & 0b0000_0001 {
-            0 => TVSystem::NTSC,
-            1 => TVSystem::PAL,
-            _ => unreachable!()
+        let mapper_id_lo = (header_buf[6] >> 4) as u16;
+        let mapper_id_mid = (byte_7 & 0b1111_0000) as u16;
+        let mapper_id_hi = match is_nes2 {
+            true => ((header_buf[8] & 0b0000_1111) as u16) << 8,
+            false => 0,
+        };
+        let mapper_id = mapper_id_hi | mapper_id_mid | mapper_id_lo;
+        let submapper_id = if is_nes2 { header_buf[8] >> 4 } else { 0 };
+
+        let console_type = match (byte_7 & 0b0000_0011, is_nes2) {
+            (0, _) => ConsoleType::Nes,
+            (1, true) => ConsoleType::VsSystem {
+                ppu: header_buf[13] & 0b0000_1111,
+                hardware: header_buf[13] >> 4,
+            },
+            (1, false) => ConsoleType::VsSystem { ppu: 0, hardware: 0 },
+            (2, _) => ConsoleType::Playchoice10,
+            (_, true) => ConsoleType::Extended(header_buf[13] & 0b0000_1111),
+            // Both bits set isn't valid in iNES 1.0, go with the VS bit.
+            (_, false) => ConsoleType::VsSystem { ppu: 0, hardware: 0 },
+        };
+        let is_vs_system = matches!(console_type, ConsoleType::VsSystem { .. });
+
+        let timing = match (is_nes2, header_buf[12] & 0b0000_0011, header_buf[9] & 0b0000_0001) {
+            (true, 0, _) => Timing::Ntsc,
+            (true, 1, _) => Timing::Pal,
+            (true, 2, _) => Timing::MultiRegion,
+            (true, _, _) => Timing::Dendy,
+            (false, _, 0) => Timing::Ntsc,
+            (false, _, _) => Timing::Pal,
+        };
+        let tv_system = match timing {
+            Timing::Pal | Timing::Dendy => TVSystem::PAL,
+            Timing::Ntsc | Timing::MultiRegion => TVSystem::NTSC,
         };
 
-        let trainer = match trainer_present {
-            true => {
-                let mut buf = vec![0u8; 512];
-                src.read_exact(&mut buf).map_err(|_| ())?;
-                Some(buf)
+        let (prg_rom_size, chr_rom_size) = match is_nes2 {
+            true => (
+                nes2_rom_size(header_buf[4], header_buf[9] & 0b0000_1111, 16*1024),
+                nes2_rom_size(header_buf[5], header_buf[9] >> 4, 8*1024),
+            ),
+            false => (header_buf[4] as usize*16*1024, header_buf[5] as usize*8*1024),
+        };
+        let prg_rom_page_count = prg_rom_size.div_ceil(16*1024) as u16;
+        let chr_rom_page_count = chr_rom_size.div_ceil(8*1024) as u16;
+
+        let (prg_ram_size, prg_nvram_size, chr_ram_size, chr_nvram_size) = match is_nes2 {
+            true => (
+                nes2_ram_size(header_buf[10] & 0b0000_1111),
+                nes2_ram_size(header_buf[10] >> 4),
+                nes2_ram_size(header_buf[11] & 0b0000_1111),
+                nes2_ram_size(header_buf[11] >> 4),
+            ),
+            false => {
+                let prg_ram = header_buf[8].max(1) as usize*8*1024;
+                let chr_ram = if chr_rom_size == 0 { 8*1024 } else { 0 };
+                match sram_enable {
+                    true => (0, prg_ram, chr_ram, 0),
+                    false => (prg_ram, 0, chr_ram, 0),
+                }
             },
+        };
+        let ram_banks = match is_nes2 {
+            true => ((prg_ram_size + prg_nvram_size) / (8*1024)).min(u8::MAX as usize) as u8,
+            false => header_buf[8],
+        };
+
+        let misc_rom_count = if is_nes2 { header_buf[14] & 0b0000_0011 } else { 0 };
+        let expansion_device = if is_nes2 { header_buf[15] & 0b0011_1111 } else { 0 };
+
+        let trainer = match trainer_present {
+            true => Some(read_section(src, 512, |expected, found| {
+                LoadError::TruncatedTrainer { expected, found }
+            })?),
             false => None
         };
 
-        let mut prg_rom: Vec<u8> = vec![0u8; prg_rom_page_count as usize*16*1024];
-        src.read_exact(&mut prg_rom).map_err(|_| ())?;
-        
-        let mut chr_rom: Vec<u8> = vec![0u8; chr_rom_page_count as usize*8*1024];
-        src.read_exact(&mut chr_rom).map_err(|_| ())?;
+        let prg_rom = read_section(src, prg_rom_size, |expected, found| {
+            LoadError::TruncatedPrg { expected, found }
+        })?;
+        let chr_rom = read_section(src, chr_rom_size, |expected, found| {
+            LoadError::TruncatedChr { expected, found }
+        })?;
 
         let mut extra_bytes = Vec::new();
-        src.read_to_end(&mut extra_bytes).map_err(|_| ())?;
-
-        let chr_ram_size = 0x1fff*(1-chr_rom_page_count) as usize;
-        let chr_ram = vec![0u8; chr_ram_size];
-
-        Ok(Self {
-            data: CartData {
-                prg_rom_page_count,
-                chr_rom_page_count,
-                mirroring,
-                sram_enable,
-                trainer_present,
-                four_screen_vram_layout,
-                mapper_id,
-                is_vs_system,
-                ram_banks,
-                tv_system,
-                trainer,
-                prg_rom,
-                chr_rom,
-                extra_bytes,
-                chr_ram,
-            },
-            mapper,
+        src.read_to_end(&mut extra_bytes)?;
+
+        let crc32 = checksum::crc32(&[&prg_rom, &chr_rom]);
+        let sha1 = checksum::sha1(&[&prg_rom, &chr_rom]);
+
+        Ok(CartData {
+            is_nes2,
+            prg_rom_page_count,
+            chr_rom_page_count,
+            mirroring,
+            sram_enable,
+            trainer_present,
+            four_screen_vram_layout,
+            mapper_id,
+            submapper_id,
+            is_vs_system,
+            console_type,
+            ram_banks,
+            tv_system,
+            timing,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            misc_rom_count,
+            expansion_device,
+            trainer,
+            prg_rom,
+            chr_rom,
+            extra_bytes,
+            chr_ram: Vec::new(),
+            prg_ram: Vec::new(),
+            crc32,
+            sha1,
+            header_fixes: Vec::new(),
+            patches: Vec::new(),
         })
     }
 
-    fn create_mapper(id: u16) -> Box<dyn Mapper> {
-        match id {
-            0 => Box::new(Mapper000::new()),
-            _ => unimplemented!()
+    /// Fix the header from `db`, then set up CHR RAM and the mapper.
+    fn from_data(mut data: CartData, db: Option<&RomDb>) -> Result<Self, LoadError> {
+        if let Some(db) = db {
+            data.header_fixes = db.apply(&mut data);
+        }
+
+        // Boards without CHR ROM always have CHR RAM, whatever the header
+        // claims.
+        data.chr_ram = match data.chr_ram_size + data.chr_nvram_size {
+            0 if data.chr_rom.is_empty() => vec![0u8; 8*1024],
+            len => vec![0u8; len],
+        };
+
+        // Copiers loaded the trainer into RAM at $7000 on power-up, so the
+        // cart needs the whole 8KB window of RAM for it.
+        if data.trainer.is_some() {
+            let min_ram = (8*1024usize).saturating_sub(data.prg_nvram_size);
+            data.prg_ram_size = data.prg_ram_size.max(min_ram);
+        }
+        data.prg_ram = vec![0u8; data.prg_ram_size + data.prg_nvram_size];
+        Self::copy_trainer(&mut data);
+
+        let mapper = Self::create_mapper(&data)?;
+
+        Ok(Self { data, mapper })
+    }
+
+    /// Put the trainer at `$7000`, over whatever RAM is there.
+    fn copy_trainer(data: &mut CartData) {
+        if let Some(trainer) = &data.trainer {
+            let at = data.prg_ram_index(0, 0x1000).unwrap_or(0);
+            data.prg_ram[at..at + trainer.len()].copy_from_slice(trainer);
+        }
+    }
+
+    fn create_mapper(data: &CartData) -> Result<Box<dyn Mapper>, LoadError> {
+        let (id, submapper) = (data.mapper_id, data.submapper_id);
+        let (prg, chr) = (data.prg_rom.len(), data.chr_rom.len());
+        let sizes_ok = match id {
+            0 => matches!(prg, 0x4000 | 0x8000) && matches!(chr, 0 | 0x2000),
+            _ => prg > 0,
+        };
+        if !sizes_ok {
+            return Err(LoadError::UnsupportedRomSize { mapper: id, prg, chr });
+        }
+
+        match (id, submapper) {
+            (0, 0) => Ok(Box::new(Mapper000::new())),
+            (1, _) => match Mmc1Board::detect(data) {
+                Some(board) => Ok(Box::new(Mapper001::new(board))),
+                None => Err(LoadError::UnsupportedSubmapper { mapper: id, submapper }),
+            },
+            (0, _) => Err(LoadError::UnsupportedSubmapper { mapper: id, submapper }),
+            _ => Err(LoadError::UnsupportedMapper { id, name: mapper::mapper_name(id) }),
         }
     }
 
@@ -142,6 +426,114 @@ impl Cart {
     pub fn ppu_write(&mut self, addr: u16, value: u8) {
         self.mapper.ppu_write(&mut self.data, addr, value)
     }
+
+    pub fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        self.mapper.expansion_audio()
+    }
+
+    /// Advance one CPU clock.
+    pub fn clock(&mut self) {
+        self.mapper.clock()
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    /// The disks of an FDS game, `None` for cartridges.
+    pub fn disk(&self) -> Option<&FdsDisk> {
+        self.mapper.disk()
+    }
+
+    pub fn disk_mut(&mut self) -> Option<&mut FdsDisk> {
+        self.mapper.disk_mut()
+    }
+
+    /// Battery-backed PRG RAM, what frontends keep in a `.sav` file.
+    /// `None` for carts without a battery.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        match self.data.sram_enable {
+            true => self.data.prg_ram.get(..self.data.prg_nvram_size).filter(|ram| !ram.is_empty()),
+            false => None,
+        }
+    }
+
+    /// Restore battery-backed PRG RAM saved from [`Cart::battery_ram`].
+    pub fn set_battery_ram(&mut self, ram: &[u8]) -> Result<(), LoadError> {
+        let expected = self.battery_ram().map_or(0, |ram| ram.len());
+        if ram.len() != expected {
+            return Err(LoadError::WrongSaveSize { expected, found: ram.len() });
+        }
+
+        // A trainer in battery RAM is copied in again after, like copiers
+        // did on every power-up.
+        self.data.prg_ram[..expected].copy_from_slice(ram);
+        Self::copy_trainer(&mut self.data);
+        Ok(())
+    }
+}
+
+impl SaveState for Cart {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.data.chr_ram);
+        w.bytes(&self.data.prg_ram);
+        // Boards like MMC1 switch it.
+        w.u8(self.data.mirroring as u8);
+        self.mapper.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes_into(&mut self.data.chr_ram)?;
+        r.bytes_into(&mut self.data.prg_ram)?;
+        self.data.mirroring = match r.u8()? {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::OneScreenLower,
+            3 => Mirroring::OneScreenUpper,
+            _ => return Err(StateError::Mismatch),
+        };
+        self.mapper.load_state(r)
+    }
+}
+
+/// Read exactly `len` bytes, or fail with `truncated(len, bytes found)`.
+fn read_section<T: Read>(
+    src: &mut T,
+    len: usize,
+    truncated: fn(usize, usize) -> LoadError,
+) -> Result<Vec<u8>, LoadError> {
+    let mut buf = Vec::new();
+    src.by_ref().take(len as u64).read_to_end(&mut buf)?;
+
+    match buf.len() == len {
+        true => Ok(buf),
+        false => Err(truncated(len, buf.len())),
+    }
+}
+
+/// NES 2.0 ROM size from the size byte and the upper nibble in byte 9.
+/// An upper nibble of `$F` means the byte is `EEEEEEMM`: 2^E * (MM*2 + 1)
+/// bytes, for sizes that aren't a multiple of `unit`.
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    match msb {
+        0xF => {
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = (lsb & 0b0000_0011) as usize*2 + 1;
+            // Too large to be real, loading will find the file too short.
+            2usize.checked_pow(exponent)
+                .and_then(|size| size.checked_mul(multiplier))
+                .unwrap_or(usize::MAX)
+        },
+        _ => (((msb as usize) << 8) | lsb as usize) * unit,
+    }
+}
+
+/// NES 2.0 RAM sizes are `64 << shift` bytes, with `0` meaning none.
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        _ => 64 << shift,
+    }
 }
 
 fn vec_to_u8_4_arr(v: &Vec<u8>) -> [u8; 4] {
@@ -168,6 +560,7 @@ impl Debug for Cart {
         };
 
         f.debug_struct("Cart")
+            .field("is_nes2", &self.data.is_nes2)
             .field("prg_rom_page_count", &self.data.prg_rom_page_count)
             .field("chr_rom_page_count", &self.data.chr_rom_page_count)
             .field("mirroring", &self.data.mirroring)
@@ -175,14 +568,147 @@ impl Debug for Cart {
             .field("trainer_present", &self.data.trainer_present)
             .field("four_screen_vram_layout", &self.data.four_screen_vram_layout)
             .field("mapper", &self.mapper.id())
+            .field("submapper_id", &self.data.submapper_id)
             .field("is_vs_system", &self.data.is_vs_system)
+            .field("console_type", &self.data.console_type)
             .field("ram_banks", &self.data.ram_banks)
             .field("tv_system", &self.data.tv_system)
+            .field("timing", &self.data.timing)
+            .field("prg_ram_size", &self.data.prg_ram_size)
+            .field("prg_nvram_size", &self.data.prg_nvram_size)
+            .field("chr_ram_size", &self.data.chr_ram_size)
+            .field("chr_nvram_size", &self.data.chr_nvram_size)
+            .field("misc_rom_count", &self.data.misc_rom_count)
+            .field("expansion_device", &self.data.expansion_device)
             .field("trainer", &trainer)
             .field("prg_rom", &format!("PRG ROM: {} bytes", self.data.prg_rom.len()))
             .field("chr_rom", &format!("CHR ROM: {} bytes", self.data.chr_rom.len()))
             .field("extra_bytes", &format!("extra bytes: {} bytes", self.data.extra_bytes.len()))
             .field("chr_ram", &format!("CHR RAM: {} bytes", self.data.chr_ram.len()))
+            .field("prg_ram", &format!("PRG RAM: {} bytes", self.data.prg_ram.len()))
             .finish()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `header` followed by `prg` and `chr` bytes of ROM.
+    fn rom(header: [u8; 16], prg: usize, chr: usize) -> Vec<u8> {
+        let mut rom = header.to_vec();
+        rom.resize(16 + prg + chr, 0);
+        rom
+    }
+
+    fn load(rom: &[u8]) -> Result<Cart, LoadError> {
+        Cart::read_from(&mut &rom[..])
+    }
+
+    #[test]
+    fn odd_rom_sizes_rejected() {
+        // No PRG ROM at all.
+        let no_prg = *b"NES\x1a\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        assert!(matches!(
+            load(&rom(no_prg, 0, 0x2000)),
+            Err(LoadError::UnsupportedRomSize { mapper: 0, prg: 0, chr: 0x2000 })
+        ));
+
+        // NES 2.0, 8KB of PRG ROM as 2^13.
+        let mut header = *b"NES\x1a\x34\x01\x00\x08\x00\x0f\x00\x00\x00\x00\x00\x00";
+        assert!(matches!(
+            load(&rom(header, 0x2000, 0x2000)),
+            Err(LoadError::UnsupportedRomSize { mapper: 0, prg: 0x2000, .. })
+        ));
+
+        // The same on MMC1 is fine, it's mirrored.
+        header[6] = 0x10;
+        let cart = load(&rom(header, 0x2000, 0x2000)).unwrap();
+        assert_eq!(cart.cpu_read(0x8000), cart.cpu_read(0xA000));
+    }
+
+    #[test]
+    fn nrom_chr_ram() {
+        let header = *b"NES\x1a\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let mut cart = load(&rom(header, 0x4000, 0)).unwrap();
+        cart.ppu_write(0x1234, 0x56);
+        assert_eq!(cart.ppu_read(0x1234), 0x56);
+    }
+
+    fn read_header(header: [u8; 16], prg: usize, chr: usize) -> CartData {
+        Cart::read_ines(&mut &rom(header, prg, chr)[..]).unwrap()
+    }
+
+    #[test]
+    fn nes2_rom_sizes() {
+        // Exponent and multiplier: 2^13 * 3 of PRG, 2^12 * 5 of CHR.
+        let header = *b"NES\x1a\x35\x32\x00\x08\x00\xff\x00\x00\x00\x00\x00\x00";
+        let data = read_header(header, 0x6000, 0x5000);
+        assert_eq!((data.prg_rom.len(), data.chr_rom.len()), (0x6000, 0x5000));
+
+        // Upper bits of the bank counts in byte 9.
+        let header = *b"NES\x1a\x02\x01\x00\x08\x00\x10\x00\x00\x00\x00\x00\x00";
+        let data = read_header(header, 2 * 0x4000, 0x101 * 0x2000);
+        assert_eq!((data.prg_rom_page_count, data.chr_rom_page_count), (2, 0x101));
+
+        // Too large for the file.
+        let header = *b"NES\x1a\xfc\x00\x00\x08\x00\x0f\x00\x00\x00\x00\x00\x00";
+        assert!(matches!(
+            Cart::read_ines(&mut &rom(header, 0x4000, 0)[..]),
+            Err(LoadError::TruncatedPrg { .. })
+        ));
+    }
+
+    #[test]
+    fn nes2_fields() {
+        let header = *b"NES\x1a\x01\x00\x12\x48\x31\x00\x97\x07\x01\x00\x00\x05";
+        let data = read_header(header, 0x4000, 0);
+
+        assert_eq!((data.mapper_id, data.submapper_id), (0x141, 3));
+        assert!(data.sram_enable);
+        assert_eq!((data.prg_ram_size, data.prg_nvram_size), (0x2000, 0x8000));
+        assert_eq!((data.chr_ram_size, data.chr_nvram_size), (0x2000, 0));
+        assert_eq!((data.timing, data.tv_system), (Timing::Pal, TVSystem::PAL));
+        assert_eq!(data.expansion_device, 5);
+
+        for (byte_12, timing) in [(2, Timing::MultiRegion), (3, Timing::Dendy)] {
+            let mut header = header;
+            header[12] = byte_12;
+            assert_eq!(read_header(header, 0x4000, 0).timing, timing);
+        }
+    }
+
+    #[test]
+    fn junk_header_ignored() {
+        // "DiskDude!" from byte 7, over an MMC1 ROM with a battery.
+        let header = *b"NES\x1a\x01\x01\x12DiskDude!";
+        let data = read_header(header, 0x4000, 0x2000);
+
+        assert!(!data.is_nes2);
+        assert_eq!(data.mapper_id, 1);
+        assert_eq!(data.console_type, ConsoleType::Nes);
+        assert_eq!(data.timing, Timing::Ntsc);
+        assert_eq!((data.prg_ram_size, data.prg_nvram_size), (0, 0x2000));
+
+        // Zeros in bytes 12-15 leave it alone.
+        let header = *b"NES\x1a\x01\x01\x12\x10\x00\x01\x00\x00\x00\x00\x00\x00";
+        let data = read_header(header, 0x4000, 0x2000);
+        assert_eq!((data.mapper_id, data.timing), (0x11, Timing::Pal));
+    }
+
+    #[test]
+    fn trainer_kept_over_battery_save() {
+        // NROM with a battery and a trainer.
+        let header = *b"NES\x1a\x01\x01\x06\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let mut rom = header.to_vec();
+        rom.extend_from_slice(&[0xEA; 512]);
+        rom.resize(16 + 512 + 0x4000 + 0x2000, 0);
+
+        let mut cart = load(&rom).unwrap();
+        assert_eq!(cart.cpu_read(0x7000), 0xEA);
+
+        cart.set_battery_ram(&[0x55; 0x2000]).unwrap();
+        assert_eq!(cart.cpu_read(0x6000), 0x55);
+        assert_eq!((cart.cpu_read(0x7000), cart.cpu_read(0x71FF)), (0xEA, 0xEA));
+        assert_eq!(cart.cpu_read(0x7200), 0x55);
+    }
+}
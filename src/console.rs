@@ -1,30 +1,78 @@
-use std::{cell::RefCell, fmt::Display, rc::Rc};
+use std::{cell::Ref, fmt::Display, io, path::Path};
 
-use crate::{Bus, Cart, Cpu, Ppu};
+use crate::movie::state_hash;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
+use crate::{Apu, AudioChannel, Bus, Button, Cart, Cpu, DeviceInput, InputDevice, Movie, MovieFrame, Palette, Ppu, WavFormat};
 
+/// The whole machine. The bus owns the PPU, APU and cart, so a console is
+/// `Send` and can run on a thread of its own.
 #[derive(Debug)]
-pub struct Console<'a> {
-    pub cpu: Cpu<'a>,
-    pub bus: Bus<'a>,
-    pub ppu: Rc<RefCell<Ppu>>,
+pub struct Console {
+    pub cpu: Cpu,
+    pub bus: Bus,
+    movie: Option<MovieState>,
 }
 
-impl<'a> Console<'a> {
+#[derive(Debug)]
+enum MovieState {
+    /// `next` collects input set during this frame, applied and appended at
+    /// the start of the next one.
+    Recording { movie: Movie, next: MovieFrame },
+    /// `frame` is the index of the next frame to apply.
+    Playing { movie: Movie, frame: usize },
+}
+
+impl Console {
     pub fn new() -> Self {
-        let cpu = Cpu::new();
-        let ppu = RefCell::new(Ppu::new());
-        let ppu = Rc::new(ppu);
-        let bus = Bus::new(ppu.clone());
+        Self { cpu: Cpu::new(), bus: Bus::new(), movie: None }
+    }
+
+    /// A console using `palette` instead of loading the one in
+    /// `resources/`.
+    pub fn with_palette(palette: Palette) -> Self {
+        Self { cpu: Cpu::new(), bus: Bus::with_ppu(Ppu::with_palette(palette)), movie: None }
+    }
+
+    pub fn ppu(&self) -> Ref<'_, Ppu> {
+        self.bus.ppu()
+    }
+
+    pub fn apu(&self) -> Ref<'_, Apu> {
+        self.bus.apu()
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        self.bus.apu_mut()
+    }
+
+    /// Put `cart` in the slot, taking out the one that was in, if any.
+    pub fn insert_cart(&mut self, cart: Box<Cart>) -> Option<Box<Cart>> {
+        self.bus.attach_cart(cart)
+    }
+
+    /// Take the cart out, e.g. to load another ROM into the same console.
+    /// Without one, reads from cartridge space return `0`.
+    pub fn eject_cart(&mut self) -> Option<Box<Cart>> {
+        self.bus.detach_cart()
+    }
 
-        Self { cpu, ppu, bus }
+    pub fn cart(&self) -> Option<&Cart> {
+        self.bus.cart()
     }
 
-    pub fn insert_cart(&mut self, cart: &'a mut Cart) {
-        self.bus.attach_cart(cart);
+    /// The inserted cart, e.g. to change FDS disk sides.
+    pub fn cart_mut(&mut self) -> Option<&mut Cart> {
+        self.bus.cart_mut()
     }
 
+    /// Press the reset button. While recording a movie the reset happens at
+    /// the start of the next frame, and during playback it's ignored.
     pub fn reset(&mut self) {
-        self.cpu.reset(&mut self.bus);
+        match &mut self.movie {
+            Some(MovieState::Recording { next, .. }) => next.commands |= crate::MOVIE_SOFT_RESET,
+            Some(MovieState::Playing { .. }) => {},
+            None => self.cpu.reset(&mut self.bus),
+        }
     }
 
     pub fn reset_to(&mut self, offset: u16) {
@@ -32,39 +80,321 @@ impl<'a> Console<'a> {
     }
 
     pub fn step(&mut self) {
+        self.clock();
+        self.cpu.step(&mut self.bus);
+    }
+
+    /// Everything but the CPU for one CPU clock.
+    fn clock(&mut self) {
+        let frame = self.frame_count();
         {
             self.ppu_step();
             self.ppu_step();
             self.ppu_step();
         }
-        self.cpu.step(&mut self.bus);
+        if self.frame_count() != frame {
+            self.next_movie_frame();
+        }
+        self.bus.clock_cart();
+        self.apu_step();
+    }
+
+    /// Run until the next frame starts, i.e. the next vblank.
+    pub fn run_frame(&mut self) {
+        let frame = self.frame_count();
+        while self.frame_count() == frame {
+            self.step();
+        }
     }
 
     fn ppu_step(&mut self) {
-            let mut ppu = (*self.ppu).borrow_mut();
-            ppu.step(&mut self.bus);
+            let ppu = self.bus.ppu_mut();
+            ppu.step();
             if ppu.nmi_signal {
                 self.cpu.nmi_triggered = true;
                 ppu.nmi_signal = false;
             }
     }
 
-    pub fn next(&mut self) {
-        {
-            let mut ppu = (*self.ppu).borrow_mut();
-        
-            for _ in 0..self.cpu.cycles {
-                ppu.step(&mut self.bus);
-                ppu.step(&mut self.bus);
-                ppu.step(&mut self.bus);
+    /// Use `palette` to turn the picture's palette indices into colors,
+    /// e.g. one loaded from a `.pal` file.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.bus.ppu_mut().set_palette(palette);
+    }
+
+    /// Frames started since power-on. A frame starts with vblank.
+    pub fn frame_count(&self) -> u64 {
+        self.bus.ppu().frame_count()
+    }
+
+    /// Hash of the CPU registers, work RAM and picture. Compare across
+    /// runs to check a movie replays identically.
+    pub fn state_hash(&self) -> u64 {
+        let reg = &self.cpu.reg;
+        let p: u8 = (&reg.P).into();
+        let registers = [reg.A, reg.X, reg.Y, reg.S, p, reg.PC as u8, (reg.PC >> 8) as u8];
+        let ram = (0..0x800).map(|addr| self.bus.read(addr));
+        let ppu = self.bus.ppu();
+
+        state_hash(registers.iter().copied().chain(ram).chain(ppu.frame().iter().copied()))
+    }
+
+    /// Snapshot of the machine, to restore with [`Console::load_state`].
+    /// Input devices aren't included, nor expansion audio chips other
+    /// than the FDS's, which only NSF rips use.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.save_parts(&mut w);
+        w.finish()
+    }
+
+    /// Restore a [`Console::save_state`] snapshot taken with the same cart
+    /// inserted. If it doesn't fit, the console is left as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let backup = self.save_state();
+
+        self.read_state(state).inspect_err(|_| {
+            // Can't fail, it was just written with the same layout.
+            let _ = self.read_state(&backup);
+        })
+    }
+
+    fn read_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(state)?;
+        self.load_parts(&mut r)?;
+        r.finish()
+    }
+
+    /// Everything [`Console::save_state`] keeps, for players wrapping a
+    /// console to add their own state to.
+    pub(crate) fn save_parts(&self, w: &mut StateWriter) {
+        self.cpu.save_state(w);
+        self.bus.save_state(w);
+        self.bus.ppu().save_state(w);
+        self.bus.apu().save_state(w);
+    }
+
+    pub(crate) fn load_parts(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.cpu.load_state(r)?;
+        self.bus.load_state(r)?;
+        self.bus.ppu_mut().load_state(r)?;
+        self.bus.apu_mut().load_state(r)
+    }
+
+    /// Start recording input into `movie`, after any frames it already
+    /// has. Recording from power-on means calling this after inserting the
+    /// cart and resetting, before the first step.
+    ///
+    /// While recording, input set through [`Console::set_input`] takes
+    /// effect at the start of the next frame, so playback sees exactly
+    /// what was recorded.
+    pub fn record_movie(&mut self, mut movie: Movie) {
+        let next = MovieFrame {
+            commands: 0,
+            ports: [0, 1, 2].map(|port| self.bus.input(port)),
+        };
+        movie.frames.push(next);
+        self.movie = Some(MovieState::Recording { movie, next });
+    }
+
+    /// Replay `movie` from its first frame. Plug in the devices it was
+    /// recorded with first, e.g. a [`crate::FourScore`] if
+    /// [`Movie::uses_four_score`]. Input set by the frontend is ignored
+    /// until playback is stopped.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.movie = Some(MovieState::Playing { movie, frame: 0 });
+        self.next_movie_frame();
+    }
+
+    /// Stop recording or playback, handing the movie back.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        match self.movie.take()? {
+            MovieState::Recording { movie, .. } => Some(movie),
+            MovieState::Playing { movie, .. } => Some(movie),
+        }
+    }
+
+    pub fn is_recording_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieState::Recording { .. }))
+    }
+
+    /// Whether a movie is playing and hasn't run out of frames.
+    pub fn is_playing_movie(&self) -> bool {
+        match &self.movie {
+            Some(MovieState::Playing { movie, frame }) => *frame <= movie.frames.len(),
+            _ => false,
+        }
+    }
+
+    /// Index of the movie frame being recorded or played.
+    pub fn movie_frame(&self) -> Option<usize> {
+        match &self.movie {
+            Some(MovieState::Recording { movie, .. }) => Some(movie.frames.len() - 1),
+            Some(MovieState::Playing { frame, .. }) => Some(frame.saturating_sub(1)),
+            None => None,
+        }
+    }
+
+    fn next_movie_frame(&mut self) {
+        let frame = match &mut self.movie {
+            Some(MovieState::Recording { movie, next }) => {
+                let frame = *next;
+                next.commands = 0;
+                movie.frames.push(frame);
+                frame
+            },
+            Some(MovieState::Playing { movie, frame }) => {
+                let next = movie.frames.get(*frame).copied();
+                *frame = (*frame + 1).min(movie.frames.len() + 1);
+                match next {
+                    Some(next) => next,
+                    None => return,
+                }
+            },
+            None => return,
+        };
+
+        if (frame.commands & (crate::MOVIE_SOFT_RESET | crate::MOVIE_POWER)) != 0 {
+            self.cpu.reset(&mut self.bus);
+        }
+        for (port, input) in frame.ports.iter().enumerate() {
+            if let Some(input) = *input {
+                self.bus.set_input(port, input);
             }
         }
+    }
+
+    fn apu_step(&mut self) {
+        self.bus.clock_apu();
+        self.cpu.irq_line = self.bus.apu().irq() || self.bus.cart_irq();
+    }
+
+    /// Plug `device` into `port`: [`crate::PORT_1`], [`crate::PORT_2`] or
+    /// [`crate::EXPANSION_PORT`]. `None` unplugs whatever was there. Both
+    /// controller ports start out with a standard controller.
+    pub fn set_device(&mut self, port: usize, device: Option<Box<dyn InputDevice>>) {
+        self.bus.set_device(port, device);
+    }
+
+    pub fn device_name(&self, port: usize) -> Option<&'static str> {
+        self.bus.device_name(port)
+    }
+
+    /// Input last set on the device in `port`, `None` if it's empty.
+    pub fn input(&self, port: usize) -> Option<DeviceInput> {
+        match &self.movie {
+            Some(MovieState::Recording { next, .. }) => next.ports[port].or_else(|| self.bus.input(port)),
+            _ => self.bus.input(port),
+        }
+    }
+
+    /// Update what the player is doing with the device in `port`.
+    /// Frontends call this once per frame.
+    pub fn set_input(&mut self, port: usize, input: DeviceInput) {
+        match &mut self.movie {
+            Some(MovieState::Recording { next, .. }) => next.ports[port] = Some(input),
+            Some(MovieState::Playing { .. }) => {},
+            None => self.bus.set_input(port, input),
+        }
+    }
+
+    /// Button state of the controller in `port`, one bit per [`Button`].
+    /// Zero if there's no standard controller there.
+    pub fn buttons(&self, port: usize) -> u8 {
+        match self.input(port) {
+            Some(DeviceInput::Pad(buttons)) => buttons,
+            _ => 0,
+        }
+    }
+
+    /// Shorthand for setting [`DeviceInput::Pad`] on `port`.
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        self.set_input(port, DeviceInput::Pad(buttons));
+    }
+
+    pub fn set_button(&mut self, port: usize, button: Button, pressed: bool) {
+        let buttons = match pressed {
+            true => self.buttons(port) | button.bit(),
+            false => self.buttons(port) & !button.bit(),
+        };
+        self.set_buttons(port, buttons);
+    }
+
+    /// Output sample rate in Hz. Defaults to 44.1kHz.
+    pub fn audio_sample_rate(&self) -> u32 {
+        self.bus.apu().sample_rate()
+    }
+
+    /// Set the output sample rate in Hz, e.g. 44100 or 48000. Discards any
+    /// buffered audio.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.bus.apu_mut().set_sample_rate(sample_rate);
+    }
+
+    /// Dynamic rate control hook for A/V sync. Frontends nudge `ratio`
+    /// slightly above `1.0` when their audio queue runs low and below `1.0`
+    /// when it fills up, e.g. `1.0 + 0.005 * (1.0 - 2.0 * fill)`.
+    pub fn set_audio_rate_adjust(&mut self, ratio: f64) {
+        self.bus.apu_mut().set_rate_adjust(ratio);
+    }
+
+    /// Number of samples `drain_audio` can currently produce.
+    pub fn audio_samples_available(&mut self) -> usize {
+        self.bus.apu_mut().samples_available()
+    }
+
+    pub fn audio_channel_muted(&self, channel: AudioChannel) -> bool {
+        self.bus.apu().is_muted(channel)
+    }
+
+    /// Leave `channel` out of the mix. Meant for debugging.
+    pub fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.bus.apu_mut().set_muted(channel, muted);
+    }
+
+    /// Pull mono samples in `-1.0..=1.0` at the output sample rate. Returns
+    /// how many samples were written to `out`.
+    pub fn drain_audio(&mut self, out: &mut [f32]) -> usize {
+        self.bus.apu_mut().drain(out)
+    }
+
+    /// Record audio to a WAV file at `path` until `stop_audio_recording`.
+    /// Recording runs independently of `drain_audio`, so headless runs that
+    /// never drain still get the full output. With `per_channel`, each APU
+    /// channel is also written to its own file next to `path`.
+    pub fn start_audio_recording<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        format: WavFormat,
+        per_channel: bool
+    ) -> io::Result<()> {
+        self.bus.apu_mut().start_recording(path.as_ref(), format, per_channel)
+    }
+
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        self.bus.apu_mut().stop_recording()
+    }
+
+    pub fn is_recording_audio(&self) -> bool {
+        self.bus.apu().is_recording()
+    }
+
+    pub fn next(&mut self) {
+        for _ in 0..self.cpu.cycles {
+            self.clock();
+        }
 
         self.cpu.next(&mut self.bus);
     }
 }
 
-impl<'a> Display for Console<'a> {
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Console {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // write!(f, "Bus: {:#?}", self.bus)?;
         // write!(f, ",\n")?;
@@ -76,4 +406,83 @@ impl<'a> Display for Console<'a> {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+// Frontends move the console to an emulation thread. Fail to build if any
+// part of it stops being `Send`.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Console>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts in RAM forever, on an NROM cart.
+    fn console() -> Console {
+        let mut rom = b"NES\x1a\x01\x01".to_vec();
+        rom.resize(16, 0);
+        let mut prg = vec![0u8; 0x4000];
+        // INC $00; INX; JMP $8000; RTI
+        prg[..7].copy_from_slice(&[0xE6, 0x00, 0xE8, 0x4C, 0x00, 0x80, 0x40]);
+        prg[0x3FFA..].copy_from_slice(&[0x06, 0x80, 0x00, 0x80, 0x06, 0x80]);
+        rom.extend_from_slice(&prg);
+        rom.resize(rom.len() + 0x2000, 0);
+
+        let mut console = Console::with_palette(Palette::new());
+        console.insert_cart(Box::new(Cart::read_from(&mut rom.as_slice()).unwrap()));
+        console.reset();
+        console
+    }
+
+    fn run_frames(console: &mut Console, frames: usize) {
+        for _ in 0..frames {
+            console.run_frame();
+        }
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut nes = console();
+        run_frames(&mut nes, 2);
+        let state = nes.save_state();
+        let saved_hash = nes.state_hash();
+
+        run_frames(&mut nes, 3);
+        let later_hash = nes.state_hash();
+        assert_ne!(later_hash, saved_hash);
+
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.state_hash(), saved_hash);
+        assert_eq!(nes.save_state(), state);
+
+        // Runs the same way again.
+        run_frames(&mut nes, 3);
+        assert_eq!(nes.state_hash(), later_hash);
+    }
+
+    #[test]
+    fn bad_state_rolled_back() {
+        let mut nes = console();
+        run_frames(&mut nes, 2);
+        let state = nes.save_state();
+        run_frames(&mut nes, 1);
+        let hash = nes.state_hash();
+        let current = nes.save_state();
+
+        let truncated = &state[..state.len() - 1];
+        assert_eq!(nes.load_state(truncated), Err(StateError::Truncated));
+        assert_eq!(nes.state_hash(), hash);
+        assert_eq!(nes.save_state(), current);
+
+        let mut old_version = state.clone();
+        old_version[4] = old_version[4].wrapping_sub(1);
+        assert!(matches!(nes.load_state(&old_version), Err(StateError::WrongVersion { .. })));
+        assert_eq!(nes.save_state(), current);
+
+        let mut too_long = state;
+        too_long.push(0);
+        assert_eq!(nes.load_state(&too_long), Err(StateError::Mismatch));
+        assert_eq!(nes.state_hash(), hash);
+    }
+}
@@ -3,10 +3,10 @@ use crate::Bus;
 
 use super::{Cpu, InstructionTarget};
 
-type OpFn<'a> = fn(&mut Cpu<'a>, &mut Bus, InstructionTarget)->();
-type AddrFn<'a> = fn(&mut Cpu<'a>, &mut Bus, Instruction)->InstructionTarget;
+type OpFn = fn(&mut Cpu, &mut Bus, InstructionTarget)->();
+type AddrFn = fn(&mut Cpu, &mut Bus, Instruction)->InstructionTarget;
 
-pub(super) fn addr_handler<'a>(op: &Operation) -> AddrFn<'a> {
+pub(super) fn addr_handler(op: &Operation) -> AddrFn {
     match op.addr_mode {
         AddrMode::Accum     => Cpu::Accum,
         AddrMode::Imm       => Cpu::Imm,
@@ -24,7 +24,7 @@ pub(super) fn addr_handler<'a>(op: &Operation) -> AddrFn<'a> {
     }
 }
 
-pub(super) fn op_handler<'a>(op: &Operation) -> OpFn<'a> {
+pub(super) fn op_handler(op: &Operation) -> OpFn {
     match op.mnemonic {
         Mnemonic::BRK => Cpu::BRK,
         Mnemonic::ORA => Cpu::ORA,
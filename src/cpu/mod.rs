@@ -10,9 +10,10 @@ use std::num::Wrapping;
 use jdasm_6502::{disasm_one, ByteSource, Instruction, Operand};
 
 use crate::Bus;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 use self::fn_table::{ addr_handler, op_handler };
 
-impl ByteSource for Bus<'_> {
+impl ByteSource for Bus {
     fn read_byte(&self, offset: u16) -> Result<u8, ()> {
         Ok(self.read(offset))
     }
@@ -33,19 +34,22 @@ enum InstructionTarget {
     MemoryAddress(u16),
 }
 
-pub struct Cpu<'a> {
+pub struct Cpu {
     pub reg: Reg,
     pub cycles: u8,
     pub ops: usize,
     pub extra_cycles_branch: u8,
     pub extra_cycles_page_bounds: u8,
     pub nmi_triggered: bool,
+    /// Level-triggered IRQ input, serviced between instructions while the
+    /// interrupt disable flag is clear.
+    pub irq_line: bool,
 
     pub clock_count: usize,
-    debug_out: Option<Box<&'a mut dyn Write>>,
+    debug_out: Option<Box<dyn Write + Send>>,
 }
 
-impl<'a> Cpu<'a> {
+impl Cpu {
     pub fn new() -> Self {
         Self {
             reg: Default::default(),
@@ -59,11 +63,18 @@ impl<'a> Cpu<'a> {
             extra_cycles_branch: 0,
             extra_cycles_page_bounds: 0,
             nmi_triggered: false,
+            irq_line: false,
         }
     }
 
-    pub fn debug_to(&mut self, d: &'a mut dyn Write) {
-        self.debug_out = Some(Box::new(d));
+    /// Trace every instruction to `out`, nestest log style.
+    pub fn debug_to(&mut self, out: Box<dyn Write + Send>) {
+        self.debug_out = Some(out);
+    }
+
+    /// Stop tracing, handing back the writer.
+    pub fn stop_debug(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.debug_out.take()
     }
 
     pub fn pc_advance(&mut self, bus: &mut Bus) -> u8 {
@@ -80,7 +91,11 @@ impl<'a> Cpu<'a> {
             self.cycles -= 1;
 
             if self.cycles == 0 {
-                self.process_instruction(bus);
+                if self.irq_line && !self.reg.P.interrupt {
+                    self.irq(bus);
+                } else {
+                    self.process_instruction(bus);
+                }
             }
         }
 
@@ -155,11 +170,12 @@ impl<'a> Cpu<'a> {
     fn push_stack(&mut self, bus: &mut Bus, value: u8) {
         let addr = 0x0100 | self.reg.S as u16;
         bus.write(addr, value);
-        self.reg.S -= 1;
+        // The stack pointer wraps around page 1.
+        self.reg.S = self.reg.S.wrapping_sub(1);
     }
 
     fn pop_stack(&mut self, bus: &mut Bus) -> u8 {
-        self.reg.S += 1;
+        self.reg.S = self.reg.S.wrapping_add(1);
         let addr = 0x0100 | self.reg.S as u16;
         bus.read(addr)
     }
@@ -179,6 +195,23 @@ impl<'a> Cpu<'a> {
         self.reg.PC = (pch << 8) | pcl;
     }
 
+    fn irq(&mut self, bus: &mut Bus) {
+        // $FFFE $FFFF
+        let pc: u16 = self.reg.PC;
+        self.push_stack(bus, pc.hi());
+        self.push_stack(bus, pc.lo());
+
+        let mut p = self.reg.P;
+        p.brk = false;
+        self.push_stack(bus, (&p).into());
+        self.reg.P.interrupt = true;
+
+        let pcl = bus.read(0xFFFE) as u16;
+        let pch = bus.read(0xFFFF) as u16;
+        self.reg.PC = (pch << 8) | pcl;
+        self.cycles += 7;
+    }
+
     /// Centralized op target access. All ops can use this to avoid switching
     /// addressing logic based on current instruction's addressing mode.
     fn fetch(&mut self, bus: &mut Bus, target: InstructionTarget) -> u8 {
@@ -200,6 +233,16 @@ impl<'a> Cpu<'a> {
         }
     }
 
+    /// Store for read-modify-write ops. In memory the 6502 writes the
+    /// unmodified value back first, then the result on the next cycle, and
+    /// mappers like MMC1 see both.
+    fn modify(&mut self, old: u8, new: u8, bus: &mut Bus, target: InstructionTarget) {
+        if let InstructionTarget::MemoryAddress(addr) = target {
+            bus.write(addr, old);
+        }
+        self.store(new, bus, target);
+    }
+
     fn branch(&mut self, bus: &mut Bus, target: InstructionTarget) {
         let addr_target = match target {
             InstructionTarget::MemoryAddress(w) => w,
@@ -411,10 +454,10 @@ impl<'a> Cpu<'a> {
 
     /// Shift Left one bit
     fn ASL(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let operand = self.fetch(bus, target);
-        self.reg.P.carry = (operand & 0x80) != 0;
-        let operand = operand << 1;
-        self.store(operand, bus, target);
+        let old = self.fetch(bus, target);
+        self.reg.P.carry = (old & 0x80) != 0;
+        let operand = old << 1;
+        self.modify(old, operand, bus, target);
         self.reg.P.zero = operand == 0;
         self.reg.P.negative = (operand & 0x80) != 0;
     }
@@ -548,10 +591,11 @@ impl<'a> Cpu<'a> {
 
     /// Decrement Memory
     fn DEC(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let m = self.fetch(bus, target).wrapping_sub(1);
+        let old = self.fetch(bus, target);
+        let m = old.wrapping_sub(1);
         self.reg.P.zero = m == 0;
         self.reg.P.negative = (m & 0x80) != 0;
-        self.store(m, bus, target);
+        self.modify(old, m, bus, target);
     }
 
     /// Decrement X Register
@@ -580,10 +624,11 @@ impl<'a> Cpu<'a> {
 
     /// Increment Memory
     fn INC(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let m = self.fetch(bus, target).wrapping_add(1);
+        let old = self.fetch(bus, target);
+        let m = old.wrapping_add(1);
         self.reg.P.zero = m == 0;
         self.reg.P.negative = (m & 0x80) != 0;
-        self.store(m, bus, target);
+        self.modify(old, m, bus, target);
     }
 
     /// Increment X Register
@@ -652,12 +697,12 @@ impl<'a> Cpu<'a> {
 
     /// Logical Shift Right
     fn LSR(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let operand = self.fetch(bus, target);
-        self.reg.P.carry = (operand & 1) == 1;
-        let operand = (operand >> 1) & 0x7F;
+        let old = self.fetch(bus, target);
+        self.reg.P.carry = (old & 1) == 1;
+        let operand = (old >> 1) & 0x7F;
         self.reg.P.zero = operand == 0;
         self.reg.P.negative = (operand & 0x80) != 0;
-        self.store(operand, bus, target);
+        self.modify(old, operand, bus, target);
     }
 
     /// No Operation
@@ -705,24 +750,24 @@ impl<'a> Cpu<'a> {
 
     /// Rotate Left
     fn ROL(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let operand = self.fetch(bus, target);
+        let old = self.fetch(bus, target);
         let old_carry = self.reg.P.carry as u8;
-        self.reg.P.carry = (operand & 0x80) != 0;
-        let operand = (operand << 1) | old_carry;
+        self.reg.P.carry = (old & 0x80) != 0;
+        let operand = (old << 1) | old_carry;
         self.reg.P.zero = operand == 0;
         self.reg.P.negative = (operand & 0x80) != 0;
-        self.store(operand, bus, target);
+        self.modify(old, operand, bus, target);
     }
 
     /// Rotate Right
     fn ROR(&mut self, bus: &mut Bus, target: InstructionTarget) {
-        let operand = self.fetch(bus, target);
+        let old = self.fetch(bus, target);
         let old_carry = self.reg.P.carry as u8;
-        self.reg.P.carry = (operand & 1) == 1;
-        let operand = (operand >> 1) | (old_carry << 7);
+        self.reg.P.carry = (old & 1) == 1;
+        let operand = (old >> 1) | (old_carry << 7);
         self.reg.P.zero = operand == 0;
         self.reg.P.negative = (operand & 0x80) != 0;
-        self.store(operand, bus, target);
+        self.modify(old, operand, bus, target);
 
     }
 
@@ -844,6 +889,41 @@ pub struct Reg {
     pub P: RegStatus,
 }
 
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.reg.A);
+        w.u8(self.reg.X);
+        w.u8(self.reg.Y);
+        w.u16(self.reg.PC);
+        w.u8(self.reg.S);
+        w.u8((&self.reg.P).into());
+        w.u8(self.cycles);
+        w.u64(self.ops as u64);
+        w.u8(self.extra_cycles_branch);
+        w.u8(self.extra_cycles_page_bounds);
+        w.bool(self.nmi_triggered);
+        w.bool(self.irq_line);
+        w.u64(self.clock_count as u64);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.reg.A = r.u8()?;
+        self.reg.X = r.u8()?;
+        self.reg.Y = r.u8()?;
+        self.reg.PC = r.u16()?;
+        self.reg.S = r.u8()?;
+        self.reg.P = r.u8()?.into();
+        self.cycles = r.u8()?;
+        self.ops = r.u64()? as usize;
+        self.extra_cycles_branch = r.u8()?;
+        self.extra_cycles_page_bounds = r.u8()?;
+        self.nmi_triggered = r.bool()?;
+        self.irq_line = r.bool()?;
+        self.clock_count = r.u64()? as usize;
+        Ok(())
+    }
+}
+
 impl Display for Reg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "A:{:02X}", self.A)?;
@@ -904,7 +984,7 @@ impl From<u8> for RegStatus {
     }
 }
 
-impl Debug for Cpu<'_> {
+impl Debug for Cpu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Cpu")
             .field("reg", &self.reg)
@@ -914,7 +994,7 @@ impl Debug for Cpu<'_> {
     }
 }
 
-impl Display for Cpu<'_> {
+impl Display for Cpu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         const W: usize = 4;
         const WL: usize = W + 2;
@@ -0,0 +1,173 @@
+use std::fmt::{self, Display};
+use std::io;
+use std::path::PathBuf;
+
+/// Why a ROM, NSF, palette or battery save couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading failed. The path is known when loading from a file.
+    Io(Option<PathBuf>, io::Error),
+    /// Doesn't start with `NES\x1A` or `UNIF`.
+    BadMagic,
+    TruncatedHeader,
+    TruncatedTrainer { expected: usize, found: usize },
+    TruncatedPrg { expected: usize, found: usize },
+    TruncatedChr { expected: usize, found: usize },
+    TruncatedPalette { expected: usize, found: usize },
+    /// A UNIF chunk, like `PRG0`, longer than what's left of the file.
+    TruncatedChunk { id: String, expected: usize, found: usize },
+    /// A UNIF file without a chunk it needs, like `MAPR`.
+    MissingChunk(&'static str),
+    /// UNIF board name that isn't in the board table.
+    UnsupportedBoard(String),
+    /// Disk images need the FDS BIOS, which isn't given.
+    MissingFdsBios,
+    BadFdsBios { found: usize },
+    BadDiskImage(&'static str),
+    /// Saved disk writes that are corrupt or for another disk.
+    BadDiskDiff,
+    /// Name is `None` for mappers that aren't well known.
+    UnsupportedMapper { id: u16, name: Option<&'static str> },
+    UnsupportedSubmapper { mapper: u16, submapper: u8 },
+    /// PRG or CHR ROM of a size the mapper can't have, in bytes.
+    UnsupportedRomSize { mapper: u16, prg: usize, chr: usize },
+    /// Corrupt or unsupported zip or gzip file.
+    BadArchive(String),
+    /// Corrupt NSF or NSFe file, or neither.
+    BadNsf(String),
+    /// A zip without any `.nes`, `.unf`, `.fds` or `.qd` file.
+    NoRomInArchive,
+    MissingArchiveMember(String),
+    /// Corrupt or unsupported IPS, BPS or UPS patch.
+    BadPatch(PathBuf, String),
+    /// A BPS or UPS patch made for a different ROM.
+    PatchMismatch(PathBuf),
+    /// Battery RAM of a different size than the cart has, `0` without a
+    /// battery.
+    WrongSaveSize { expected: usize, found: usize },
+}
+
+impl LoadError {
+    /// Attach the file being read to an IO error.
+    pub(crate) fn with_path(self, path: PathBuf) -> Self {
+        match self {
+            LoadError::Io(None, e) => LoadError::Io(Some(path), e),
+            e => e,
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(Some(path), e) => write!(f, "{}: {}", path.display(), e),
+            LoadError::Io(None, e) => write!(f, "{}", e),
+            LoadError::BadMagic => write!(f, "not an iNES or UNIF ROM, missing the NES<EOF> or UNIF signature"),
+            LoadError::TruncatedHeader => write!(f, "file is too short for a ROM header"),
+            LoadError::TruncatedTrainer { expected, found } => {
+                write!(f, "trainer is cut short, {} of {} bytes", found, expected)
+            },
+            LoadError::TruncatedPrg { expected, found } => {
+                write!(f, "PRG ROM is cut short, {} of {} bytes", found, expected)
+            },
+            LoadError::TruncatedChr { expected, found } => {
+                write!(f, "CHR ROM is cut short, {} of {} bytes", found, expected)
+            },
+            LoadError::TruncatedPalette { expected, found } => {
+                write!(f, "palette is cut short, {} of {} bytes", found, expected)
+            },
+            LoadError::TruncatedChunk { id, expected, found } => {
+                write!(f, "{} chunk is cut short, {} of {} bytes", id, found, expected)
+            },
+            LoadError::MissingChunk(id) => write!(f, "UNIF file has no {} chunk", id),
+            LoadError::UnsupportedBoard(board) => write!(f, "board {} isn't supported", board),
+            LoadError::MissingFdsBios => write!(f, "disk images need the FDS BIOS, disksys.rom"),
+            LoadError::BadFdsBios { found } => {
+                write!(f, "FDS BIOS should be {} bytes, not {}", crate::FDS_BIOS_SIZE, found)
+            },
+            LoadError::BadDiskImage(problem) => write!(f, "bad disk image: {}", problem),
+            LoadError::BadDiskDiff => write!(f, "saved disk changes are corrupt or for another disk"),
+            LoadError::UnsupportedMapper { id, name: Some(name) } => {
+                write!(f, "mapper {} ({}) isn't supported", id, name)
+            },
+            LoadError::UnsupportedMapper { id, name: None } => {
+                write!(f, "mapper {} isn't supported", id)
+            },
+            LoadError::UnsupportedSubmapper { mapper, submapper } => {
+                write!(f, "submapper {} of mapper {} isn't supported", submapper, mapper)
+            },
+            LoadError::UnsupportedRomSize { mapper, prg, chr } => {
+                write!(f, "mapper {} can't have {} bytes of PRG ROM and {} of CHR ROM", mapper, prg, chr)
+            },
+            LoadError::BadArchive(problem) => write!(f, "can't unpack archive: {}", problem),
+            LoadError::BadNsf(problem) => write!(f, "bad NSF file: {}", problem),
+            LoadError::NoRomInArchive => write!(f, "no ROM file in archive"),
+            LoadError::MissingArchiveMember(name) => write!(f, "no file named {} in archive", name),
+            LoadError::BadPatch(path, problem) => {
+                write!(f, "can't apply patch {}: {}", path.display(), problem)
+            },
+            LoadError::PatchMismatch(path) => {
+                write!(f, "patch {} is for a different ROM", path.display())
+            },
+            LoadError::WrongSaveSize { expected: 0, .. } => write!(f, "this game has no battery save"),
+            LoadError::WrongSaveSize { expected, found } => {
+                write!(f, "battery save is {} bytes, the game has {}", found, expected)
+            },
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(None, e)
+    }
+}
+
+/// Why an `.fm2` movie couldn't be read.
+#[derive(Debug)]
+pub enum MovieError {
+    /// Reading failed. The path is known when reading from a file.
+    Io(Option<PathBuf>, io::Error),
+    /// A header or input line that doesn't parse, numbered from 1.
+    BadLine { line: usize, problem: &'static str },
+    /// No `version 3` header, the only text format there is.
+    BadVersion,
+    /// A movie that can't be replayed, like one starting from a save state.
+    Unsupported(&'static str),
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(Some(path), e) => write!(f, "{}: {}", path.display(), e),
+            MovieError::Io(None, e) => write!(f, "{}", e),
+            MovieError::BadLine { line, problem } => write!(f, "line {}: {}", line, problem),
+            MovieError::BadVersion => write!(f, "not a version 3 fm2 movie"),
+            MovieError::Unsupported(what) => write!(f, "{} aren't supported", what),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MovieError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(None, e)
+    }
+}
@@ -0,0 +1,449 @@
+//! Famicom Disk System disk images, `.fds` and `.qd`.
+//! See: <https://www.nesdev.org/wiki/FDS_disk_format>
+
+use std::fs;
+use std::path::Path;
+
+use crate::mapper::MapperFds;
+use crate::patch;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{checksum, Cart, CartData, ConsoleType, LoadError, Mirroring, TVSystem, Timing};
+
+/// A disk side in `.fds` files: the blocks back to back, without gaps or
+/// CRCs.
+pub const FDS_SIDE_SIZE: usize = 65500;
+
+/// The RAM adapter's BIOS, `disksys.rom`, mapped at `$E000-$FFFF`.
+pub const FDS_BIOS_SIZE: usize = 0x2000;
+
+/// fwNES header, before the sides.
+const FDS_MAGIC: &[u8] = b"FDS\x1A";
+const FDS_HEADER_LEN: usize = 16;
+/// Block 1 of every side.
+const DISK_MAGIC: &[u8] = b"\x01*NINTENDO-HVC*";
+
+/// QuickDisk images keep each block's CRC and pad sides to 64 KiB.
+const QD_SIDE_SIZE: usize = 0x10000;
+
+/// Gaps before the first block and between blocks, in bytes.
+const LEAD_IN: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+/// Start of a block after a gap.
+const GAP_END: u8 = 0x80;
+
+/// How long a disk stays out when changing sides, so the BIOS notices.
+/// About a second of CPU clocks.
+const SWAP_DELAY: u32 = 1_800_000;
+
+/// Whether `data` looks like a disk image rather than a cartridge ROM.
+pub(crate) fn is_disk_image(data: &[u8]) -> bool {
+    data.starts_with(FDS_MAGIC) || data.starts_with(DISK_MAGIC)
+}
+
+/// Load a disk image with the BIOS from `bios` into a cart with the RAM
+/// adapter, disk side A inserted.
+pub(crate) fn read_fds(image: &[u8], bios: Option<&Path>) -> Result<Cart, LoadError> {
+    let disk = FdsDisk::from_image(image)?;
+
+    let bios_path = bios.ok_or(LoadError::MissingFdsBios)?;
+    let bios = fs::read(bios_path)
+        .map_err(|e| LoadError::Io(Some(bios_path.to_owned()), e))?;
+    if bios.len() != FDS_BIOS_SIZE {
+        return Err(LoadError::BadFdsBios { found: bios.len() });
+    }
+
+    let crc32 = checksum::crc32(&[&disk.original]);
+    let sha1 = checksum::sha1(&[&disk.original]);
+
+    let data = CartData {
+        is_nes2: false,
+        prg_rom_page_count: 1,
+        chr_rom_page_count: 0,
+        mirroring: Mirroring::Horizontal,
+        sram_enable: false,
+        trainer_present: false,
+        four_screen_vram_layout: false,
+        mapper_id: 20,
+        submapper_id: 0,
+        is_vs_system: false,
+        console_type: ConsoleType::Nes,
+        ram_banks: 4,
+        tv_system: TVSystem::NTSC,
+        timing: Timing::Ntsc,
+        prg_ram_size: 0x8000,
+        prg_nvram_size: 0,
+        chr_ram_size: 0x2000,
+        chr_nvram_size: 0,
+        misc_rom_count: 0,
+        expansion_device: 0,
+        trainer: None,
+        prg_rom: bios,
+        chr_rom: Vec::new(),
+        extra_bytes: Vec::new(),
+        chr_ram: vec![0u8; 0x2000],
+        prg_ram: vec![0u8; 0x8000],
+        crc32,
+        sha1,
+        header_fixes: Vec::new(),
+        patches: Vec::new(),
+    };
+
+    Ok(Cart { data, mapper: Box::new(MapperFds::new(disk)) })
+}
+
+/// The disks in the drive, and which side is in it. Changes written by
+/// games stay in memory, frontends keep them with [`FdsDisk::diff`].
+pub struct FdsDisk {
+    /// Each side as the drive reads it, with gaps and CRCs.
+    sides: Vec<Vec<u8>>,
+    /// Sides as loaded, in `.fds` layout, to diff changes against.
+    original: Vec<u8>,
+    inserted: Option<usize>,
+    /// Side to insert once the countdown, in CPU clocks, runs out.
+    swap: Option<(usize, u32)>,
+    modified: bool,
+}
+
+impl FdsDisk {
+    fn from_image(image: &[u8]) -> Result<Self, LoadError> {
+        let body = match image.starts_with(FDS_MAGIC) {
+            true => image.get(FDS_HEADER_LEN..).unwrap_or(&[]),
+            false => image,
+        };
+
+        let is_qd = body.len() % QD_SIDE_SIZE == 0 && body.len() % FDS_SIDE_SIZE != 0;
+        let sides: Vec<Vec<u8>> = match is_qd {
+            true => body.chunks(QD_SIDE_SIZE).map(qd_to_fds).collect(),
+            false => body.chunks(FDS_SIDE_SIZE).map(|side| {
+                let mut side = side.to_vec();
+                side.resize(FDS_SIDE_SIZE, 0);
+                side
+            }).collect(),
+        };
+
+        if sides.is_empty() {
+            return Err(LoadError::BadDiskImage("no disk sides"));
+        }
+        if !sides[0].starts_with(DISK_MAGIC) {
+            return Err(LoadError::BadDiskImage("side A has no disk info block"));
+        }
+
+        Ok(Self {
+            sides: sides.iter().map(|side| fds_to_raw(side)).collect(),
+            original: sides.concat(),
+            inserted: Some(0),
+            swap: None,
+            modified: false,
+        })
+    }
+
+    /// Number of disk sides, two per disk.
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// Side in the drive, `None` while it's empty or changing sides.
+    pub fn inserted_side(&self) -> Option<usize> {
+        self.inserted
+    }
+
+    /// Put `side` in the drive. A different side goes in after the drive
+    /// has been empty for a moment, like a player flipping the disk.
+    pub fn insert_side(&mut self, side: usize) {
+        if side >= self.sides.len() || self.inserted == Some(side) {
+            return;
+        }
+
+        match self.inserted.take() {
+            Some(_) => self.swap = Some((side, SWAP_DELAY)),
+            None => {
+                self.inserted = Some(side);
+                self.swap = None;
+            },
+        }
+    }
+
+    pub fn eject(&mut self) {
+        self.inserted = None;
+        self.swap = None;
+    }
+
+    /// Whether a game wrote to the disk since it was loaded, or since the
+    /// last [`FdsDisk::apply_diff`] or [`FdsDisk::mark_saved`].
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+
+    /// The sides as they are now, in `.fds` layout without a header.
+    pub fn image(&self) -> Vec<u8> {
+        self.sides.iter().flat_map(|side| raw_to_fds(side)).collect()
+    }
+
+    /// Everything written to the disk, as an IPS patch against the image
+    /// it was loaded from.
+    pub fn diff(&self) -> Vec<u8> {
+        patch::make_diff(&self.original, &self.image())
+    }
+
+    /// Restore writes saved with [`FdsDisk::diff`].
+    pub fn apply_diff(&mut self, diff: &[u8]) -> Result<(), LoadError> {
+        let image = patch::apply_diff(&self.original, diff)
+            .filter(|image| image.len() == self.original.len())
+            .ok_or(LoadError::BadDiskDiff)?;
+
+        self.sides = image.chunks(FDS_SIDE_SIZE).map(fds_to_raw).collect();
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Advance one CPU clock, for changing sides.
+    pub(crate) fn clock(&mut self) {
+        self.swap = match self.swap {
+            Some((side, 0)) => {
+                self.inserted = Some(side);
+                None
+            },
+            Some((side, delay)) => Some((side, delay - 1)),
+            None => None,
+        };
+    }
+
+    /// Length of the inserted side as the drive reads it.
+    pub(crate) fn len(&self) -> usize {
+        self.inserted.map_or(0, |side| self.sides[side].len())
+    }
+
+    pub(crate) fn read(&self, pos: usize) -> u8 {
+        self.inserted.and_then(|side| self.sides[side].get(pos).copied()).unwrap_or(0)
+    }
+
+    pub(crate) fn write(&mut self, pos: usize, value: u8) {
+        let sides = &mut self.sides;
+        if let Some(byte) = self.inserted.and_then(|side| sides[side].get_mut(pos)) {
+            self.modified |= *byte != value;
+            *byte = value;
+        }
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        for side in &self.sides {
+            w.bytes(side);
+        }
+        w.u8(self.inserted.map_or(0xff, |side| side as u8));
+        let (side, delay) = self.swap.unwrap_or((0xff, 0));
+        w.u8(side as u8);
+        w.u32(delay);
+    }
+
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        // Only a state with different disk contents needs saving again.
+        for side in &mut self.sides {
+            let mut loaded = vec![0u8; side.len()];
+            r.bytes_into(&mut loaded)?;
+            self.modified |= loaded != *side;
+            *side = loaded;
+        }
+        let count = self.sides.len();
+        let side = |n: u8| Some(n as usize).filter(|&n| n < count);
+        self.inserted = side(r.u8()?);
+        let swap = side(r.u8()?);
+        let delay = r.u32()?;
+        self.swap = swap.map(|side| (side, delay));
+        Ok(())
+    }
+}
+
+/// Length of the block starting at `block`, `None` if it isn't a block.
+/// File data blocks take their size from the header block before them.
+fn block_len(block: &[u8], file_size: usize) -> Option<usize> {
+    match block.first()? {
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+/// Blocks of a side, each followed by `crc_len` bytes of CRC.
+fn blocks(side: &[u8], crc_len: usize) -> Vec<&[u8]> {
+    let mut blocks = Vec::new();
+    let mut file_size = 0;
+    let mut at = 0;
+
+    while let Some(len) = side.get(at..).and_then(|rest| block_len(rest, file_size)) {
+        let Some(block) = side.get(at..at + len) else { break };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+        blocks.push(block);
+        at += len + crc_len;
+    }
+
+    blocks
+}
+
+/// `.qd` side to `.fds` layout, dropping the CRCs.
+fn qd_to_fds(side: &[u8]) -> Vec<u8> {
+    let mut fds = blocks(side, 2).concat();
+    fds.resize(FDS_SIDE_SIZE, 0);
+    fds
+}
+
+/// `.fds` side to what the drive reads: a lead-in, then each block after
+/// a gap end mark and followed by a CRC and a gap. CRCs are left zero,
+/// nothing checks them. The free space at the end stays free.
+fn fds_to_raw(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0u8; LEAD_IN];
+    let mut used = 0;
+
+    for block in blocks(side, 0) {
+        raw.push(GAP_END);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&[0, 0]);
+        raw.resize(raw.len() + BLOCK_GAP, 0);
+        used += block.len();
+    }
+
+    raw.resize(raw.len() + FDS_SIDE_SIZE.saturating_sub(used), 0);
+    raw
+}
+
+/// Back from what the drive reads to `.fds` layout.
+fn raw_to_fds(raw: &[u8]) -> Vec<u8> {
+    let mut fds = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut file_size = 0;
+    let mut at = 0;
+
+    loop {
+        // Skip the gap up to its end mark.
+        match raw.get(at..).and_then(|rest| rest.iter().position(|&b| b != 0)) {
+            Some(gap) if raw[at + gap] == GAP_END => at += gap + 1,
+            _ => break,
+        }
+
+        let Some(len) = block_len(&raw[at..], file_size) else { break };
+        let Some(block) = raw.get(at..at + len) else { break };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+        fds.extend_from_slice(block);
+        at += len + 2;
+    }
+
+    fds.resize(FDS_SIDE_SIZE, 0);
+    fds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One side with a single file of `data`, in `.fds` layout.
+    fn side(data: &[u8]) -> Vec<u8> {
+        let mut side = DISK_MAGIC.to_vec();
+        side.resize(56, 0);
+        side.extend_from_slice(&[2, 1]);
+        let mut header = [0u8; 16];
+        header[0] = 3;
+        header[3..11].copy_from_slice(b"TESTFILE");
+        header[13..15].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        side.extend_from_slice(&header);
+        side.push(4);
+        side.extend_from_slice(data);
+        side.resize(FDS_SIDE_SIZE, 0);
+        side
+    }
+
+    /// Where the file data starts in the drive's view of the side: after
+    /// three blocks with their gap end marks, CRCs and gaps, then the
+    /// fourth's gap end mark and block type.
+    const DATA_AT: usize = LEAD_IN + 3 * (1 + 2 + BLOCK_GAP) + 56 + 2 + 16 + 2;
+
+    #[test]
+    fn fds_and_raw() {
+        let sides = [side(b"side A"), side(b"side B")].concat();
+        let mut image = FDS_MAGIC.to_vec();
+        image.resize(FDS_HEADER_LEN, 0);
+        image.extend_from_slice(&sides);
+
+        let disk = FdsDisk::from_image(&image).unwrap();
+        assert_eq!(disk.side_count(), 2);
+        assert_eq!(disk.image(), sides);
+        assert_eq!(disk.read(LEAD_IN), GAP_END);
+        assert_eq!(disk.read(DATA_AT), b's');
+
+        // Without the header.
+        assert_eq!(FdsDisk::from_image(&sides).unwrap().image(), sides);
+    }
+
+    #[test]
+    fn qd_side() {
+        let fds = side(b"data");
+        let mut qd = Vec::new();
+        for block in blocks(&fds, 0) {
+            qd.extend_from_slice(block);
+            qd.extend_from_slice(&[0xAB, 0xCD]);
+        }
+        qd.resize(QD_SIDE_SIZE, 0);
+
+        assert_eq!(FdsDisk::from_image(&qd).unwrap().image(), fds);
+    }
+
+    #[test]
+    fn bad_images() {
+        assert!(matches!(FdsDisk::from_image(FDS_MAGIC), Err(LoadError::BadDiskImage(_))));
+        assert!(matches!(
+            FdsDisk::from_image(&[0; FDS_SIDE_SIZE]),
+            Err(LoadError::BadDiskImage(_))
+        ));
+    }
+
+    #[test]
+    fn diff_round_trip() {
+        let image = side(b"before");
+        let mut disk = FdsDisk::from_image(&image).unwrap();
+
+        // Writing what's there already isn't a change.
+        disk.write(DATA_AT, b'b');
+        assert!(!disk.is_modified());
+        disk.write(DATA_AT, b'B');
+        assert!(disk.is_modified());
+
+        let diff = disk.diff();
+        let mut restored = FdsDisk::from_image(&image).unwrap();
+        restored.apply_diff(&diff).unwrap();
+        assert!(!restored.is_modified());
+        assert_eq!(restored.image(), disk.image());
+        assert_eq!(&restored.image()[75..81], b"Before");
+
+        assert!(matches!(restored.apply_diff(b"junk"), Err(LoadError::BadDiskDiff)));
+    }
+
+    #[test]
+    fn state_only_modifies_on_change() {
+        let mut disk = FdsDisk::from_image(&side(b"before")).unwrap();
+        let saved = {
+            let mut w = StateWriter::new();
+            disk.save_state(&mut w);
+            w.finish()
+        };
+        let load = |disk: &mut FdsDisk| {
+            let mut r = StateReader::new(&saved).unwrap();
+            disk.load_state(&mut r).unwrap();
+        };
+
+        load(&mut disk);
+        assert!(!disk.is_modified());
+
+        disk.write(DATA_AT, b'B');
+        disk.mark_saved();
+        load(&mut disk);
+        assert!(disk.is_modified());
+        assert_eq!(disk.read(DATA_AT), b'b');
+    }
+}
@@ -0,0 +1,72 @@
+use super::{DeviceInput, InputDevice};
+use crate::Ppu;
+
+/// Taito Arkanoid "Vaus" controller: a knob read back as 8 serial bits, MSB
+/// first and inverted, plus a button. See: <https://www.nesdev.org/wiki/Arkanoid_controller>
+///
+/// The NES version plugs into a controller port and uses bits 3-4. The
+/// Famicom version plugs into the expansion port, with the button on bit 1
+/// of `$4016` and the knob on bit 1 of `$4017`.
+#[derive(Debug)]
+pub struct ArkanoidPaddle {
+    famicom: bool,
+    position: u8,
+    button: bool,
+    shift: u8,
+}
+
+impl ArkanoidPaddle {
+    /// Games read roughly `$62-$F2` across the knob's range.
+    const CENTER: u8 = 0xaa;
+
+    pub fn nes() -> Self {
+        Self::new(false)
+    }
+
+    pub fn famicom() -> Self {
+        Self::new(true)
+    }
+
+    fn new(famicom: bool) -> Self {
+        Self { famicom, position: Self::CENTER, button: false, shift: 0 }
+    }
+
+    fn serial(&mut self) -> u8 {
+        let bit = (!self.shift >> 7) & 1;
+        self.shift <<= 1;
+        bit
+    }
+}
+
+impl InputDevice for ArkanoidPaddle {
+    fn write(&mut self, value: u8) {
+        if (value & 0b0000_0001) != 0 {
+            self.shift = self.position;
+        }
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        let button = self.button as u8;
+
+        match (self.famicom, addr) {
+            (false, _) => (button << 4) | (self.serial() << 3),
+            (true, 0x4016) => button << 1,
+            (true, _) => self.serial() << 1,
+        }
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Paddle { position: self.position, button: self.button }
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::Paddle { position, button } = input {
+            self.position = position;
+            self.button = button;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Arkanoid Controller"
+    }
+}
@@ -0,0 +1,96 @@
+use super::{shift_out, DeviceInput, InputDevice};
+use crate::Ppu;
+
+/// Standard controller buttons, in the order they're reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button { A, B, Select, Start, Up, Down, Left, Right }
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    /// Bit in a button state byte, as passed to [`crate::Console::set_buttons`].
+    pub fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// Standard NES controller, a 4021 shift register behind `$4016`/`$4017`.
+/// See: <https://www.nesdev.org/wiki/Standard_controller>
+///
+/// Writing 1 to bit 0 of `$4016` strobes it, continuously latching the
+/// buttons. Once the strobe is cleared, each read shifts out one button,
+/// A first, then 1s after the eighth read.
+#[derive(Debug, Default)]
+pub struct Controller {
+    /// One bit per [`Button`].
+    buttons: u8,
+    shift: u32,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = buttons as u32;
+        }
+    }
+
+    /// Strobe input, `---- ---S`.
+    pub(super) fn strobe(&mut self, value: u8) {
+        self.strobe = (value & 0b0000_0001) != 0;
+        if self.strobe {
+            self.shift = self.buttons as u32;
+        }
+    }
+
+    /// Serial data bit.
+    pub(super) fn serial(&mut self) -> u8 {
+        match self.strobe {
+            true => self.buttons & 1,
+            false => shift_out(&mut self.shift, 8),
+        }
+    }
+}
+
+impl InputDevice for Controller {
+    fn write(&mut self, value: u8) {
+        self.strobe(value);
+    }
+
+    /// Data on bit 0.
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        self.serial()
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Pad(self.buttons)
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::Pad(buttons) = input {
+            self.set_buttons(buttons);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Standard Controller"
+    }
+}
@@ -0,0 +1,91 @@
+use super::{DeviceInput, InputDevice};
+use crate::Ppu;
+
+/// Key names by row, first column's 4 keys then the second's, in the order
+/// they appear on bits 1-4 of `$4017`.
+pub const KEY_MATRIX: [[&str; 8]; 9] = [
+    ["]", "[", "RETURN", "F8", "STOP", "YEN", "RSHIFT", "KANA"],
+    [";", ":", "@", "F7", "^", "-", "/", "_"],
+    ["K", "L", "O", "F6", "0", "P", ",", "."],
+    ["J", "U", "I", "F5", "8", "9", "N", "M"],
+    ["H", "G", "Y", "F4", "6", "7", "V", "B"],
+    ["D", "R", "T", "F3", "4", "5", "C", "F"],
+    ["A", "S", "W", "F2", "3", "E", "Z", "X"],
+    ["CTR", "Q", "ESC", "F1", "2", "1", "GRPH", "LSHIFT"],
+    ["LEFT", "RIGHT", "UP", "CLR", "INS", "DEL", "SPACE", "DOWN"],
+];
+
+/// Family BASIC keyboard (HVC-007) on the expansion port.
+/// See: <https://www.nesdev.org/wiki/Family_BASIC_Keyboard>
+///
+/// `$4016` writes select a row and column of the key matrix; reads of
+/// `$4017` return its 4 keys on bits 1-4, 0 when pressed. Writing the
+/// column bit from 1 to 0 moves to the next row.
+#[derive(Debug, Default)]
+pub struct FamilyKeyboard {
+    /// Pressed keys, see [`DeviceInput::Keyboard`].
+    rows: [u8; 9],
+    enabled: bool,
+    row: usize,
+    column: u8,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Row and bit within [`DeviceInput::Keyboard`] for a name from
+    /// [`KEY_MATRIX`].
+    pub fn key_position(name: &str) -> Option<(usize, u8)> {
+        KEY_MATRIX.iter().enumerate().find_map(|(row, keys)| {
+            keys.iter()
+                .position(|k| k.eq_ignore_ascii_case(name))
+                .map(|bit| (row, bit as u8))
+        })
+    }
+}
+
+impl InputDevice for FamilyKeyboard {
+    /// `---- -KCR`: keyboard enable, column, reset to the first row.
+    fn write(&mut self, value: u8) {
+        let column = (value >> 1) & 1;
+
+        if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        if (value & 0b0000_0001) != 0 {
+            self.row = 0;
+        }
+
+        self.column = column;
+        self.enabled = (value & 0b0000_0100) != 0;
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        if addr != 0x4017 || !self.enabled {
+            return 0;
+        }
+
+        let pressed = match self.rows.get(self.row) {
+            Some(keys) => (keys >> (4 * self.column)) & 0x0f,
+            None => 0,
+        };
+
+        (!pressed & 0x0f) << 1
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Keyboard(self.rows)
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::Keyboard(rows) = input {
+            self.rows = rows;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Family BASIC Keyboard"
+    }
+}
@@ -0,0 +1,127 @@
+use super::{Controller, DeviceInput, InputDevice};
+use crate::Ppu;
+
+/// One port's half of the NES Four Score. Reads shift out the first pad,
+/// then the second, then an 8-bit signature identifying the port.
+/// See: <https://www.nesdev.org/wiki/Four_Score>
+///
+/// Plug `FourScore::port_1()` into port 1 for players 1 and 3, and
+/// `FourScore::port_2()` into port 2 for players 2 and 4.
+#[derive(Debug)]
+pub struct FourScore {
+    pads: [Controller; 2],
+    signature: u8,
+    /// Bits shifted out since the last strobe.
+    count: u8,
+    strobe: bool,
+}
+
+impl FourScore {
+    pub fn port_1() -> Self {
+        Self::new(0b0000_1000)
+    }
+
+    pub fn port_2() -> Self {
+        Self::new(0b0000_0100)
+    }
+
+    fn new(signature: u8) -> Self {
+        Self {
+            pads: Default::default(),
+            signature,
+            count: 0,
+            strobe: false,
+        }
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, value: u8) {
+        self.strobe = (value & 0b0000_0001) != 0;
+        if self.strobe {
+            self.count = 0;
+        }
+
+        for pad in self.pads.iter_mut() {
+            pad.strobe(value);
+        }
+    }
+
+    /// Data on bit 0.
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            return self.pads[0].serial();
+        }
+
+        let bit = match self.count {
+            0..=7 => self.pads[0].serial(),
+            8..=15 => self.pads[1].serial(),
+            16..=23 => (self.signature >> (self.count - 16)) & 1,
+            _ => 1,
+        };
+
+        self.count = self.count.saturating_add(1);
+        bit
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Pads(self.pads[0].buttons(), self.pads[1].buttons())
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        match input {
+            DeviceInput::Pads(first, second) => {
+                self.pads[0].set_buttons(first);
+                self.pads[1].set_buttons(second);
+            },
+            DeviceInput::Pad(first) => self.pads[0].set_buttons(first),
+            _ => {},
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Four Score"
+    }
+}
+
+/// Famicom 4-player adapter on the expansion port, in its simple mode:
+/// player 3 on bit 1 of `$4016` and player 4 on bit 1 of `$4017`. Players
+/// 1 and 2 are the regular controllers.
+#[derive(Debug, Default)]
+pub struct FamicomFourPlayer {
+    pads: [Controller; 2],
+}
+
+impl FamicomFourPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl InputDevice for FamicomFourPlayer {
+    fn write(&mut self, value: u8) {
+        for pad in self.pads.iter_mut() {
+            pad.strobe(value);
+        }
+    }
+
+    fn read(&mut self, addr: u16, _ppu: &Ppu) -> u8 {
+        let pad = &mut self.pads[(addr & 1) as usize];
+        pad.serial() << 1
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Pads(self.pads[0].buttons(), self.pads[1].buttons())
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::Pads(third, fourth) = input {
+            self.pads[0].set_buttons(third);
+            self.pads[1].set_buttons(fourth);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Famicom 4-Player Adapter"
+    }
+}
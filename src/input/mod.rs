@@ -0,0 +1,74 @@
+mod arkanoid;
+mod controller;
+mod family_keyboard;
+mod four_score;
+mod power_pad;
+mod zapper;
+
+pub use self::arkanoid::ArkanoidPaddle;
+pub use self::controller::{Button, Controller};
+pub use self::family_keyboard::{FamilyKeyboard, KEY_MATRIX};
+pub use self::four_score::{FamicomFourPlayer, FourScore};
+pub use self::power_pad::PowerPad;
+pub use self::zapper::Zapper;
+
+use crate::Ppu;
+
+/// Controller port 1, read through `$4016`.
+pub const PORT_1: usize = 0;
+/// Controller port 2, read through `$4017`.
+pub const PORT_2: usize = 1;
+/// Famicom expansion port. Devices here drive bit 1 of `$4016` and bits 1-4
+/// of `$4017`.
+pub const EXPANSION_PORT: usize = 2;
+
+/// What the player is doing with a device. Frontends set it once per frame
+/// with [`crate::Console::set_input`]; devices ignore input meant for a
+/// different kind of device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceInput {
+    /// Standard controller, one bit per [`Button`].
+    Pad(u8),
+    /// Two standard controllers on a multitap, e.g. players 1 and 3 on the
+    /// first Four Score port.
+    Pads(u8, u8),
+    /// Screen pixel the Zapper is aimed at, `None` when pointed away.
+    Zapper { aim: Option<(u8, u8)>, trigger: bool },
+    /// Arkanoid controller knob position and button.
+    Paddle { position: u8, button: bool },
+    /// Power Pad buttons 1-12, as bits 0-11.
+    PowerPad(u16),
+    /// Family BASIC keyboard matrix, one byte per row. The low nibble holds
+    /// the first column's keys, the high nibble the second's.
+    Keyboard([u8; 9]),
+}
+
+/// Something plugged into a controller port or the expansion port.
+///
+/// Every device sees `$4016` writes. Reads of `$4016`/`$4017` combine the
+/// bits driven by whatever is plugged in, over open bus.
+///
+/// Devices are `Send` so the console can run on its own thread.
+pub trait InputDevice: Send {
+    /// `$4016` write: `---- -OOO`. Bit 0 strobes the controllers, bits 1-2
+    /// are extra outputs on the expansion port.
+    fn write(&mut self, value: u8);
+
+    /// Data bits driven for a read of `addr`, `$4016` or `$4017`. Only bits
+    /// 0-4 are connected. Devices that need to see the screen get the PPU.
+    fn read(&mut self, addr: u16, ppu: &Ppu) -> u8;
+
+    fn input(&self) -> DeviceInput;
+
+    fn set_input(&mut self, input: DeviceInput);
+
+    fn name(&self) -> &'static str;
+}
+
+/// Shift out bits LSB first, then 1s once they run out, like the 4021
+/// shift registers in most devices.
+fn shift_out(shift: &mut u32, width: u32) -> u8 {
+    let bit = (*shift & 1) as u8;
+    *shift = (*shift >> 1) | (1 << (width - 1));
+    bit
+}
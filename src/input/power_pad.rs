@@ -0,0 +1,69 @@
+use super::{shift_out, DeviceInput, InputDevice};
+use crate::Ppu;
+
+/// Order buttons are shifted out on bit 3.
+const D3_ORDER: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+/// Order buttons are shifted out on bit 4.
+const D4_ORDER: [u8; 4] = [4, 3, 12, 8];
+
+/// Bandai/Nintendo Power Pad floor mat with 12 buttons, on a controller
+/// port. Two shift registers report it on bits 3 and 4.
+/// See: <https://www.nesdev.org/wiki/Power_Pad>
+#[derive(Debug, Default)]
+pub struct PowerPad {
+    /// Button `n` is bit `n - 1`.
+    buttons: u16,
+    shift_d3: u32,
+    shift_d4: u32,
+    strobe: bool,
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn latch(&mut self) {
+        let buttons = self.buttons;
+        let gather = |order: &[u8]| order.iter()
+            .enumerate()
+            .map(|(i, n)| (((buttons >> (n - 1)) & 1) as u32) << i)
+            .sum::<u32>();
+
+        self.shift_d3 = gather(&D3_ORDER);
+        self.shift_d4 = gather(&D4_ORDER) | 0xf0;
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, value: u8) {
+        self.strobe = (value & 0b0000_0001) != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, _addr: u16, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+
+        let d3 = shift_out(&mut self.shift_d3, 8);
+        let d4 = shift_out(&mut self.shift_d4, 8);
+        (d4 << 4) | (d3 << 3)
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::PowerPad(self.buttons)
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::PowerPad(buttons) = input {
+            self.buttons = buttons & 0x0fff;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Power Pad"
+    }
+}
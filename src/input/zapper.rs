@@ -0,0 +1,72 @@
+use super::{DeviceInput, InputDevice};
+use crate::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Scanlines the photodiode keeps reporting light after the beam passes.
+const LIGHT_SCANLINES: usize = 20;
+
+/// NES Zapper light gun. See: <https://www.nesdev.org/wiki/Zapper>
+///
+/// Light is sensed when the beam has recently drawn a bright pixel where the
+/// gun is aimed. Brightness is judged from the palette index alone, so it
+/// doesn't depend on the palette in use.
+#[derive(Debug, Default)]
+pub struct Zapper {
+    aim: Option<(u8, u8)>,
+    trigger: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn senses_light(&self, ppu: &Ppu) -> bool {
+        let (x, y) = match self.aim {
+            Some((x, y)) => (x as usize, y as usize),
+            None => return false,
+        };
+
+        if y >= SCREEN_HEIGHT {
+            return false;
+        }
+
+        let scanline = ppu.scanline();
+        let drawn = scanline > y || (scanline == y && ppu.scanline_cycle() > x + 1);
+        if !drawn || scanline >= y + LIGHT_SCANLINES {
+            return false;
+        }
+
+        is_bright(ppu.frame()[y * SCREEN_WIDTH + x])
+    }
+}
+
+/// Light grays and the pastel rows, minus the blacks in columns `$D-$F`.
+fn is_bright(color: u8) -> bool {
+    (color & 0x30) >= 0x20 && (color & 0x0f) < 0x0d
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _value: u8) {}
+
+    /// `---T L---`: trigger held, light *not* sensed.
+    fn read(&mut self, _addr: u16, ppu: &Ppu) -> u8 {
+        let light = (!self.senses_light(ppu) as u8) << 3;
+        let trigger = (self.trigger as u8) << 4;
+        trigger | light
+    }
+
+    fn input(&self) -> DeviceInput {
+        DeviceInput::Zapper { aim: self.aim, trigger: self.trigger }
+    }
+
+    fn set_input(&mut self, input: DeviceInput) {
+        if let DeviceInput::Zapper { aim, trigger } = input {
+            self.aim = aim;
+            self.trigger = trigger;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "Zapper"
+    }
+}
@@ -1,14 +1,37 @@
+mod apu;
+mod archive;
 mod bus;
 mod console;
 mod constant;
 mod cpu;
+mod error;
+mod fds;
 mod cart;
+mod checksum;
+mod input;
 mod mapper;
+mod movie;
+mod nsf;
 mod ppu;
 mod palette;
+mod patch;
+mod romdb;
+mod state;
+mod unif;
+mod wav;
 
+pub use self::apu::*;
 pub use self::bus::*;
 pub use self::console::*;
 pub use self::cpu::*;
+pub use self::error::*;
+pub use self::fds::*;
 pub use self::cart::*;
-pub use self::ppu::*;
\ No newline at end of file
+pub use self::input::*;
+pub use self::movie::*;
+pub use self::nsf::*;
+pub use self::palette::*;
+pub use self::ppu::*;
+pub use self::romdb::*;
+pub use self::state::StateError;
+pub use self::wav::*;
\ No newline at end of file
@@ -1,5 +1,3 @@
-use core::panic;
-
 use super::Mapper;
 use crate::CartData;
 
@@ -14,29 +12,39 @@ impl Mapper000 {
 
 impl Mapper for Mapper000 {
     fn cpu_read(&self, cart: &CartData, addr: u16) -> u8 {
-        let mask = match cart.prg_rom_page_count {
-            2 => 0x7fff,        // 16kb
-            1 => 0x3fff,        // 32kb
-            _ => panic!(
-                "Mapping failed: {} PRG pages not supported by this mapper",
-                cart.prg_rom_page_count
-            ),
-        };
-
-        cart.prg_rom[(addr & mask) as usize]
-    }
+        if addr < 0x6000 {
+            // Nothing mapped below PRG RAM.
+            return 0;
+        }
+        if addr < 0x8000 {
+            return match cart.prg_ram.len() {
+                0 => 0,
+                len => cart.prg_ram[(addr - 0x6000) as usize % len],
+            };
+        }
 
-    fn cpu_write(&self, _cart: &mut CartData, _addr: u16, _value: u8) {
+        // 16KB is mirrored at $C000.
+        cart.prg_rom[(addr & 0x7fff) as usize % cart.prg_rom.len()]
+    }
 
+    fn cpu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        let len = cart.prg_ram.len();
+        if (0x6000..0x8000).contains(&addr) && len > 0 {
+            cart.prg_ram[(addr - 0x6000) as usize % len] = value;
+        }
     }
 
     fn ppu_read(&self, cart: &CartData, addr: u16) -> u8 {
-        cart.chr_rom[(addr & 0x1fff) as usize]
+        match cart.chr_rom.is_empty() {
+            true => cart.chr_ram[(addr & 0x1fff) as usize % cart.chr_ram.len()],
+            false => cart.chr_rom[(addr & 0x1fff) as usize],
+        }
     }
 
-    fn ppu_write(&self, cart: &mut CartData, addr: u16, value: u8) {
-        if cart.chr_rom_page_count == 0 {
-            cart.chr_ram[(addr & 0x1fff) as usize] = value;
+    fn ppu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        if cart.chr_rom.is_empty() {
+            let len = cart.chr_ram.len();
+            cart.chr_ram[(addr & 0x1fff) as usize % len] = value;
         }
     }
 
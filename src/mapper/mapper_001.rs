@@ -0,0 +1,399 @@
+use super::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{CartData, Mirroring};
+
+/// Boards that wire the MMC1 differently, picked by submapper or guessed
+/// from ROM and RAM sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mmc1Board {
+    /// SxROM boards with up to 256KB PRG and 8KB RAM.
+    Standard,
+    /// Submapper 3, the MMC1A: PRG RAM can't be disabled.
+    Mmc1a,
+    /// 512KB PRG, CHR bit 4 selects the 256KB half.
+    Surom,
+    /// 16KB RAM, CHR bit 3 selects the 8KB bank.
+    Sorom,
+    /// 512KB PRG and 32KB RAM, CHR bit 4 selects the PRG half and bits 2-3
+    /// the RAM bank.
+    Sxrom,
+    /// Submapper 5, SEROM/SHROM: 32KB PRG that isn't banked.
+    Serom,
+}
+
+impl Mmc1Board {
+    /// Board for the cart's submapper, guessed from sizes for submapper 0.
+    /// `None` if the submapper isn't one of MMC1's.
+    pub fn detect(cart: &CartData) -> Option<Self> {
+        let board = match cart.submapper_id {
+            0 => {
+                let ram = cart.prg_ram.len();
+                match cart.prg_rom.len() > 256*1024 {
+                    true if ram >= 32*1024 => Mmc1Board::Sxrom,
+                    true => Mmc1Board::Surom,
+                    false if ram >= 32*1024 => Mmc1Board::Sxrom,
+                    false if ram >= 16*1024 => Mmc1Board::Sorom,
+                    false => Mmc1Board::Standard,
+                }
+            },
+            1 => Mmc1Board::Surom,
+            2 => Mmc1Board::Sorom,
+            3 => Mmc1Board::Mmc1a,
+            4 => Mmc1Board::Sxrom,
+            5 => Mmc1Board::Serom,
+            _ => return None,
+        };
+        Some(board)
+    }
+}
+
+/// Nintendo MMC1, mapper 1.
+/// See: <https://www.nesdev.org/wiki/MMC1>
+///
+/// Registers are loaded a bit at a time through a 5-bit shift register at
+/// `$8000-$FFFF`: bit 0 of four writes is shifted in, and the fifth write
+/// loads the register picked by its address, `$8000` control, `$A000` CHR
+/// bank 0, `$C000` CHR bank 1 and `$E000` PRG bank. Writing a value with bit
+/// 7 set resets the shift register.
+pub struct Mapper001 {
+    board: Mmc1Board,
+
+    shift: u8,
+    /// Bits in `shift` so far.
+    shift_count: u8,
+
+    control: u8,
+    chr0: u8,
+    chr1: u8,
+    prg: u8,
+    /// CHR bank last written in 4KB mode, the one whose upper bits drive
+    /// the PRG and RAM lines of SUROM, SOROM and SXROM.
+    last_chr: u8,
+
+    /// CPU clocks since the last serial write. The MMC1 ignores a write on
+    /// the clock right after another, which games hit with
+    /// read-modify-write instructions.
+    since_write: u64,
+}
+
+impl Mapper001 {
+    pub fn new(board: Mmc1Board) -> Self {
+        Self {
+            board,
+            shift: 0,
+            shift_count: 0,
+            // Power on in PRG mode 3, last bank fixed at $C000, so the
+            // reset vector is there.
+            control: 0x0C,
+            chr0: 0,
+            chr1: 0,
+            prg: 0,
+            last_chr: 0,
+            since_write: u64::MAX,
+        }
+    }
+
+    fn write_register(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        match addr & 0x6000 {
+            0x0000 => {
+                self.control = value;
+                cart.mirroring = match value & 0x03 {
+                    0 => Mirroring::OneScreenLower,
+                    1 => Mirroring::OneScreenUpper,
+                    2 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+            },
+            0x2000 => {
+                self.chr0 = value;
+                self.last_chr = value;
+            },
+            0x4000 => {
+                self.chr1 = value;
+                if self.chr_4k_mode() {
+                    self.last_chr = value;
+                }
+            },
+            _ => self.prg = value,
+        }
+    }
+
+    fn chr_4k_mode(&self) -> bool {
+        self.control & 0x10 != 0
+    }
+
+    /// The CHR bank driving the extra board lines.
+    fn board_lines(&self) -> u8 {
+        match self.chr_4k_mode() {
+            true => self.last_chr,
+            false => self.chr0,
+        }
+    }
+
+    fn prg_offset(&self, cart: &CartData, addr: u16) -> usize {
+        if self.board == Mmc1Board::Serom {
+            return (addr & 0x7fff) as usize % cart.prg_rom.len();
+        }
+
+        let outer = match self.board {
+            Mmc1Board::Surom | Mmc1Board::Sxrom => (self.board_lines() & 0x10) as usize,
+            _ => 0,
+        };
+        let bank = (self.prg & 0x0f) as usize;
+        let upper = addr >= 0xc000;
+
+        let bank = match (self.control >> 2) & 0x03 {
+            0 | 1 => (bank & !1) | upper as usize,
+            2 if upper => bank,
+            2 => 0,
+            _ if upper => 0x0f,
+            _ => bank,
+        };
+
+        ((outer | bank) * 0x4000 + (addr & 0x3fff) as usize) % cart.prg_rom.len()
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.board == Mmc1Board::Mmc1a || self.prg & 0x10 == 0
+    }
+
+    /// Offset in `CartData::prg_ram`, `None` if it's disabled.
+    fn prg_ram_offset(&self, cart: &CartData, addr: u16) -> Option<usize> {
+        if !self.prg_ram_enabled() {
+            return None;
+        }
+
+        let bank = match self.board {
+            Mmc1Board::Sorom => (self.board_lines() >> 3) & 0x01,
+            Mmc1Board::Sxrom => (self.board_lines() >> 2) & 0x03,
+            _ => 0,
+        } as usize;
+
+        cart.prg_ram_index(bank, (addr & 0x1fff) as usize)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = (addr & 0x1fff) as usize;
+        match (self.chr_4k_mode(), addr >= 0x1000) {
+            (false, _) => (self.chr0 & 0x1e) as usize * 0x1000 + addr,
+            (true, false) => self.chr0 as usize * 0x1000 + addr,
+            (true, true) => self.chr1 as usize * 0x1000 + (addr & 0x0fff),
+        }
+    }
+}
+
+impl Mapper for Mapper001 {
+    fn cpu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xffff => cart.prg_rom[self.prg_offset(cart, addr)],
+            0x6000..=0x7fff => match self.prg_ram_offset(cart, addr) {
+                Some(offset) => cart.prg_ram[offset],
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0xffff => {
+                let consecutive = self.since_write <= 1;
+                self.since_write = 0;
+                if consecutive {
+                    return;
+                }
+
+                if value & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+
+                self.shift |= (value & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    let value = self.shift;
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.write_register(cart, addr, value);
+                }
+            },
+            0x6000..=0x7fff => {
+                if let Some(offset) = self.prg_ram_offset(cart, addr) {
+                    cart.prg_ram[offset] = value;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        let chr = match cart.chr_rom.is_empty() {
+            true => &cart.chr_ram,
+            false => &cart.chr_rom,
+        };
+        chr[self.chr_offset(addr) % chr.len()]
+    }
+
+    fn ppu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        if cart.chr_rom.is_empty() {
+            let offset = self.chr_offset(addr) % cart.chr_ram.len();
+            cart.chr_ram[offset] = value;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.since_write = self.since_write.saturating_add(1);
+    }
+
+    fn id(&self) -> u16 {
+        1
+    }
+
+    fn name(&self) -> String {
+        match self.board {
+            Mmc1Board::Standard => "MMC1".to_owned(),
+            Mmc1Board::Mmc1a => "MMC1A".to_owned(),
+            board => format!("MMC1 ({:?})", board).to_uppercase(),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for value in [
+            self.shift, self.shift_count, self.control, self.chr0, self.chr1, self.prg,
+            self.last_chr,
+        ] {
+            w.u8(value);
+        }
+        w.u64(self.since_write);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for value in [
+            &mut self.shift, &mut self.shift_count, &mut self.control, &mut self.chr0,
+            &mut self.chr1, &mut self.prg, &mut self.last_chr,
+        ] {
+            *value = r.u8()?;
+        }
+        self.since_write = r.u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cart;
+
+    /// NES 2.0 MMC1 ROM with each 16KB PRG bank filled with its number,
+    /// RAM sizes given as NES 2.0 shift counts.
+    fn cart(submapper: u8, prg_banks: u8, ram_shifts: u8, battery: bool) -> Cart {
+        let mut rom = b"NES\x1a".to_vec();
+        rom.extend_from_slice(&[prg_banks, 0, 0x10 | (battery as u8) << 1, 0x08]);
+        rom.extend_from_slice(&[submapper << 4, 0, ram_shifts, 0x07, 0, 0, 0, 0]);
+        for bank in 0..prg_banks {
+            rom.resize(rom.len() + 0x4000, bank);
+        }
+        Cart::read_from(&mut rom.as_slice()).unwrap()
+    }
+
+    /// Load an MMC1 register through the serial port.
+    fn write(cart: &mut Cart, addr: u16, value: u8) {
+        for bit in 0..5 {
+            cart.cpu_write(addr, value >> bit & 1);
+            cart.clock();
+            cart.clock();
+        }
+    }
+
+    fn banks(cart: &Cart) -> (u8, u8) {
+        (cart.cpu_read(0x8000), cart.cpu_read(0xC000))
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut cart = cart(0, 16, 0x07, false);
+
+        // Powers on in mode 3, the last bank fixed at $C000.
+        write(&mut cart, 0xE000, 5);
+        assert_eq!(banks(&cart), (5, 15));
+
+        write(&mut cart, 0x8000, 0x08);
+        assert_eq!(banks(&cart), (0, 5));
+
+        for control in [0x00, 0x04] {
+            write(&mut cart, 0x8000, control);
+            assert_eq!(banks(&cart), (4, 5));
+        }
+
+        // A reset goes back to mode 3.
+        cart.cpu_write(0x8000, 0x80);
+        assert_eq!(banks(&cart), (5, 15));
+    }
+
+    #[test]
+    fn surom_outer_bank() {
+        let mut cart = cart(1, 32, 0x07, false);
+
+        write(&mut cart, 0xE000, 2);
+        assert_eq!(banks(&cart), (2, 15));
+
+        write(&mut cart, 0xA000, 0x10);
+        assert_eq!(banks(&cart), (18, 31));
+
+        // In 4KB CHR mode the last bank written drives the line.
+        write(&mut cart, 0x8000, 0x1C);
+        write(&mut cart, 0xC000, 0x00);
+        assert_eq!(banks(&cart), (2, 15));
+        write(&mut cart, 0xA000, 0x10);
+        assert_eq!(banks(&cart), (18, 31));
+    }
+
+    #[test]
+    fn sorom_ram_banks() {
+        // 8KB of RAM, then 8KB battery-backed.
+        let mut cart = cart(2, 16, 0x77, true);
+
+        cart.cpu_write(0x6000, 0xAA);
+        write(&mut cart, 0xA000, 0x08);
+        assert_eq!(cart.cpu_read(0x6000), 0);
+        cart.cpu_write(0x6000, 0xBB);
+
+        write(&mut cart, 0xA000, 0x00);
+        assert_eq!(cart.cpu_read(0x6000), 0xAA);
+        assert_eq!(cart.battery_ram().map(|ram| (ram.len(), ram[0])), Some((0x2000, 0xBB)));
+    }
+
+    #[test]
+    fn sxrom_ram_banks() {
+        let mut cart = cart(4, 32, 0x90, true);
+
+        for bank in 0..4 {
+            write(&mut cart, 0xA000, bank << 2);
+            cart.cpu_write(0x7FFF, bank + 1);
+        }
+        for bank in 0..4 {
+            write(&mut cart, 0xA000, bank << 2);
+            assert_eq!(cart.cpu_read(0x7FFF), bank + 1);
+        }
+
+        // Disabled by bit 4 of the PRG bank.
+        write(&mut cart, 0xE000, 0x10);
+        assert_eq!(cart.cpu_read(0x7FFF), 0);
+    }
+
+    #[test]
+    fn consecutive_writes_ignored() {
+        let mut cart = cart(0, 16, 0x07, false);
+
+        // Each bit written twice on back to back clocks, like an INC does.
+        for bit in 0..5 {
+            cart.cpu_write(0xE000, 6 >> bit & 1);
+            cart.clock();
+            cart.cpu_write(0xE000, 6 >> bit & 1);
+            cart.clock();
+            cart.clock();
+        }
+        assert_eq!(banks(&cart), (6, 15));
+    }
+}
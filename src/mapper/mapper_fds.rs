@@ -0,0 +1,359 @@
+use std::cell::Cell;
+
+use super::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{CartData, ExpansionAudio, FdsAudio, FdsDisk, Mirroring};
+
+/// CPU clocks for the head to get back to the start of the disk.
+const REWIND_DELAY: u32 = 50000;
+/// CPU clocks per byte at the drive's 96.4kHz bit rate.
+const BYTE_DELAY: u32 = 149;
+
+/// Famicom Disk System RAM adapter with its disk drive.
+/// See: <https://www.nesdev.org/wiki/Family_Computer_Disk_System>
+///
+/// `$6000-$DFFF` is 32KB of RAM, kept in `CartData::prg_ram`, and
+/// `$E000-$FFFF` the BIOS, kept in `CartData::prg_rom`. CHR is 8KB of
+/// RAM. The registers at `$4020-$4033` drive a timer IRQ and the disk,
+/// which streams a byte at a time through `$4024` and `$4031`. Sound is
+/// at `$4040-$4092`.
+pub struct MapperFds {
+    disk: FdsDisk,
+    audio: FdsAudio,
+
+    /// `$4023` bits 0 and 1.
+    disk_regs_enabled: bool,
+    sound_regs_enabled: bool,
+
+    /// `$4020-$4022`.
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    /// Reading `$4030` acknowledges IRQs, hence the cells.
+    timer_irq: Cell<bool>,
+
+    /// `$4025`.
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    /// `$4026`, read back through `$4033`.
+    ext_out: u8,
+
+    write_data: u8,
+    read_data: u8,
+    /// A byte came in or went out, `$4030` bit 1.
+    transfer_complete: Cell<bool>,
+    disk_irq: Cell<bool>,
+
+    /// Head position on the inserted side.
+    position: usize,
+    delay: u32,
+    /// The head went past the end and has to rewind.
+    end_of_head: bool,
+    /// The gap before a block was passed, bytes are data now.
+    gap_ended: bool,
+    scanning: bool,
+}
+
+impl MapperFds {
+    pub fn new(disk: FdsDisk) -> Self {
+        Self {
+            disk,
+            audio: FdsAudio::new(),
+            disk_regs_enabled: false,
+            sound_regs_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: Cell::new(false),
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            ext_out: 0,
+            write_data: 0,
+            read_data: 0,
+            transfer_complete: Cell::new(false),
+            disk_irq: Cell::new(false),
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            gap_ended: false,
+            scanning: false,
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+
+        match self.timer_counter {
+            0 => {
+                self.timer_irq.set(true);
+                self.timer_counter = self.timer_reload;
+                self.timer_enabled = self.timer_repeat;
+            },
+            _ => self.timer_counter -= 1,
+        }
+    }
+
+    /// Move the head along, transferring a byte every [`BYTE_DELAY`]
+    /// clocks while the motor runs.
+    fn clock_disk(&mut self) {
+        if self.disk.inserted_side().is_none() || !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+
+        match self.read_mode {
+            true => {
+                let value = self.disk.read(self.position);
+                if !self.disk_ready {
+                    self.gap_ended = false;
+                } else if value != 0 && !self.gap_ended {
+                    // The gap end mark itself isn't handed over.
+                    self.gap_ended = true;
+                    irq = false;
+                }
+
+                if self.gap_ended {
+                    self.transfer_complete.set(true);
+                    self.read_data = value;
+                    self.disk_irq.set(self.disk_irq.get() || irq);
+                }
+            },
+            false => {
+                // CRC bytes aren't computed, nothing checks them.
+                let mut value = 0;
+                if !self.crc_control {
+                    self.transfer_complete.set(true);
+                    self.disk_irq.set(self.disk_irq.get() || irq);
+                    value = self.write_data;
+                }
+                if !self.disk_ready {
+                    value = 0;
+                }
+
+                self.disk.write(self.position, value);
+                self.gap_ended = false;
+            },
+        }
+
+        self.position += 1;
+        match self.position >= self.disk.len() {
+            true => self.motor_on = false,
+            false => self.delay = BYTE_DELAY,
+        }
+    }
+
+    fn read_register(&self, addr: u16) -> u8 {
+        let inserted = self.disk.inserted_side().is_some();
+
+        match addr {
+            0x4030 => {
+                let value = (self.timer_irq.get() as u8)
+                    | (self.transfer_complete.get() as u8) << 1
+                    | (self.end_of_head as u8) << 6;
+                self.timer_irq.set(false);
+                self.disk_irq.set(false);
+                self.transfer_complete.set(false);
+                value
+            },
+            0x4031 => {
+                self.transfer_complete.set(false);
+                self.disk_irq.set(false);
+                self.read_data
+            },
+            // Not inserted, not ready, write protected. The rest is open bus.
+            0x4032 => {
+                0x40 | (!inserted as u8)
+                    | ((!inserted || !self.scanning) as u8) << 1
+                    | (!inserted as u8) << 2
+            },
+            // Bit 7 is the battery, reading good.
+            0x4033 => 0x80 | (self.ext_out & 0x7f),
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        match addr {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xff00) | value as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00ff) | (value as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = value & 0x01 != 0;
+                self.timer_enabled = value & 0x02 != 0;
+                match self.timer_enabled {
+                    true => self.timer_counter = self.timer_reload,
+                    false => self.timer_irq.set(false),
+                }
+            },
+            0x4024 => {
+                self.write_data = value;
+                self.transfer_complete.set(false);
+                self.disk_irq.set(false);
+            },
+            0x4025 => {
+                self.motor_on = value & 0x01 != 0;
+                self.reset_transfer = value & 0x02 != 0;
+                self.read_mode = value & 0x04 != 0;
+                cart.mirroring = match value & 0x08 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+                self.crc_control = value & 0x10 != 0;
+                self.disk_ready = value & 0x40 != 0;
+                self.disk_irq_enabled = value & 0x80 != 0;
+                self.disk_irq.set(false);
+            },
+            0x4026 => self.ext_out = value,
+            _ => {},
+        }
+    }
+}
+
+impl Mapper for MapperFds {
+    fn cpu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        match addr {
+            0x4030..=0x4033 if self.disk_regs_enabled => self.read_register(addr),
+            0x4040..=0x4092 if self.sound_regs_enabled => self.audio.read(addr).unwrap_or(0),
+            0x6000..=0xdfff => cart.prg_ram[(addr - 0x6000) as usize],
+            0xe000..=0xffff => cart.prg_rom[(addr - 0xe000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        match addr {
+            0x4023 => {
+                self.disk_regs_enabled = value & 0x01 != 0;
+                self.sound_regs_enabled = value & 0x02 != 0;
+                if !self.disk_regs_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq.set(false);
+                    self.disk_irq.set(false);
+                }
+            },
+            0x4020..=0x4026 if self.disk_regs_enabled => self.write_register(cart, addr, value),
+            0x4040..=0x408a if self.sound_regs_enabled => self.audio.write(addr, value),
+            0x6000..=0xdfff => cart.prg_ram[(addr - 0x6000) as usize] = value,
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        cart.chr_ram[(addr & 0x1fff) as usize]
+    }
+
+    fn ppu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        cart.chr_ram[(addr & 0x1fff) as usize] = value;
+    }
+
+    fn clock(&mut self) {
+        self.disk.clock();
+        self.clock_timer();
+        self.clock_disk();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq.get() || self.disk_irq.get()
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        Some(&mut self.audio)
+    }
+
+    fn disk(&self) -> Option<&FdsDisk> {
+        Some(&self.disk)
+    }
+
+    fn disk_mut(&mut self) -> Option<&mut FdsDisk> {
+        Some(&mut self.disk)
+    }
+
+    fn id(&self) -> u16 {
+        20
+    }
+
+    fn name(&self) -> String {
+        "FDS".to_owned()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.disk.save_state(w);
+        for flag in [
+            self.disk_regs_enabled, self.sound_regs_enabled, self.timer_repeat,
+            self.timer_enabled, self.timer_irq.get(), self.motor_on, self.reset_transfer,
+            self.read_mode, self.crc_control, self.disk_ready, self.disk_irq_enabled,
+            self.transfer_complete.get(), self.disk_irq.get(), self.end_of_head,
+            self.gap_ended, self.scanning,
+        ] {
+            w.bool(flag);
+        }
+        w.u16(self.timer_reload);
+        w.u16(self.timer_counter);
+        w.u8(self.ext_out);
+        w.u8(self.write_data);
+        w.u8(self.read_data);
+        w.u32(self.position as u32);
+        w.u32(self.delay);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.disk.load_state(r)?;
+        for flag in [
+            &mut self.disk_regs_enabled, &mut self.sound_regs_enabled, &mut self.timer_repeat,
+            &mut self.timer_enabled,
+        ] {
+            *flag = r.bool()?;
+        }
+        self.timer_irq.set(r.bool()?);
+        for flag in [
+            &mut self.motor_on, &mut self.reset_transfer, &mut self.read_mode,
+            &mut self.crc_control, &mut self.disk_ready, &mut self.disk_irq_enabled,
+        ] {
+            *flag = r.bool()?;
+        }
+        self.transfer_complete.set(r.bool()?);
+        self.disk_irq.set(r.bool()?);
+        for flag in [&mut self.end_of_head, &mut self.gap_ended, &mut self.scanning] {
+            *flag = r.bool()?;
+        }
+        self.timer_reload = r.u16()?;
+        self.timer_counter = r.u16()?;
+        self.ext_out = r.u8()?;
+        self.write_data = r.u8()?;
+        self.read_data = r.u8()?;
+        self.position = r.u32()? as usize;
+        self.delay = r.u32()?;
+        self.audio.load_state(r)
+    }
+}
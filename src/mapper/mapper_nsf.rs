@@ -0,0 +1,187 @@
+use super::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{
+    CartData, ExpansionAudio, ExpansionMix, FdsAudio, Mmc5Audio, N163Audio, NsfExpansion,
+    Sunsoft5bAudio, Vrc6Audio, Vrc7Audio,
+};
+
+/// Where the NSF driver parks the CPU between INIT/PLAY calls.
+pub const NSF_IDLE_ADDR: u16 = 0x4100;
+
+/// `JMP $4100`
+const IDLE_LOOP: [u8; 3] = [0x4c, NSF_IDLE_ADDR as u8, (NSF_IDLE_ADDR >> 8) as u8];
+
+/// Synthetic cartridge board for NSF music rips.
+///
+/// Song data lives in `CartData::prg_rom`, already laid out in 4KB banks.
+/// Bankswitched tunes select banks through `$5FF8-$5FFF`, others see a flat
+/// 32KB image at `$8000`. `$6000-$7FFF` is work RAM, and a tiny idle loop is
+/// mapped at [`NSF_IDLE_ADDR`] for the player to return to.
+///
+/// FDS tunes run entirely from RAM at `$6000-$FFFF`, writable up to `$DFFF`.
+/// Selecting a bank, including `$6000` and `$7000` through `$5FF6-$5FF7`,
+/// copies it in. Expansion chips flagged in the header are mixed in and get
+/// every register write.
+pub struct MapperNsf {
+    bankswitched: bool,
+    banks: [u8; 8],
+    fds: bool,
+    ram: Box<[u8]>,
+    /// MMC5 `$5C00-$5FF5` and the `$5205-$5206` multiplier.
+    exram: Option<Box<[u8]>>,
+    multiplier: [u8; 2],
+    audio: ExpansionMix,
+}
+
+impl MapperNsf {
+    pub fn new(bankswitched: bool, banks: [u8; 8], expansion: NsfExpansion) -> Self {
+        let mut audio = ExpansionMix::new();
+        if expansion.vrc6 {
+            audio.push(Box::new(Vrc6Audio::new()));
+        }
+        if expansion.vrc7 {
+            audio.push(Box::new(Vrc7Audio::new()));
+        }
+        if expansion.fds {
+            audio.push(Box::new(FdsAudio::new()));
+        }
+        if expansion.mmc5 {
+            audio.push(Box::new(Mmc5Audio::new()));
+        }
+        if expansion.n163 {
+            audio.push(Box::new(N163Audio::new()));
+        }
+        if expansion.sunsoft_5b {
+            audio.push(Box::new(Sunsoft5bAudio::new()));
+        }
+
+        let ram_size = if expansion.fds { 0xa000 } else { 0x2000 };
+
+        Self {
+            bankswitched,
+            banks,
+            fds: expansion.fds,
+            ram: vec![0u8; ram_size].into_boxed_slice(),
+            exram: match expansion.mmc5 {
+                true => Some(vec![0u8; 0x400].into_boxed_slice()),
+                false => None,
+            },
+            multiplier: [0; 2],
+            audio,
+        }
+    }
+
+    fn bank_count(cart: &CartData) -> usize {
+        (cart.prg_rom.len() / 0x1000).max(1)
+    }
+
+    fn bank_offset(cart: &CartData, bank: u8) -> usize {
+        (bank as usize % Self::bank_count(cart)) * 0x1000
+    }
+
+    /// FDS: copy `bank` into the RAM slot at `$6000 + slot * $1000`.
+    fn load_fds_bank(&mut self, cart: &CartData, slot: usize, bank: u8) {
+        let src = Self::bank_offset(cart, bank);
+        let dst = slot * 0x1000;
+
+        match cart.prg_rom.get(src..src + 0x1000) {
+            Some(data) => self.ram[dst..dst + 0x1000].copy_from_slice(data),
+            None => self.ram[dst..dst + 0x1000].fill(0),
+        }
+    }
+}
+
+impl Mapper for MapperNsf {
+    fn cpu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        if let Some(value) = self.audio.read(addr) {
+            return value;
+        }
+
+        match (addr, &self.exram) {
+            (0x4100..=0x4102, _) => IDLE_LOOP[(addr - NSF_IDLE_ADDR) as usize],
+            (0x5205, Some(_)) => (self.multiplier[0] as u16 * self.multiplier[1] as u16) as u8,
+            (0x5206, Some(_)) => ((self.multiplier[0] as u16 * self.multiplier[1] as u16) >> 8) as u8,
+            (0x5c00..=0x5ff5, Some(exram)) => exram[(addr - 0x5c00) as usize],
+            (0x6000..=0xffff, _) if self.fds => self.ram[(addr - 0x6000) as usize],
+            (0x6000..=0x7fff, _) => self.ram[(addr & 0x1fff) as usize],
+            (0x8000..=0xffff, _) => {
+                let slot = ((addr - 0x8000) >> 12) as usize;
+                let offset = match self.bankswitched {
+                    true => Self::bank_offset(cart, self.banks[slot]),
+                    false => slot * 0x1000,
+                };
+                cart.prg_rom.get(offset + (addr & 0x0fff) as usize).copied().unwrap_or(0)
+            },
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        self.audio.write(addr, value);
+
+        match addr {
+            0x5205..=0x5206 => self.multiplier[(addr - 0x5205) as usize] = value,
+            0x5c00..=0x5ff5 => {
+                if let Some(exram) = &mut self.exram {
+                    exram[(addr - 0x5c00) as usize] = value;
+                }
+            },
+            0x5ff6..=0x5fff if self.fds => self.load_fds_bank(cart, (addr - 0x5ff6) as usize, value),
+            0x5ff8..=0x5fff if self.bankswitched => {
+                self.banks[(addr - 0x5ff8) as usize] = value;
+            },
+            0x6000..=0xdfff if self.fds => self.ram[(addr - 0x6000) as usize] = value,
+            0x6000..=0x7fff => self.ram[(addr & 0x1fff) as usize] = value,
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&self, cart: &CartData, addr: u16) -> u8 {
+        cart.chr_ram[(addr & 0x1fff) as usize]
+    }
+
+    fn ppu_write(&mut self, cart: &mut CartData, addr: u16, value: u8) {
+        cart.chr_ram[(addr & 0x1fff) as usize] = value;
+    }
+
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        match self.audio.is_empty() {
+            true => None,
+            false => Some(&mut self.audio),
+        }
+    }
+
+    fn id(&self) -> u16 {
+        // NSF has no iNES mapper number.
+        0xffff
+    }
+
+    fn name(&self) -> String {
+        "NSF".to_owned()
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        for bank in self.banks {
+            w.u8(bank);
+        }
+        w.bytes(&self.ram);
+        if let Some(exram) = &self.exram {
+            w.bytes(exram);
+        }
+        w.u8(self.multiplier[0]);
+        w.u8(self.multiplier[1]);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for bank in &mut self.banks {
+            *bank = r.u8()?;
+        }
+        r.bytes_into(&mut self.ram)?;
+        if let Some(exram) = &mut self.exram {
+            r.bytes_into(exram)?;
+        }
+        self.multiplier = [r.u8()?, r.u8()?];
+        self.audio.load_state(r)
+    }
+}
@@ -1,14 +1,85 @@
 mod mapper_000;
+mod mapper_001;
+mod mapper_fds;
+mod mapper_nsf;
 
 pub use self::mapper_000::Mapper000;
+pub use self::mapper_001::{Mapper001, Mmc1Board};
+pub use self::mapper_fds::MapperFds;
+pub use self::mapper_nsf::{MapperNsf, NSF_IDLE_ADDR};
 
-use crate::CartData;
+use crate::{CartData, ExpansionAudio, FdsDisk};
+use crate::state::{StateError, StateReader, StateWriter};
 
-pub trait Mapper {
+/// Common names of well-known mappers, for telling users which board a
+/// ROM needs.
+pub fn mapper_name(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        16 => "Bandai FCG",
+        19 => "Namco 163",
+        20 => "FDS",
+        21 | 23 | 25 => "VRC2/VRC4",
+        22 => "VRC2",
+        24 | 26 => "VRC6",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        85 => "VRC7",
+        206 => "Namco 118",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// A cartridge board. `Send` so the console can run on its own thread.
+pub trait Mapper: Send {
     fn id(&self) -> u16;
     fn name(&self) -> String;
     fn cpu_read(&self, cart: &CartData, addr: u16) -> u8;
-    fn cpu_write(&self, cart: &mut CartData, addr: u16, value: u8);
+    fn cpu_write(&mut self, cart: &mut CartData, addr: u16, value: u8);
     fn ppu_read(&self, cart: &CartData, addr: u16) -> u8;
-    fn ppu_write(&self, cart: &mut CartData, addr: u16, value: u8);
+    fn ppu_write(&mut self, cart: &mut CartData, addr: u16, value: u8);
+
+    /// Sound chip on the board, clocked and mixed in by the APU. The mapper
+    /// forwards register writes to it from `cpu_write`.
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        None
+    }
+
+    /// Advance one CPU clock, for boards with timers or a disk drive.
+    fn clock(&mut self) {}
+
+    /// Whether the board is pulling the CPU's IRQ line low.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// The disk drive of the FDS RAM adapter.
+    fn disk(&self) -> Option<&FdsDisk> {
+        None
+    }
+
+    fn disk_mut(&mut self) -> Option<&mut FdsDisk> {
+        None
+    }
+
+    /// Registers and on-board RAM for save states. Boards without any
+    /// keep these defaults.
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,418 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::{DeviceInput, MovieError, EXPANSION_PORT, PORT_1, PORT_2};
+
+/// Frame command: press the reset button.
+pub const MOVIE_SOFT_RESET: u8 = 0b0000_0001;
+/// Frame command: power cycle. Replayed as a reset, the console can't be
+/// power cycled yet.
+pub const MOVIE_POWER: u8 = 0b0000_0010;
+
+/// Zapper aim written for a gun pointed away from the screen. Any row past
+/// the bottom of the picture reads back as `None`.
+const ZAPPER_OFFSCREEN: (u8, u8) = (0, 255);
+
+/// Input for one frame. Movies are replayed by applying these at the start
+/// of every frame, see [`crate::Console::play_movie`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// `MOVIE_*` bits.
+    pub commands: u8,
+    /// Input per port, `None` for an empty port.
+    pub ports: [Option<DeviceInput>; 3],
+}
+
+/// Recorded input, starting from power-on.
+///
+/// Round-trips FCEUX `.fm2` text movies with standard controllers, Four
+/// Score and Zapper. See: <https://fceux.com/web/FM2.html>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_filename: String,
+    /// Kept as written, e.g. `base64:...`. Not checked against the cart.
+    pub rom_checksum: String,
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub comments: Vec<String>,
+    pub frames: Vec<MovieFrame>,
+}
+
+/// `port0`/`port1` device types.
+const FM2_NONE: u8 = 0;
+const FM2_GAMEPAD: u8 = 1;
+const FM2_ZAPPER: u8 = 2;
+
+/// Gamepad field letters, one per [`crate::Button`] from the highest bit.
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+impl Movie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether any frame has players 3 and 4, needing a Four Score.
+    pub fn uses_four_score(&self) -> bool {
+        self.frames.iter()
+            .flat_map(|f| f.ports.iter())
+            .any(|p| matches!(p, Some(DeviceInput::Pads(..))))
+    }
+
+    pub fn read_fm2_file<P: AsRef<Path>>(fname: P) -> Result<Self, MovieError> {
+        let path = fname.as_ref();
+        let with_path = |e| MovieError::Io(Some(path.to_owned()), e);
+        let mut file = File::open(path).map_err(with_path)?;
+        Self::read_fm2(&mut file).map_err(|e| match e {
+            MovieError::Io(None, e) => with_path(e),
+            e => e,
+        })
+    }
+
+    /// Parse a text `.fm2` movie. Binary movies and movies that start from
+    /// a save state aren't supported.
+    pub fn read_fm2<T: Read>(src: &mut T) -> Result<Self, MovieError> {
+        let mut movie = Movie::new();
+        let mut four_score = false;
+        let mut port_types = [FM2_GAMEPAD, FM2_GAMEPAD];
+        let mut version = None;
+
+        for (number, line) in BufReader::new(src).lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            let bad_line = |problem| MovieError::BadLine { line: number + 1, problem };
+
+            if line.starts_with('|') {
+                let frame = parse_fm2_frame(line, four_score, port_types).map_err(bad_line)?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value),
+                None => (line, ""),
+            };
+            let flag = value == "1";
+
+            match key {
+                "version" => version = Some(value.to_owned()),
+                "rerecordCount" => {
+                    movie.rerecord_count = value.parse()
+                        .map_err(|_| bad_line("rerecordCount isn't a number"))?;
+                },
+                "palFlag" => movie.pal = flag,
+                "romFilename" => movie.rom_filename = value.to_owned(),
+                "romChecksum" => movie.rom_checksum = value.to_owned(),
+                "guid" => movie.guid = value.to_owned(),
+                "comment" => movie.comments.push(value.to_owned()),
+                "fourscore" => four_score = flag,
+                "port0" | "port1" => {
+                    let port_type = match value.parse() {
+                        Ok(port_type @ (FM2_NONE | FM2_GAMEPAD | FM2_ZAPPER)) => port_type,
+                        _ => return Err(bad_line("unknown device type")),
+                    };
+                    port_types[(key == "port1") as usize] = port_type;
+                },
+                "port2" if value != "0" => return Err(MovieError::Unsupported("expansion port devices")),
+                "binary" if flag => return Err(MovieError::Unsupported("binary movies")),
+                "FDS" if flag => return Err(MovieError::Unsupported("FDS movies")),
+                "savestate" if !value.is_empty() => {
+                    return Err(MovieError::Unsupported("movies starting from a save state"));
+                },
+                _ => {},
+            }
+        }
+
+        match version.as_deref() {
+            Some("3") => Ok(movie),
+            _ => Err(MovieError::BadVersion),
+        }
+    }
+
+    pub fn write_fm2_file<P: AsRef<Path>>(&self, fname: P) -> io::Result<()> {
+        let mut file = File::create(fname)?;
+        self.write_fm2(&mut file)
+    }
+
+    /// Write a text `.fm2` movie. Fails with `InvalidData` for devices the
+    /// format can't hold, e.g. a Power Pad, or a port whose device changes.
+    pub fn write_fm2<W: Write>(&self, dst: &mut W) -> io::Result<()> {
+        let four_score = self.uses_four_score();
+        let port_types = [self.fm2_port_type(PORT_1)?, self.fm2_port_type(PORT_2)?];
+
+        if self.frames.iter().any(|f| f.ports[EXPANSION_PORT].is_some()) {
+            return Err(invalid_data("expansion port devices can't be stored in fm2"));
+        }
+
+        writeln!(dst, "version 3")?;
+        writeln!(dst, "emuVersion 0")?;
+        writeln!(dst, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(dst, "palFlag {}", self.pal as u8)?;
+        writeln!(dst, "romFilename {}", self.rom_filename)?;
+        writeln!(dst, "romChecksum {}", self.rom_checksum)?;
+        writeln!(dst, "guid {}", self.guid)?;
+        writeln!(dst, "fourscore {}", four_score as u8)?;
+        writeln!(dst, "microphone 0")?;
+        writeln!(dst, "port0 {}", if four_score { FM2_GAMEPAD } else { port_types[0] })?;
+        writeln!(dst, "port1 {}", if four_score { FM2_GAMEPAD } else { port_types[1] })?;
+        writeln!(dst, "port2 0")?;
+        for comment in &self.comments {
+            writeln!(dst, "comment {}", comment)?;
+        }
+
+        for frame in &self.frames {
+            write!(dst, "|{}|", frame.commands)?;
+
+            if four_score {
+                let (p1, p3) = pads(frame.ports[PORT_1]);
+                let (p2, p4) = pads(frame.ports[PORT_2]);
+                for buttons in [p1, p2, p3, p4] {
+                    write!(dst, "{}|", gamepad_field(buttons))?;
+                }
+            } else {
+                for input in &frame.ports[PORT_1..=PORT_2] {
+                    match *input {
+                        Some(DeviceInput::Pad(buttons)) => write!(dst, "{}", gamepad_field(buttons))?,
+                        Some(DeviceInput::Zapper { aim, trigger }) => {
+                            let (x, y) = aim.unwrap_or(ZAPPER_OFFSCREEN);
+                            write!(dst, "{} {} {} 0 0", x, y, trigger as u8)?;
+                        },
+                        _ => {},
+                    }
+                    write!(dst, "|")?;
+                }
+            }
+
+            writeln!(dst, "|")?;
+        }
+
+        Ok(())
+    }
+
+    /// Device type for a controller port, from the first frame with input
+    /// on it.
+    fn fm2_port_type(&self, port: usize) -> io::Result<u8> {
+        let mut port_type = None;
+
+        for input in self.frames.iter().filter_map(|f| f.ports[port]) {
+            let this = match input {
+                DeviceInput::Pad(_) | DeviceInput::Pads(..) => FM2_GAMEPAD,
+                DeviceInput::Zapper { .. } => FM2_ZAPPER,
+                _ => return Err(invalid_data("only controllers and the Zapper can be stored in fm2")),
+            };
+
+            match port_type {
+                Some(port_type) if port_type != this => {
+                    return Err(invalid_data("fm2 can't change devices mid-movie"));
+                },
+                _ => port_type = Some(this),
+            }
+        }
+
+        Ok(port_type.unwrap_or(FM2_NONE))
+    }
+}
+
+/// Parse an input log line, `|commands|port0|port1|port2|`, or with a Four
+/// Score `|commands|p1|p2|p3|p4|port2|`.
+fn parse_fm2_frame(line: &str, four_score: bool, port_types: [u8; 2]) -> Result<MovieFrame, &'static str> {
+    let mut fields = line.split('|').skip(1);
+    let mut field = || fields.next().ok_or("missing a field");
+
+    let commands = field()?.trim();
+    let commands = match commands.is_empty() {
+        true => 0,
+        false => commands.parse().map_err(|_| "commands aren't a number")?,
+    };
+    let mut frame = MovieFrame { commands, ..MovieFrame::default() };
+
+    if four_score {
+        let pads = [field()?, field()?, field()?, field()?];
+        let pads = pads.iter().map(|p| parse_gamepad(p)).collect::<Result<Vec<_>, _>>()?;
+        frame.ports[PORT_1] = Some(DeviceInput::Pads(pads[0], pads[2]));
+        frame.ports[PORT_2] = Some(DeviceInput::Pads(pads[1], pads[3]));
+        return Ok(frame);
+    }
+
+    for (port, &port_type) in port_types.iter().enumerate() {
+        let value = field()?;
+        frame.ports[port] = match port_type {
+            FM2_NONE => None,
+            FM2_GAMEPAD => Some(DeviceInput::Pad(parse_gamepad(value)?)),
+            FM2_ZAPPER => Some(parse_zapper(value)?),
+            _ => return Err("unknown device type"),
+        };
+    }
+
+    Ok(frame)
+}
+
+/// `RLDUTSBA`, with `.` or a space for released buttons.
+fn parse_gamepad(field: &str) -> Result<u8, &'static str> {
+    if field.len() != FM2_BUTTONS.len() {
+        return Err("gamepad field isn't 8 buttons");
+    }
+
+    let buttons = field.bytes()
+        .enumerate()
+        .filter(|&(_, c)| c != b'.' && c != b' ')
+        .fold(0, |buttons, (i, _)| buttons | (0x80 >> i));
+
+    Ok(buttons)
+}
+
+fn gamepad_field(buttons: u8) -> String {
+    FM2_BUTTONS.iter()
+        .enumerate()
+        .map(|(i, &c)| if buttons & (0x80 >> i) != 0 { c as char } else { '.' })
+        .collect()
+}
+
+/// `x y buttons ...`, trigger on bit 0 of `buttons`.
+fn parse_zapper(field: &str) -> Result<DeviceInput, &'static str> {
+    let bad = "Zapper field isn't x, y and buttons";
+    let mut values = field.split_whitespace().map(|v| v.parse::<u8>().map_err(|_| bad));
+    let mut value = || values.next().unwrap_or(Err(bad));
+    let (x, y, buttons) = (value()?, value()?, value()?);
+
+    let aim = match (y as usize) < crate::SCREEN_HEIGHT {
+        true => Some((x, y)),
+        false => None,
+    };
+
+    Ok(DeviceInput::Zapper { aim, trigger: (buttons & 1) != 0 })
+}
+
+/// Players on one Four Score port. A lone controller is the first player.
+fn pads(input: Option<DeviceInput>) -> (u8, u8) {
+    match input {
+        Some(DeviceInput::Pads(first, second)) => (first, second),
+        Some(DeviceInput::Pad(buttons)) => (buttons, 0),
+        _ => (0, 0),
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// FNV-1a over machine state. Cheap and stable across builds, for
+/// comparing a replay against a recording.
+pub(crate) fn state_hash<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(movie: &Movie) -> Movie {
+        let mut fm2 = Vec::new();
+        movie.write_fm2(&mut fm2).unwrap();
+        Movie::read_fm2(&mut fm2.as_slice()).unwrap()
+    }
+
+    fn frame(commands: u8, ports: [Option<DeviceInput>; 2]) -> MovieFrame {
+        MovieFrame { commands, ports: [ports[0], ports[1], None] }
+    }
+
+    fn read(fm2: &str) -> Result<Movie, MovieError> {
+        Movie::read_fm2(&mut fm2.as_bytes())
+    }
+
+    #[test]
+    fn gamepads() {
+        let movie = Movie {
+            rom_filename: "game".to_owned(),
+            rom_checksum: "base64:AAAA".to_owned(),
+            guid: "00000000-0000-0000-0000-000000000000".to_owned(),
+            rerecord_count: 12,
+            pal: true,
+            comments: vec!["author someone".to_owned()],
+            frames: vec![
+                frame(MOVIE_POWER, [Some(DeviceInput::Pad(0)), Some(DeviceInput::Pad(0))]),
+                frame(0, [Some(DeviceInput::Pad(0x81)), Some(DeviceInput::Pad(0x10))]),
+                frame(MOVIE_SOFT_RESET, [Some(DeviceInput::Pad(0xFF)), Some(DeviceInput::Pad(0))]),
+            ],
+        };
+
+        assert_eq!(round_trip(&movie), movie);
+
+        let mut fm2 = Vec::new();
+        movie.write_fm2(&mut fm2).unwrap();
+        let fm2 = String::from_utf8(fm2).unwrap();
+        assert!(fm2.contains("\n|0|R......A|...U....||\n"));
+    }
+
+    #[test]
+    fn four_score() {
+        let pads = |a, b| Some(DeviceInput::Pads(a, b));
+        let movie = Movie {
+            frames: vec![
+                frame(0, [pads(1, 2), pads(4, 8)]),
+                frame(0, [pads(0x80, 0), pads(0, 0x40)]),
+            ],
+            ..Movie::new()
+        };
+
+        assert!(movie.uses_four_score());
+        assert_eq!(round_trip(&movie), movie);
+    }
+
+    #[test]
+    fn zapper() {
+        let zapper = |aim, trigger| Some(DeviceInput::Zapper { aim, trigger });
+        let movie = Movie {
+            frames: vec![
+                frame(0, [Some(DeviceInput::Pad(0)), zapper(Some((128, 100)), true)]),
+                frame(0, [Some(DeviceInput::Pad(0)), zapper(None, false)]),
+            ],
+            ..Movie::new()
+        };
+
+        let mut fm2 = Vec::new();
+        movie.write_fm2(&mut fm2).unwrap();
+        let fm2 = String::from_utf8(fm2).unwrap();
+        assert!(fm2.contains("port1 2\n"));
+        assert!(fm2.contains("|0|........|0 255 0 0 0||\n"));
+
+        assert_eq!(round_trip(&movie), movie);
+    }
+
+    #[test]
+    fn unsupported_movies() {
+        let header = "version 3\nport0 1\nport1 1\n";
+        let unsupported = |extra: &str| match read(&format!("{}{}\n", header, extra)) {
+            Err(MovieError::Unsupported(what)) => what,
+            other => panic!("{:?}", other.map(|_| ())),
+        };
+
+        assert_eq!(unsupported("binary 1"), "binary movies");
+        assert_eq!(unsupported("savestate base64:AAAA"), "movies starting from a save state");
+        assert_eq!(unsupported("port2 1"), "expansion port devices");
+
+        assert!(read(&format!("{}binary 0\nport2 0\nsavestate\n", header)).is_ok());
+        assert!(matches!(read("version 2\n"), Err(MovieError::BadVersion)));
+        assert!(matches!(
+            read(&format!("{}|0|........|RL|\n", header)),
+            Err(MovieError::BadLine { line: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn device_change_not_written() {
+        let movie = Movie {
+            frames: vec![
+                frame(0, [Some(DeviceInput::Pad(0)), None]),
+                frame(0, [Some(DeviceInput::Zapper { aim: None, trigger: false }), None]),
+            ],
+            ..Movie::new()
+        };
+
+        let err = movie.write_fm2(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,634 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::mapper::{Mapper, MapperNsf, NSF_IDLE_ADDR};
+use crate::checksum;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::{Cart, CartData, Console, ConsoleType, LoadError, Mirroring, TVSystem, Timing, CPU_CLOCK_NTSC, CPU_CLOCK_PAL};
+
+/// Default play rate for NSFe files without a `RATE` chunk, in microseconds.
+const NTSC_PLAY_SPEED: u16 = 16639;
+const PAL_PLAY_SPEED: u16 = 19997;
+
+/// NSF/NSFe music rip.
+/// See: <https://www.nesdev.org/wiki/NSF> and <https://www.nesdev.org/wiki/NSFe>
+#[derive(Debug, Clone)]
+pub struct Nsf {
+    pub version: u8,
+    pub total_songs: u8,
+    /// 1-based, as stored in the file.
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+
+    /// PLAY call period in microseconds.
+    pub play_speed_ntsc: u16,
+    pub play_speed_pal: u16,
+    /// Initial `$5FF8-$5FFF` values. All zero if not bankswitched.
+    pub bankswitch_init: [u8; 8],
+    pub region: NsfRegion,
+    pub expansion: NsfExpansion,
+
+    /// NSFe only: per-track titles, lengths and fade-outs in milliseconds.
+    pub track_labels: Vec<String>,
+    pub track_times: Vec<i32>,
+    pub track_fades: Vec<i32>,
+
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsfRegion { NTSC, PAL, Dual }
+
+/// Expansion sound chips a tune uses, as flagged in the header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NsfExpansion {
+    pub vrc6: bool,
+    pub vrc7: bool,
+    pub fds: bool,
+    pub mmc5: bool,
+    pub n163: bool,
+    pub sunsoft_5b: bool,
+}
+
+impl From<u8> for NsfExpansion {
+    fn from(b: u8) -> Self {
+        Self {
+            vrc6: (b & 0b0000_0001) != 0,
+            vrc7: (b & 0b0000_0010) != 0,
+            fds: (b & 0b0000_0100) != 0,
+            mmc5: (b & 0b0000_1000) != 0,
+            n163: (b & 0b0001_0000) != 0,
+            sunsoft_5b: (b & 0b0010_0000) != 0,
+        }
+    }
+}
+
+impl From<u8> for NsfRegion {
+    fn from(b: u8) -> Self {
+        match b & 0b0000_0011 {
+            0 => NsfRegion::NTSC,
+            1 => NsfRegion::PAL,
+            _ => NsfRegion::Dual,
+        }
+    }
+}
+
+impl Nsf {
+    pub fn read_file<P: AsRef<Path>>(fname: P) -> Result<Self, LoadError> {
+        let path = fname.as_ref();
+        let mut src = File::open(path)
+            .map_err(|e| LoadError::Io(Some(path.to_owned()), e))?;
+        Self::read_from(&mut src).map_err(|e| e.with_path(path.to_owned()))
+    }
+
+    /// Reads either format, telling them apart by their magic bytes.
+    pub fn read_from<T: Read>(src: &mut T) -> Result<Self, LoadError> {
+        let mut buf = Vec::new();
+        src.read_to_end(&mut buf)?;
+
+        let nsf = match buf.get(..4) {
+            Some(b"NESM") => Self::parse_nsf(&buf)?,
+            Some(b"NSFE") => Self::parse_nsfe(&buf)?,
+            _ => return Err(bad_nsf("missing the NESM or NSFE signature")),
+        };
+
+        // Only FDS tunes, which run from RAM, can load below ROM.
+        let lowest = if nsf.expansion.fds { 0x6000 } else { 0x8000 };
+        if nsf.load_addr < lowest {
+            return Err(bad_nsf(format!(
+                "load address ${:04X} is below ${:04X}", nsf.load_addr, lowest,
+            )));
+        }
+
+        Ok(nsf)
+    }
+
+    fn parse_nsf(buf: &[u8]) -> Result<Self, LoadError> {
+        if buf.len() < 0x80 {
+            return Err(bad_nsf("file is too short for the header"));
+        }
+        if buf[4] != 0x1a {
+            return Err(bad_nsf("missing the NESM signature"));
+        }
+
+        let mut bankswitch_init = [0u8; 8];
+        bankswitch_init.copy_from_slice(&buf[0x70..0x78]);
+
+        Ok(Self {
+            version: buf[0x05],
+            total_songs: buf[0x06],
+            starting_song: buf[0x07],
+            load_addr: read_u16(buf, 0x08),
+            init_addr: read_u16(buf, 0x0a),
+            play_addr: read_u16(buf, 0x0c),
+            name: read_str(&buf[0x0e..0x2e]),
+            artist: read_str(&buf[0x2e..0x4e]),
+            copyright: read_str(&buf[0x4e..0x6e]),
+            play_speed_ntsc: read_u16(buf, 0x6e),
+            play_speed_pal: read_u16(buf, 0x78),
+            bankswitch_init,
+            region: buf[0x7a].into(),
+            expansion: buf[0x7b].into(),
+            track_labels: Vec::new(),
+            track_times: Vec::new(),
+            track_fades: Vec::new(),
+            data: buf[0x80..].to_vec(),
+        })
+    }
+
+    fn parse_nsfe(buf: &[u8]) -> Result<Self, LoadError> {
+        let mut nsf = Self {
+            version: 1,
+            total_songs: 1,
+            starting_song: 1,
+            load_addr: 0,
+            init_addr: 0,
+            play_addr: 0,
+            name: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            play_speed_ntsc: NTSC_PLAY_SPEED,
+            play_speed_pal: PAL_PLAY_SPEED,
+            bankswitch_init: [0u8; 8],
+            region: NsfRegion::NTSC,
+            expansion: NsfExpansion::default(),
+            track_labels: Vec::new(),
+            track_times: Vec::new(),
+            track_fades: Vec::new(),
+            data: Vec::new(),
+        };
+
+        let mut has_info = false;
+        let mut pos = 4;
+
+        // Chunks: [length: u32][id: 4 chars][data]
+        loop {
+            let header = buf.get(pos..pos + 8)
+                .ok_or_else(|| bad_nsf("missing the NEND chunk"))?;
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let id = &header[4..8];
+            let data = buf.get(pos + 8..pos.saturating_add(8 + len)).ok_or_else(|| {
+                bad_nsf(format!("{} chunk runs past the end", String::from_utf8_lossy(id)))
+            })?;
+            pos += 8 + len;
+
+            match id {
+                b"INFO" => {
+                    if data.len() < 8 {
+                        return Err(bad_nsf("INFO chunk is too short"));
+                    }
+                    nsf.load_addr = read_u16(data, 0);
+                    nsf.init_addr = read_u16(data, 2);
+                    nsf.play_addr = read_u16(data, 4);
+                    nsf.region = data[6].into();
+                    nsf.expansion = data[7].into();
+                    nsf.total_songs = data.get(8).copied().unwrap_or(1);
+                    nsf.starting_song = data.get(9).copied().unwrap_or(0) + 1;
+                    has_info = true;
+                },
+                b"DATA" => nsf.data = data.to_vec(),
+                b"BANK" => {
+                    for (dst, src) in nsf.bankswitch_init.iter_mut().zip(data) {
+                        *dst = *src;
+                    }
+                },
+                b"RATE" => {
+                    if data.len() >= 2 {
+                        nsf.play_speed_ntsc = read_u16(data, 0);
+                    }
+                    if data.len() >= 4 {
+                        nsf.play_speed_pal = read_u16(data, 2);
+                    }
+                },
+                b"auth" => {
+                    let mut fields = data.split(|b| *b == 0).map(read_str);
+                    nsf.name = fields.next().unwrap_or_default();
+                    nsf.artist = fields.next().unwrap_or_default();
+                    nsf.copyright = fields.next().unwrap_or_default();
+                },
+                b"tlbl" => {
+                    nsf.track_labels = data.split(|b| *b == 0).map(read_str).collect();
+                    nsf.track_labels.truncate(nsf.total_songs as usize);
+                },
+                b"time" => nsf.track_times = read_i32s(data),
+                b"fade" => nsf.track_fades = read_i32s(data),
+                b"NEND" => break,
+                // Chunks starting with an uppercase letter are mandatory.
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(bad_nsf(format!(
+                        "unknown {} chunk is needed to play it", String::from_utf8_lossy(id),
+                    )));
+                },
+                _ => {},
+            }
+        }
+
+        match (has_info, nsf.data.is_empty()) {
+            (false, _) => Err(bad_nsf("missing the INFO chunk")),
+            (_, true) => Err(bad_nsf("missing the DATA chunk")),
+            _ => Ok(nsf),
+        }
+    }
+
+    pub fn is_bankswitched(&self) -> bool {
+        self.bankswitch_init.iter().any(|b| *b != 0)
+    }
+
+    /// Bank register writes that set up the tune's initial memory layout.
+    /// FDS tunes run from RAM, so even ones that aren't bankswitched get
+    /// their image copied in this way.
+    pub fn bank_writes(&self) -> Vec<(u16, u8)> {
+        let init = &self.bankswitch_init;
+
+        let banks: Vec<u8> = match (self.expansion.fds, self.is_bankswitched()) {
+            // $5FF6-$5FF7 take header bytes $76-$77.
+            (true, true) => [init[6], init[7]].iter().chain(init.iter()).copied().collect(),
+            // Flat image from $6000.
+            (true, false) => (0..10).collect(),
+            (false, true) => init.to_vec(),
+            (false, false) => return Vec::new(),
+        };
+
+        let base = if self.expansion.fds { 0x5ff6 } else { 0x5ff8 };
+        banks.into_iter().enumerate().map(|(i, b)| (base + i as u16, b)).collect()
+    }
+
+    /// Build the synthetic cartridge the tune plays from.
+    pub fn to_cart(&self) -> Cart {
+        // Non-bankswitched FDS tunes can load as low as $6000.
+        let flat_base = if self.expansion.fds { 0x6000 } else { 0x8000 };
+
+        let prg_rom = match self.is_bankswitched() {
+            // Banks are 4KB aligned, with the load address's low 12 bits
+            // as padding in front of the data.
+            true => {
+                let padding = (self.load_addr & 0x0fff) as usize;
+                let mut image = vec![0u8; padding];
+                image.extend_from_slice(&self.data);
+                let banks = image.len().div_ceil(0x1000);
+                image.resize(banks * 0x1000, 0);
+                image
+            },
+            false => {
+                let size = 0x10000 - flat_base as usize;
+                let mut image = vec![0u8; size];
+                let start = (self.load_addr.saturating_sub(flat_base) as usize).min(size);
+                let len = self.data.len().min(size - start);
+                image[start..start + len].copy_from_slice(&self.data[..len]);
+                image
+            },
+        };
+
+        let mapper = MapperNsf::new(self.is_bankswitched(), self.bankswitch_init, self.expansion);
+        let crc32 = checksum::crc32(&[&prg_rom]);
+        let sha1 = checksum::sha1(&[&prg_rom]);
+
+        Cart {
+            data: CartData {
+                is_nes2: false,
+                prg_rom_page_count: (prg_rom.len() / 0x4000) as u16,
+                chr_rom_page_count: 0,
+                mirroring: Mirroring::Vertical,
+                sram_enable: false,
+                trainer_present: false,
+                four_screen_vram_layout: false,
+                mapper_id: mapper.id(),
+                submapper_id: 0,
+                is_vs_system: false,
+                console_type: ConsoleType::Nes,
+                ram_banks: 1,
+                tv_system: match self.region {
+                    NsfRegion::PAL => TVSystem::PAL,
+                    _ => TVSystem::NTSC,
+                },
+                timing: match self.region {
+                    NsfRegion::NTSC => Timing::Ntsc,
+                    NsfRegion::PAL => Timing::Pal,
+                    NsfRegion::Dual => Timing::MultiRegion,
+                },
+                prg_ram_size: 0x2000,
+                prg_nvram_size: 0,
+                chr_ram_size: 0x2000,
+                chr_nvram_size: 0,
+                misc_rom_count: 0,
+                expansion_device: 0,
+                trainer: None,
+                prg_rom,
+                chr_rom: Vec::new(),
+                extra_bytes: Vec::new(),
+                chr_ram: vec![0u8; 0x2000],
+                // The player has its own RAM, laid out for FDS tunes too.
+                prg_ram: Vec::new(),
+                crc32,
+                sha1,
+                header_fixes: Vec::new(),
+                patches: Vec::new(),
+            },
+            mapper: Box::new(mapper),
+        }
+    }
+}
+
+/// Drives an NSF's INIT and PLAY routines on an otherwise idle console.
+pub struct NsfPlayer {
+    pub console: Console,
+    init_addr: u16,
+    play_addr: u16,
+    bank_writes: Vec<(u16, u8)>,
+    fds: bool,
+    total_songs: u8,
+    song: u8,
+    pal: bool,
+
+    /// CPU cycles between PLAY calls.
+    play_period: f64,
+    play_timer: f64,
+    /// Inside INIT or PLAY, i.e. not parked at the idle loop.
+    busy: bool,
+}
+
+impl NsfPlayer {
+    /// `cart` has to be built from `nsf` with [`Nsf::to_cart`].
+    pub fn new(nsf: &Nsf, cart: Cart) -> Self {
+        let mut console = Console::new();
+        console.insert_cart(Box::new(cart));
+
+        let pal = nsf.region == NsfRegion::PAL;
+        let (clock_rate, speed) = match pal {
+            true => (CPU_CLOCK_PAL, nsf.play_speed_pal),
+            false => (CPU_CLOCK_NTSC, nsf.play_speed_ntsc),
+        };
+        console.apu_mut().set_clock_rate(clock_rate);
+
+        let speed = if speed == 0 { NTSC_PLAY_SPEED } else { speed };
+
+        let mut player = Self {
+            console,
+            init_addr: nsf.init_addr,
+            play_addr: nsf.play_addr,
+            bank_writes: nsf.bank_writes(),
+            fds: nsf.expansion.fds,
+            total_songs: nsf.total_songs.max(1),
+            song: 0,
+            pal,
+            play_period: clock_rate * speed as f64 / 1_000_000.0,
+            play_timer: 0.0,
+            busy: false,
+        };
+
+        player.select_song(nsf.starting_song.saturating_sub(1));
+        player
+    }
+
+    pub fn total_songs(&self) -> u8 {
+        self.total_songs
+    }
+
+    /// Current song, 0-based.
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+
+    /// Reset the console and run the INIT routine for `song` (0-based).
+    pub fn select_song(&mut self, song: u8) {
+        self.song = song.min(self.total_songs - 1);
+
+        let nes = &mut self.console;
+        nes.reset_to(NSF_IDLE_ADDR);
+
+        for addr in (0x0000..0x0800).chain(0x6000..0x8000) {
+            nes.bus.write(addr, 0);
+        }
+
+        for addr in 0x4000..0x4014 {
+            nes.bus.write(addr, 0);
+        }
+        nes.bus.write(0x4015, 0x00);
+        nes.bus.write(0x4015, 0x0f);
+        nes.bus.write(0x4017, 0x40);
+
+        for (addr, bank) in self.bank_writes.iter() {
+            nes.bus.write(*addr, *bank);
+        }
+
+        if self.fds {
+            // Enable sound and the wavetable, and set the envelope speed.
+            nes.bus.write(0x4023, 0x83);
+            nes.bus.write(0x4089, 0x00);
+            nes.bus.write(0x408a, 0xe8);
+        }
+
+        nes.apu_mut().clear_audio();
+
+        nes.cpu.reg.A = self.song;
+        nes.cpu.reg.X = self.pal as u8;
+        self.call(self.init_addr);
+        self.play_timer = self.play_period;
+    }
+
+    /// Emulate the routine at `addr` being called with `JSR`, returning to
+    /// the idle loop.
+    fn call(&mut self, addr: u16) {
+        let nes = &mut self.console;
+        let ret = NSF_IDLE_ADDR - 1;
+
+        for b in [(ret >> 8) as u8, ret as u8].iter() {
+            nes.bus.write(0x0100 | nes.cpu.reg.S as u16, *b);
+            nes.cpu.reg.S = nes.cpu.reg.S.wrapping_sub(1);
+        }
+
+        nes.cpu.reg.PC = addr;
+        self.busy = true;
+    }
+
+    /// Advance one CPU cycle, calling PLAY whenever it's due.
+    pub fn step(&mut self) {
+        self.console.step();
+
+        if self.busy && self.console.cpu.reg.PC == NSF_IDLE_ADDR {
+            self.busy = false;
+        }
+
+        self.play_timer -= 1.0;
+        if self.play_timer <= 0.0 && !self.busy {
+            self.play_timer += self.play_period;
+            self.call(self.play_addr);
+        }
+    }
+
+    /// Run for `seconds` of emulated time.
+    pub fn run_for(&mut self, seconds: f64) {
+        let clock_rate = if self.pal { CPU_CLOCK_PAL } else { CPU_CLOCK_NTSC };
+        let cycles = (seconds * clock_rate) as u64;
+
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+
+    /// See [`Console::drain_audio`].
+    pub fn drain_audio(&mut self, out: &mut [f32]) -> usize {
+        self.console.drain_audio(out)
+    }
+
+    /// Snapshot of the console and of where the player is in the song.
+    /// [`Console::save_state`] alone would lose the PLAY timing.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.console.save_parts(&mut w);
+        w.u8(self.song);
+        w.u64(self.play_timer.to_bits());
+        w.bool(self.busy);
+        w.finish()
+    }
+
+    /// Restore a [`NsfPlayer::save_state`] snapshot of the same NSF. If it
+    /// doesn't fit, the player is left as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let backup = self.save_state();
+
+        self.read_state(state).inspect_err(|_| {
+            // Can't fail, it was just written with the same layout.
+            let _ = self.read_state(&backup);
+        })
+    }
+
+    fn read_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(state)?;
+        self.console.load_parts(&mut r)?;
+        self.song = r.u8()?;
+        self.play_timer = f64::from_bits(r.u64()?);
+        self.busy = r.bool()?;
+        r.finish()
+    }
+}
+
+fn bad_nsf<S: Into<String>>(problem: S) -> LoadError {
+    LoadError::BadNsf(problem.into())
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_i32s(buf: &[u8]) -> Vec<i32> {
+    buf.chunks_exact(4)
+        .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+/// Null-terminated (or padded) string field.
+fn read_str(buf: &[u8]) -> String {
+    let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nsf_header(load_addr: u16, expansion: u8) -> Vec<u8> {
+        let mut buf = b"NESM\x1a\x01\x03\x02".to_vec();
+        for addr in [load_addr, 0x8003, 0x8006] {
+            buf.extend_from_slice(&addr.to_le_bytes());
+        }
+        for text in ["Song", "Someone", "2026 Someone"] {
+            let mut field = text.as_bytes().to_vec();
+            field.resize(32, 0);
+            buf.extend_from_slice(&field);
+        }
+        buf.extend_from_slice(&16639u16.to_le_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        buf.extend_from_slice(&19997u16.to_le_bytes());
+        buf.extend_from_slice(&[1, expansion, 0, 0, 0, 0]);
+        buf.extend_from_slice(&[0x60; 3]);
+        buf
+    }
+
+    fn nsfe(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut buf = b"NSFE".to_vec();
+        for (id, data) in chunks {
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(*id);
+            buf.extend_from_slice(data);
+        }
+        buf
+    }
+
+    fn read(buf: &[u8]) -> Result<Nsf, LoadError> {
+        Nsf::read_from(&mut &buf[..])
+    }
+
+    fn problem(buf: &[u8]) -> String {
+        match read(buf) {
+            Err(LoadError::BadNsf(problem)) => problem,
+            other => panic!("{:?}", other.map(|nsf| nsf.name)),
+        }
+    }
+
+    #[test]
+    fn nsf_header_fields() {
+        let nsf = read(&nsf_header(0x8000, 0x05)).unwrap();
+
+        assert_eq!((nsf.version, nsf.total_songs, nsf.starting_song), (1, 3, 2));
+        assert_eq!((nsf.load_addr, nsf.init_addr, nsf.play_addr), (0x8000, 0x8003, 0x8006));
+        assert_eq!((nsf.name.as_str(), nsf.artist.as_str()), ("Song", "Someone"));
+        assert_eq!(nsf.copyright, "2026 Someone");
+        assert_eq!((nsf.play_speed_ntsc, nsf.play_speed_pal), (16639, 19997));
+        assert_eq!(nsf.region, NsfRegion::PAL);
+        assert_eq!(nsf.expansion, NsfExpansion { vrc6: true, fds: true, ..Default::default() });
+        assert!(!nsf.is_bankswitched());
+        assert_eq!(nsf.data, [0x60; 3]);
+    }
+
+    #[test]
+    fn nsfe_chunks() {
+        let info = [0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x00, 0x00, 0x02, 0x01];
+        let nsf = read(&nsfe(&[
+            (b"INFO", &info),
+            (b"DATA", &[0x60]),
+            (b"auth", b"Song\0Someone\0\0"),
+            (b"tlbl", b"One\0Two\0Three\0"),
+            (b"time", &[0xE8, 0x03, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]),
+            (b"xtra", b"skipped"),
+            (b"NEND", &[]),
+        ])).unwrap();
+
+        assert_eq!((nsf.total_songs, nsf.starting_song), (2, 2));
+        assert_eq!((nsf.name.as_str(), nsf.artist.as_str()), ("Song", "Someone"));
+        assert_eq!(nsf.track_labels, ["One", "Two"]);
+        assert_eq!(nsf.track_times, [1000, -1]);
+        assert_eq!((nsf.play_speed_ntsc, nsf.data.len()), (NTSC_PLAY_SPEED, 1));
+    }
+
+    #[test]
+    fn bad_files() {
+        assert!(problem(b"NESN").contains("signature"));
+        assert!(problem(&nsf_header(0x8000, 0)[..0x7f]).contains("too short"));
+
+        let info = [0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0x00, 0x00];
+        assert!(problem(&nsfe(&[(b"INFO", &info), (b"NEND", &[])])).contains("DATA"));
+        assert!(problem(&nsfe(&[(b"INFO", &info[..4])])).contains("INFO chunk is too short"));
+        assert!(problem(&nsfe(&[(b"INFO", &info), (b"VRC7", &[])])).contains("VRC7"));
+
+        let mut cut_short = nsfe(&[(b"INFO", &info), (b"DATA", &[0x60; 8])]);
+        cut_short.truncate(cut_short.len() - 1);
+        assert!(problem(&cut_short).contains("DATA chunk runs past the end"));
+    }
+
+    #[test]
+    fn low_load_address() {
+        assert!(problem(&nsf_header(0x6000, 0)).contains("load address $6000 is below $8000"));
+
+        // FDS tunes run from RAM at $6000, but not lower.
+        assert_eq!(read(&nsf_header(0x6000, 0x04)).unwrap().load_addr, 0x6000);
+        assert!(problem(&nsf_header(0x5000, 0x04)).contains("below $6000"));
+    }
+}
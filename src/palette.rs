@@ -1,6 +1,9 @@
 use std::io::Read;
 use std::fs::File;
 use std::ops::{Index, IndexMut};
+use std::path::Path;
+
+use crate::LoadError;
 
 
 #[derive(Clone, Copy)]
@@ -11,6 +14,7 @@ pub struct Color {
 }
 
 /// Represents a palette in `.pal` format. 64 entries stored as: R G B. 192 bytes total.
+#[derive(Clone)]
 pub struct Palette([Color; 64]);
 
 impl Index<usize> for Palette {
@@ -32,9 +36,12 @@ impl Palette {
         Self([Color { r: 0, g: 0, b: 0 } ; 64])
     }
 
-    pub fn read_data<R: Read>(src: &mut R) -> Result<Self, ()> {
-        let mut data = [0u8; 192];
-        src.read_exact(&mut data).or_else(|_| Err(()))?;
+    pub fn read_data<R: Read>(src: &mut R) -> Result<Self, LoadError> {
+        let mut data = Vec::new();
+        src.take(192).read_to_end(&mut data)?;
+        if data.len() < 192 {
+            return Err(LoadError::TruncatedPalette { expected: 192, found: data.len() });
+        }
 
         let mut p = Palette::new();
         for i in 0..64 {
@@ -47,8 +54,10 @@ impl Palette {
         Ok(p)
     }
 
-    pub fn from_file(filename: &str) -> Result<Self, ()> {
-        let mut f = File::open(filename).or_else(|_| Err(()))?;
-        Self::read_data(&mut f)
+    pub fn from_file<P: AsRef<Path>>(filename: P) -> Result<Self, LoadError> {
+        let path = filename.as_ref();
+        let mut f = File::open(path)
+            .map_err(|e| LoadError::Io(Some(path.to_owned()), e))?;
+        Self::read_data(&mut f).map_err(|e| e.with_path(path.to_owned()))
     }
 }
\ No newline at end of file
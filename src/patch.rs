@@ -0,0 +1,432 @@
+//! IPS, BPS and UPS soft patches, applied to the ROM in memory.
+
+use std::path::{Path, PathBuf};
+
+use crate::checksum;
+use crate::LoadError;
+
+const EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
+/// Largest patched ROM, well past any real cart, so a bad size in a BPS or
+/// UPS patch can't ask for all memory.
+const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Patches next to `rom`: `<name>.ips`, `.bps` or `.ups`, then numbered
+/// ones to stack on top like `<name>.ips1`, `<name>.bps2`, in that order.
+pub(crate) fn find_patches(rom: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+
+    for n in 0.. {
+        let suffix = if n == 0 { String::new() } else { n.to_string() };
+        let before = found.len();
+
+        for ext in EXTENSIONS.iter() {
+            let patch = rom.with_extension(format!("{}{}", ext, suffix));
+            if patch.is_file() {
+                found.push(patch);
+            }
+        }
+
+        if n > 0 && found.len() == before {
+            break;
+        }
+    }
+
+    found
+}
+
+/// Apply `patch`, read from `path`, to `rom`.
+pub(crate) fn apply(rom: &[u8], patch: &[u8], path: &Path) -> Result<Vec<u8>, LoadError> {
+    let bad = |problem: &str| LoadError::BadPatch(path.to_owned(), problem.to_owned());
+
+    let result = match patch {
+        [b'P', b'A', b'T', b'C', b'H', ..] => apply_ips(rom, &patch[5..]),
+        [b'B', b'P', b'S', b'1', ..] => apply_bps(rom, patch),
+        [b'U', b'P', b'S', b'1', ..] => apply_ups(rom, patch),
+        _ => return Err(bad("not an IPS, BPS or UPS patch")),
+    };
+
+    result.map_err(|e| match e {
+        PatchError::Bad(problem) => bad(problem),
+        PatchError::WrongSource => LoadError::PatchMismatch(path.to_owned()),
+    })
+}
+
+/// IPS patch turning `old` into `new`, for saving changes like writes to
+/// a disk image. Both must be the same length, under 16 MiB.
+pub(crate) fn make_diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+
+    let mut at = 0;
+    while at < new.len() {
+        if old[at] == new[at] {
+            at += 1;
+            continue;
+        }
+
+        // An offset spelling "EOF" would end the patch, start a byte early.
+        let start = if at == 0x45_4F46 { at - 1 } else { at };
+        let mut end = at;
+        while end < new.len() && end - start < 0xFFFF && old[end] != new[end] {
+            end += 1;
+        }
+
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&new[start..end]);
+        at = end;
+    }
+
+    patch.extend_from_slice(b"EOF");
+    patch
+}
+
+/// Apply a [`make_diff`] patch to `old`.
+pub(crate) fn apply_diff(old: &[u8], diff: &[u8]) -> Option<Vec<u8>> {
+    match diff {
+        [b'P', b'A', b'T', b'C', b'H', ..] => apply_ips(old, &diff[5..]).ok(),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PatchError {
+    Bad(&'static str),
+    /// The source checksum doesn't match: it's a patch for another ROM.
+    WrongSource,
+}
+
+const CUT_SHORT: PatchError = PatchError::Bad("cut short");
+
+/// Reads through a patch, failing at its end.
+struct Cursor<'p> {
+    data: &'p [u8],
+    at: usize,
+}
+
+impl<'p> Cursor<'p> {
+    fn bytes(&mut self, len: usize) -> Result<&'p [u8], PatchError> {
+        let end = self.at.checked_add(len).ok_or(CUT_SHORT)?;
+        let bytes = self.data.get(self.at..end).ok_or(CUT_SHORT)?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<usize, PatchError> {
+        let b = self.bytes(2)?;
+        Ok((b[0] as usize) << 8 | b[1] as usize)
+    }
+
+    fn u24_be(&mut self) -> Result<usize, PatchError> {
+        let b = self.bytes(3)?;
+        Ok((b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    /// BPS and UPS variable length number: 7 bits at a time, least
+    /// significant first, with the top bit marking the last byte.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let overflow = PatchError::Bad("number too large");
+        let (mut value, mut shift) = (0usize, 1usize);
+
+        loop {
+            let b = self.u8()?;
+            value = ((b & 0x7F) as usize).checked_mul(shift)
+                .and_then(|v| v.checked_add(value))
+                .ok_or(overflow)?;
+            if b & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(overflow)?;
+            value = value.checked_add(shift).ok_or(overflow)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut patch = Cursor { data: records, at: 0 };
+
+    loop {
+        if patch.data.get(patch.at..patch.at + 3) == Some(b"EOF") {
+            patch.at += 3;
+            break;
+        }
+
+        let offset = patch.u24_be()?;
+        let (len, fill) = match patch.u16_be()? {
+            // Run length encoded: a count, then the byte to repeat.
+            0 => (patch.u16_be()?, Some(patch.u8()?)),
+            len => (len, None),
+        };
+
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        match fill {
+            Some(value) => out[offset..offset + len].fill(value),
+            None => out[offset..offset + len].copy_from_slice(patch.bytes(len)?),
+        }
+    }
+
+    // Optional size to truncate to, after the end marker.
+    if let Ok(size) = patch.u24_be() {
+        out.truncate(size);
+    }
+
+    Ok(out)
+}
+
+/// Checks the checksums at the end of a BPS or UPS patch, returning the
+/// target's and the patch minus its footer.
+fn check_footer<'p>(rom: &[u8], patch: &'p [u8]) -> Result<(u32, &'p [u8]), PatchError> {
+    if patch.len() < 16 {
+        return Err(CUT_SHORT);
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let crc = |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+
+    if checksum::crc32(&[&patch[..patch.len() - 4]]) != crc(8) {
+        return Err(PatchError::Bad("patch is corrupt"));
+    }
+    if checksum::crc32(&[rom]) != crc(0) {
+        return Err(PatchError::WrongSource);
+    }
+
+    Ok((crc(4), body))
+}
+
+fn check_target(out: &[u8], crc32: u32) -> Result<(), PatchError> {
+    match checksum::crc32(&[out]) == crc32 {
+        true => Ok(()),
+        false => Err(PatchError::Bad("patched ROM doesn't match the expected checksum")),
+    }
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body) = check_footer(rom, patch)?;
+    let mut patch = Cursor { data: body, at: 4 };
+
+    let source_size = patch.number()?;
+    let target_size = patch.number()?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Bad("patched ROM is too large"));
+    }
+    if source_size != rom.len() {
+        return Err(PatchError::WrongSource);
+    }
+
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+
+    // Hunks: skip ahead, then XOR bytes in up to a zero.
+    let mut offset = 0usize;
+    while patch.at < body.len() {
+        offset = offset.checked_add(patch.number()?).ok_or(PatchError::Bad("offset too large"))?;
+        loop {
+            let x = patch.u8()?;
+            if offset < out.len() {
+                out[offset] ^= x;
+            }
+            offset += 1;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body) = check_footer(rom, patch)?;
+    let mut patch = Cursor { data: body, at: 4 };
+    let out_of_bounds = || PatchError::Bad("copies from out of bounds");
+
+    let source_size = patch.number()?;
+    let target_size = patch.number()?;
+    let metadata_size = patch.number()?;
+    patch.bytes(metadata_size)?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Bad("patched ROM is too large"));
+    }
+    if source_size != rom.len() {
+        return Err(PatchError::WrongSource);
+    }
+
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0usize, 0usize);
+
+    // Move a relative offset: the lowest bit is the sign.
+    let seek = |at: usize, d: usize| match d & 1 {
+        0 => at.checked_add(d >> 1),
+        _ => at.checked_sub(d >> 1),
+    };
+
+    while patch.at < body.len() {
+        let action = patch.number()?;
+        let len = (action >> 2) + 1;
+        // Checked up front, so a bogus length can't grow `out` first.
+        if len > target_size - out.len() {
+            return Err(PatchError::Bad("writes past the end of the ROM"));
+        }
+
+        match action & 3 {
+            // Source read: same position in the source.
+            0 => {
+                let from = out.len();
+                let bytes = rom.get(from..from + len).ok_or_else(out_of_bounds)?;
+                out.extend_from_slice(bytes);
+            },
+            // Target read: bytes from the patch.
+            1 => out.extend_from_slice(patch.bytes(len)?),
+            // Source copy: from anywhere in the source.
+            2 => {
+                source_at = seek(source_at, patch.number()?).ok_or_else(out_of_bounds)?;
+                let bytes = rom.get(source_at..source_at + len).ok_or_else(out_of_bounds)?;
+                out.extend_from_slice(bytes);
+                source_at += len;
+            },
+            // Target copy: from what's been written, overlapping allowed.
+            _ => {
+                target_at = seek(target_at, patch.number()?).ok_or_else(out_of_bounds)?;
+                for _ in 0..len {
+                    let b = *out.get(target_at).ok_or_else(out_of_bounds)?;
+                    out.push(b);
+                    target_at += 1;
+                }
+            },
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(PatchError::Bad("patched ROM has the wrong size"));
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(out: &mut Vec<u8>, mut n: usize) {
+        loop {
+            let bits = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | bits);
+                return;
+            }
+            out.push(bits);
+            n -= 1;
+        }
+    }
+
+    /// Adds the BPS/UPS footer of checksums.
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&checksum::crc32(&[source]).to_le_bytes());
+        patch.extend_from_slice(&checksum::crc32(&[target]).to_le_bytes());
+        let crc = checksum::crc32(&[&patch]);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    fn apply_ok(rom: &[u8], patch: &[u8]) -> Vec<u8> {
+        apply(rom, patch, Path::new("test.patch")).unwrap()
+    }
+
+    #[test]
+    fn ips_records() {
+        let mut patch = b"PATCH".to_vec();
+        // Two bytes at 1, then three 0xAA run length encoded past the end.
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0x11, 0x22]);
+        patch.extend_from_slice(&[0, 0, 4, 0, 0, 0, 3, 0xAA]);
+        patch.extend_from_slice(b"EOF");
+
+        assert_eq!(apply_ok(&[0; 4], &patch), [0, 0x11, 0x22, 0, 0xAA, 0xAA, 0xAA]);
+
+        // Truncated to a size after the end marker.
+        patch.extend_from_slice(&[0, 0, 2]);
+        assert_eq!(apply_ok(&[0; 4], &patch), [0, 0x11]);
+    }
+
+    #[test]
+    fn ips_diff_around_eof_offset() {
+        let old = vec![0u8; 0x45_4F48];
+        let mut new = old.clone();
+        new[0x45_4F46] = 1;
+
+        let diff = make_diff(&old, &new);
+        assert_eq!(&diff[5..8], &[0x45, 0x4F, 0x45]);
+        assert_eq!(apply_diff(&old, &diff), Some(new));
+    }
+
+    #[test]
+    fn bps_copies() {
+        let source = [10, 20, 30, 40];
+        let target = [30, 40, 9, 30, 40, 9, 30, 40];
+
+        let mut patch = b"BPS1".to_vec();
+        for n in [source.len(), target.len(), 0] {
+            number(&mut patch, n);
+        }
+        // Source copy of two bytes from +2.
+        number(&mut patch, (1 << 2) | 2);
+        number(&mut patch, 2 << 1);
+        // Target read of one byte.
+        number(&mut patch, 1);
+        patch.push(9);
+        // Target copy of five bytes from 0, overlapping what it writes.
+        number(&mut patch, (4 << 2) | 3);
+        number(&mut patch, 0);
+
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply_ok(&source, &patch), target);
+        assert!(matches!(
+            apply(&[1, 2, 3, 4], &patch, Path::new("test.patch")),
+            Err(LoadError::PatchMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn bps_rejects_long_copy() {
+        let source = [10, 20, 30, 40];
+        let target = [10, 20];
+
+        let mut patch = b"BPS1".to_vec();
+        for n in [source.len(), target.len(), 0] {
+            number(&mut patch, n);
+        }
+        number(&mut patch, 1 << 2);
+        // Target copy far past the target size.
+        number(&mut patch, ((1 << 40) << 2) | 3);
+        number(&mut patch, 0);
+
+        let patch = with_footer(patch, &source, &target);
+        assert!(matches!(
+            apply(&source, &patch, Path::new("test.patch")),
+            Err(LoadError::BadPatch(..))
+        ));
+    }
+
+    #[test]
+    fn ups_xor_past_source() {
+        let source = [1, 2];
+        let target = [1, 7, 3, 4];
+
+        let mut patch = b"UPS1".to_vec();
+        number(&mut patch, source.len());
+        number(&mut patch, target.len());
+        number(&mut patch, 1);
+        patch.extend_from_slice(&[2 ^ 7, 3, 4, 0]);
+
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply_ok(&source, &patch), target);
+    }
+}
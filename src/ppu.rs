@@ -2,9 +2,19 @@
 
 use std::fmt::Debug;
 
-use crate::{Bus, palette::Palette};
+use crate::palette::Palette;
+use crate::state::{SaveState, StateError, StateReader, StateWriter};
 // #![allow(non_snake_case)]
 
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+/// Frames per second: 262 lines of 341 dots at a 5.369318 MHz dot clock,
+/// one dot shorter every other frame.
+pub const FRAME_RATE_NTSC: f64 = 60.0988;
+/// 312 lines of 341 dots at 5.320342 MHz.
+pub const FRAME_RATE_PAL: f64 = 50.007;
+
 pub struct Ppu {
     /// `$2000` Write
     ppu_ctrl: RegPPUCtrl,
@@ -33,6 +43,11 @@ pub struct Ppu {
     clock_count: usize,
     scanline: usize,
     scanline_cycle: usize,
+    /// Frames started so far, counted at the start of vblank.
+    frame_count: u64,
+
+    /// Picture as palette indices, row by row.
+    frame: Box<[u8]>,
 
     pub nmi_signal: bool,
 }
@@ -43,6 +58,11 @@ impl Ppu {
             "resources/ntscpalette.pal"
         ).unwrap();
 
+        Self::with_palette(color_palette)
+    }
+
+    /// A PPU using `color_palette` instead of the one in `resources/`.
+    pub fn with_palette(color_palette: Palette) -> Self {
         Self {
             ppu_ctrl: RegPPUCtrl::default(),
             ppu_mask: RegPPUMask::default(),
@@ -57,14 +77,17 @@ impl Ppu {
             clock_count: 0,
             scanline: 261,
             scanline_cycle: 0,
+            frame_count: 0,
+            frame: vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
             nmi_signal: false,
         }
     }
         
-    pub fn step(&mut self, bus: &mut Bus) {
+    pub fn step(&mut self) {
         match self.scanline {
             241 => {
                 if self.scanline_cycle == 1 {
+                    self.frame_count += 1;
                     self.ppu_status.vblank = true;
                     self.nmi_signal = self.ppu_status.vblank &&
                         self.ppu_ctrl.nmi_enable;
@@ -99,6 +122,36 @@ impl Ppu {
         self.scanline %= 262;
     }
 
+    /// Current picture, `SCREEN_WIDTH * SCREEN_HEIGHT` palette indices.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Colors for the palette indices in [`Ppu::frame`].
+    pub fn palette(&self) -> &Palette {
+        &self.color_palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.color_palette = palette;
+    }
+
+    /// Number of times vblank has started since power-on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Beam position: scanline `0..262`, with 240 onwards being vblank.
+    pub fn scanline(&self) -> usize {
+        self.scanline
+    }
+
+    /// Beam position within the scanline, `0..341`. Visible pixels are
+    /// output on cycles 1-256.
+    pub fn scanline_cycle(&self) -> usize {
+        self.scanline_cycle
+    }
+
     pub fn read(&mut self, addr: u16) -> u8 {
         match addr {
             0x2000 => unimplemented!(),
@@ -135,6 +188,51 @@ impl Ppu {
     }
 }
 
+impl SaveState for Ppu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8((&self.ppu_ctrl).into());
+        w.u8((&self.ppu_mask).into());
+        w.u8((&self.ppu_status).into());
+        w.u8(self.oam_addr);
+        w.u8(self.ppu_scroll.x);
+        w.u8(self.ppu_scroll.y);
+        w.u8(self.ppu_scroll.counter as u8);
+        w.u16(self.ppu_addr.value);
+        w.u8(self.ppu_addr.counter);
+        w.u8(self.ppu_data);
+        w.u8(self.oam_dma);
+        w.bytes(&self.vram);
+        w.u32(self.clock_count as u32);
+        w.u16(self.scanline as u16);
+        w.u16(self.scanline_cycle as u16);
+        w.u64(self.frame_count);
+        w.bytes(&self.frame);
+        w.bool(self.nmi_signal);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ppu_ctrl = r.u8()?.into();
+        self.ppu_mask = r.u8()?.into();
+        self.ppu_status = r.u8()?.into();
+        self.oam_addr = r.u8()?;
+        self.ppu_scroll.x = r.u8()?;
+        self.ppu_scroll.y = r.u8()?;
+        self.ppu_scroll.counter = r.u8()? as usize;
+        self.ppu_addr.value = r.u16()?;
+        self.ppu_addr.counter = r.u8()?;
+        self.ppu_data = r.u8()?;
+        self.oam_dma = r.u8()?;
+        r.bytes_into(&mut self.vram)?;
+        self.clock_count = r.u32()? as usize;
+        self.scanline = r.u16()? as usize;
+        self.scanline_cycle = r.u16()? as usize;
+        self.frame_count = r.u64()?;
+        r.bytes_into(&mut self.frame)?;
+        self.nmi_signal = r.bool()?;
+        Ok(())
+    }
+}
+
 impl Debug for Ppu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Ppu")
@@ -197,8 +295,14 @@ struct RegPPUCtrl {
 }
 
 impl From<&RegPPUCtrl> for u8 {
-    fn from(_: &RegPPUCtrl) -> Self {
-        todo!()
+    fn from(val: &RegPPUCtrl) -> Self {
+        val.nametable_select |
+        ((val.increment_mode        as u8) << 2) |
+        ((val.sprite_tile_select    as u8) << 3) |
+        ((val.bg_tile_select        as u8) << 4) |
+        ((val.sprite_height         as u8) << 5) |
+        ((val.ppu_master_slave      as u8) << 6) |
+        ((val.nmi_enable            as u8) << 7)
     }
 }
 
@@ -252,7 +356,14 @@ struct RegPPUMask {
 
 impl From<&RegPPUMask> for u8 {
     fn from(val: &RegPPUMask) -> Self {
-        todo!()
+        (val.greyscale as u8) |
+        ((val.bg_left_col_enable        as u8) << 1) |
+        ((val.sprite_left_col_enable    as u8) << 2) |
+        ((val.bg_enable                 as u8) << 3) |
+        ((val.sprite_enable             as u8) << 4) |
+        ((val.ce_r                      as u8) << 5) |
+        ((val.ce_g                      as u8) << 6) |
+        ((val.ce_b                      as u8) << 7)
     }
 }
 
@@ -311,6 +422,16 @@ impl Default for RegPPUStatus {
     }
 }
 
+impl From<u8> for RegPPUStatus {
+    fn from(b: u8) -> Self {
+        Self {
+            overflow: (b & 0b_0010_0000) != 0,
+            sprite0_hit: (b & 0b_0100_0000) != 0,
+            vblank: (b & 0b_1000_0000) != 0,
+        }
+    }
+}
+
 impl From<&RegPPUStatus> for u8 {
     fn from(val: &RegPPUStatus) -> Self {
         {
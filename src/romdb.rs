@@ -0,0 +1,298 @@
+//! Game database for fixing wrong iNES headers, looked up by the checksum
+//! of PRG and CHR ROM. None is built in: it's read from a file in the NES
+//! 2.0 XML database format, like the full `nes20db.xml`, of which only
+//! these elements and attributes are used:
+//!
+//! ```xml
+//! <game>
+//!   <rom size="40960" crc32="0123ABCD" sha1="..."/>
+//!   <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+//!   <prgram size="8192"/>  <prgnvram size="8192"/>
+//!   <chrram size="8192"/>  <chrnvram size="8192"/>
+//!   <console type="0" region="0"/>
+//!   <expansion type="1"/>
+//! </game>
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+
+use crate::{CartData, LoadError, Mirroring, TVSystem, Timing};
+
+/// What the database knows about one dump.
+#[derive(Debug, Clone)]
+pub struct DbEntry {
+    pub crc32: u32,
+    /// Not every entry has one; CRC-32 alone is trusted then.
+    pub sha1: Option<[u8; 20]>,
+    pub mapper: u16,
+    pub submapper: u8,
+    /// `None` when the mapper controls mirroring.
+    pub mirroring: Option<Mirroring>,
+    pub four_screen: bool,
+    pub battery: bool,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Option<Timing>,
+    pub expansion_device: Option<u8>,
+}
+
+/// A header field the database disagreed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderFix {
+    pub field: &'static str,
+    pub header: String,
+    pub database: String,
+}
+
+impl Display for HeaderFix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.header, self.database)
+    }
+}
+
+pub struct RomDb {
+    /// Several dumps can share a CRC-32, told apart by SHA-1.
+    entries: HashMap<u32, Vec<DbEntry>>,
+}
+
+impl RomDb {
+    /// Load a database file, like the full `nes20db.xml`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| LoadError::Io(Some(path.to_owned()), e))?;
+        Ok(Self::from_xml(&text))
+    }
+
+    /// Parse NES 2.0 XML database text. Games without a usable `<rom>` or
+    /// `<pcb>` are skipped.
+    pub fn from_xml(text: &str) -> Self {
+        let mut entries: HashMap<u32, Vec<DbEntry>> = HashMap::new();
+
+        let mut rest = text;
+        while let Some(start) = rest.find("<game>") {
+            // Skip games in comments.
+            if let Some(comment) = rest[..start].find("<!--") {
+                let after = &rest[comment + 4..];
+                rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+                continue;
+            }
+
+            let game = &rest[start..];
+            let end = game.find("</game>").unwrap_or(game.len());
+            if let Some(entry) = parse_game(&game[..end]) {
+                entries.entry(entry.crc32).or_default().push(entry);
+            }
+            rest = &game[end..];
+        }
+
+        Self { entries }
+    }
+
+    /// Number of games.
+    pub fn len(&self) -> usize {
+        self.entries.values().map(|e| e.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, crc32: u32, sha1: &[u8; 20]) -> Option<&DbEntry> {
+        self.entries.get(&crc32)?
+            .iter()
+            .find(|e| e.sha1.is_none_or(|s| &s == sha1))
+    }
+
+    /// Override `cart`'s header fields with the database's, returning what
+    /// changed.
+    pub(crate) fn apply(&self, cart: &mut CartData) -> Vec<HeaderFix> {
+        let entry = match self.lookup(cart.crc32, &cart.sha1) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+
+        let mut fixes = Vec::new();
+        let mut fix = |field, header: String, database: String| {
+            if header != database {
+                fixes.push(HeaderFix { field, header, database });
+            }
+        };
+
+        fix("mapper", cart.mapper_id.to_string(), entry.mapper.to_string());
+        cart.mapper_id = entry.mapper;
+        fix("submapper", cart.submapper_id.to_string(), entry.submapper.to_string());
+        cart.submapper_id = entry.submapper;
+
+        if let Some(mirroring) = entry.mirroring {
+            fix("mirroring", mirroring_name(cart), match entry.four_screen {
+                true => "four-screen".to_owned(),
+                false => format!("{:?}", mirroring).to_lowercase(),
+            });
+            cart.mirroring = mirroring;
+            cart.four_screen_vram_layout = entry.four_screen;
+        }
+
+        fix("battery", cart.sram_enable.to_string(), entry.battery.to_string());
+        cart.sram_enable = entry.battery;
+
+        let sizes = [
+            ("PRG RAM", &mut cart.prg_ram_size, entry.prg_ram_size),
+            ("PRG NVRAM", &mut cart.prg_nvram_size, entry.prg_nvram_size),
+            ("CHR RAM", &mut cart.chr_ram_size, entry.chr_ram_size),
+            ("CHR NVRAM", &mut cart.chr_nvram_size, entry.chr_nvram_size),
+        ];
+        for (field, size, db_size) in sizes {
+            fix(field, size.to_string(), db_size.to_string());
+            *size = db_size;
+        }
+
+        if let Some(timing) = entry.timing {
+            fix("region", format!("{:?}", cart.timing), format!("{:?}", timing));
+            cart.timing = timing;
+            cart.tv_system = match timing {
+                Timing::Pal | Timing::Dendy => TVSystem::PAL,
+                Timing::Ntsc | Timing::MultiRegion => TVSystem::NTSC,
+            };
+        }
+
+        if let Some(device) = entry.expansion_device {
+            fix("input device", cart.expansion_device.to_string(), device.to_string());
+            cart.expansion_device = device;
+        }
+
+        fixes
+    }
+}
+
+fn mirroring_name(cart: &CartData) -> String {
+    match cart.four_screen_vram_layout {
+        true => "four-screen".to_owned(),
+        false => format!("{:?}", cart.mirroring).to_lowercase(),
+    }
+}
+
+fn parse_game(game: &str) -> Option<DbEntry> {
+    let mut entry = DbEntry {
+        crc32: 0,
+        sha1: None,
+        mapper: 0,
+        submapper: 0,
+        mirroring: None,
+        four_screen: false,
+        battery: false,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+        timing: None,
+        expansion_device: None,
+    };
+    let (mut has_rom, mut has_pcb) = (false, false);
+
+    for (name, attrs) in tags(game) {
+        let get = |key: &str| attr(attrs, key);
+        let size = || get("size").and_then(|s| s.parse().ok()).unwrap_or(0);
+
+        match name {
+            "rom" => {
+                entry.crc32 = u32::from_str_radix(get("crc32")?, 16).ok()?;
+                entry.sha1 = get("sha1").and_then(parse_sha1);
+                has_rom = true;
+            },
+            "pcb" => {
+                entry.mapper = get("mapper")?.parse().ok()?;
+                entry.submapper = get("submapper").and_then(|s| s.parse().ok()).unwrap_or(0);
+                entry.battery = get("battery") == Some("1");
+                match get("mirroring") {
+                    Some("H") => entry.mirroring = Some(Mirroring::Horizontal),
+                    Some("V") => entry.mirroring = Some(Mirroring::Vertical),
+                    Some("4") => {
+                        entry.mirroring = Some(Mirroring::Vertical);
+                        entry.four_screen = true;
+                    },
+                    _ => {},
+                }
+                has_pcb = true;
+            },
+            "prgram" => entry.prg_ram_size = size(),
+            "prgnvram" => entry.prg_nvram_size = size(),
+            "chrram" => entry.chr_ram_size = size(),
+            "chrnvram" => entry.chr_nvram_size = size(),
+            "console" => {
+                entry.timing = match get("region") {
+                    Some("0") => Some(Timing::Ntsc),
+                    Some("1") => Some(Timing::Pal),
+                    Some("2") => Some(Timing::MultiRegion),
+                    Some("3") => Some(Timing::Dendy),
+                    _ => None,
+                };
+            },
+            "expansion" => entry.expansion_device = get("type").and_then(|s| s.parse().ok()),
+            _ => {},
+        }
+    }
+
+    match has_rom && has_pcb {
+        true => Some(entry),
+        false => None,
+    }
+}
+
+/// Start tags in `xml` as (name, attribute text), skipping comments.
+fn tags(xml: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut rest = xml;
+
+    std::iter::from_fn(move || loop {
+        let start = rest.find('<')?;
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let end = rest.find('>')?;
+        let tag = rest[1..end].trim_end_matches('/').trim();
+        rest = &rest[end + 1..];
+
+        if tag.starts_with('/') || tag.starts_with('?') {
+            continue;
+        }
+
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        return Some((&tag[..name_end], &tag[name_end..]));
+    })
+}
+
+/// Value of `key="value"` in a tag's attribute text.
+fn attr<'a>(attrs: &'a str, key: &str) -> Option<&'a str> {
+    let mut rest = attrs;
+    while let Some(eq) = rest.find("=\"") {
+        let name = rest[..eq].trim();
+        let value = &rest[eq + 2..];
+        let end = value.find('"')?;
+        if name == key {
+            return Some(&value[..end]);
+        }
+        rest = &value[end + 1..];
+    }
+    None
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+
+    let mut sha1 = [0u8; 20];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(sha1)
+}
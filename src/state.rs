@@ -0,0 +1,162 @@
+use std::fmt::{self, Display};
+
+/// Save state header: magic, then a format version bumped whenever any
+/// component's layout changes.
+const STATE_MAGIC: &[u8; 4] = b"JDST";
+const STATE_VERSION: u8 = 5;
+
+/// Why a save state couldn't be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// Doesn't start with `JDST`.
+    BadMagic,
+    /// Saved by a version of the emulator with another layout.
+    WrongVersion { found: u8 },
+    /// Ends before everything was read.
+    Truncated,
+    /// Doesn't fit this machine, like RAM of a different size or bytes
+    /// left over, usually because another cart is inserted.
+    Mismatch,
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::WrongVersion { found } => {
+                write!(f, "save state is version {}, this emulator reads {}", found, STATE_VERSION)
+            },
+            StateError::Truncated => write!(f, "save state is cut off"),
+            StateError::Mismatch => write!(f, "save state is for a different game"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Components that can be snapshotted. `load_state` reads back exactly
+/// what `save_state` wrote, in the same order.
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Little-endian binary writer for save states.
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buf = STATE_MAGIC.to_vec();
+        buf.push(STATE_VERSION);
+        Self { buf }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.u32(v.to_bits());
+    }
+
+    /// Length-prefixed, so loading can check it fits.
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+}
+
+pub struct StateReader<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> StateReader<'d> {
+    /// Fails if `data` isn't a save state of this version.
+    pub fn new(data: &'d [u8]) -> Result<Self, StateError> {
+        match data.split_at(STATE_MAGIC.len().min(data.len())) {
+            (magic, _) if magic != STATE_MAGIC => Err(StateError::BadMagic),
+            (_, [STATE_VERSION, rest @ ..]) => Ok(Self { data: rest }),
+            (_, [found, ..]) => Err(StateError::WrongVersion { found: *found }),
+            (_, []) => Err(StateError::Truncated),
+        }
+    }
+
+    /// Fails unless everything was read, i.e. the layout matched.
+    pub fn finish(self) -> Result<(), StateError> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(StateError::Mismatch),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'d [u8], StateError> {
+        if self.data.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut a = [0; N];
+        a.copy_from_slice(self.take(N)?);
+        Ok(a)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    /// Read into `dst`, which must be exactly as long as what was saved.
+    pub fn bytes_into(&mut self, dst: &mut [u8]) -> Result<(), StateError> {
+        let len = self.u32()? as usize;
+        if len != dst.len() {
+            return Err(StateError::Mismatch);
+        }
+        dst.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! UNIF ROMs: a board name and ROM chunks instead of an iNES header.
+//! See: <https://www.nesdev.org/wiki/UNIF>
+
+use crate::checksum;
+use crate::{CartData, ConsoleType, LoadError, Mirroring, TVSystem, Timing};
+
+pub(crate) const UNIF_MAGIC: &[u8] = b"UNIF";
+
+/// Chunks start after the magic, the revision and padding.
+const HEADER_LEN: usize = 32;
+
+/// Prefixes naming who made a board, not part of the board name itself.
+const BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-"];
+
+/// iNES mapper and submapper for a board name, without its `NES-` style
+/// prefix.
+pub(crate) fn board_mapper(board: &str) -> Option<(u16, u8)> {
+    let name = BOARD_PREFIXES.iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board)
+        .to_ascii_uppercase();
+
+    let mapper = match name.as_str() {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "SAROM" | "SBROM" | "SCROM" | "SC1ROM" | "SFROM" | "SGROM" | "SJROM" | "SKROM"
+            | "SLROM" | "SL1ROM" | "SL2ROM" | "SL3ROM" | "SLRROM" | "SNROM" => (1, 0),
+        "SUROM" => (1, 1),
+        "SOROM" => (1, 2),
+        "SXROM" => (1, 4),
+        // Fixed 32k PRG, no bank switching of it.
+        "SEROM" | "SHROM" | "SH1ROM" => (1, 5),
+        "UNROM" | "UOROM" | "UN1ROM" => (2, 0),
+        "CNROM" => (3, 0),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TNROM" | "TR1ROM"
+            | "TSROM" | "TVROM" | "HKROM" => (4, 0),
+        "TKSROM" | "TLSROM" => (118, 0),
+        "TQROM" => (119, 0),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "AMROM" | "ANROM" | "AN1ROM" | "AOROM" => (7, 0),
+        "PEEOROM" | "PNROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, 0),
+        "CPROM" => (13, 0),
+        "BNROM" => (34, 0),
+        "GNROM" | "MHROM" => (66, 0),
+        _ => return None,
+    };
+    Some(mapper)
+}
+
+/// Parse a UNIF file into the same cart data an iNES header gives.
+pub(crate) fn read_unif(buf: &[u8]) -> Result<CartData, LoadError> {
+    if buf.len() < HEADER_LEN {
+        return Err(LoadError::TruncatedHeader);
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = Mirroring::Horizontal;
+    let mut four_screen_vram_layout = false;
+    let mut sram_enable = false;
+    let mut timing = Timing::Ntsc;
+
+    // Chunks: [id: 4 chars][length: u32][data]
+    let mut pos = HEADER_LEN;
+    while pos < buf.len() {
+        let header = buf.get(pos..pos + 8).ok_or(LoadError::TruncatedHeader)?;
+        let id = &header[0..4];
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let data = buf.get(pos + 8..).unwrap_or(&[]);
+        let data = data.get(..len).ok_or_else(|| LoadError::TruncatedChunk {
+            id: String::from_utf8_lossy(id).into_owned(),
+            expected: len,
+            found: data.len(),
+        })?;
+        pos += 8 + len;
+
+        match id {
+            b"MAPR" => board = Some(read_str(data)),
+            [b'P', b'R', b'G', n] | [b'C', b'H', b'R', n] => {
+                let chunks = if id[0] == b'P' { &mut prg_chunks } else { &mut chr_chunks };
+                if let Some(i) = (*n as char).to_digit(16) {
+                    chunks[i as usize] = Some(data);
+                }
+            },
+            b"MIRR" => {
+                // 5 is mapper controlled, the header value doesn't matter.
+                mirroring = match data.first() {
+                    Some(1) => Mirroring::Vertical,
+                    Some(2) => Mirroring::OneScreenLower,
+                    Some(3) => Mirroring::OneScreenUpper,
+                    _ => Mirroring::Horizontal,
+                };
+                four_screen_vram_layout = data.first() == Some(&4);
+            },
+            b"BATR" => sram_enable = data.first().is_some_and(|&b| b != 0),
+            b"TVCI" => {
+                timing = match data.first() {
+                    Some(1) => Timing::Pal,
+                    Some(2) => Timing::MultiRegion,
+                    _ => Timing::Ntsc,
+                };
+            },
+            _ => {},
+        }
+    }
+
+    let board = board.ok_or(LoadError::MissingChunk("MAPR"))?;
+    let (mapper_id, submapper_id) = board_mapper(&board)
+        .ok_or_else(|| LoadError::UnsupportedBoard(board.clone()))?;
+
+    // PRG0 to PRGF, and CHR0 to CHRF, are laid out in order of number.
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|c| c.iter().copied()).collect();
+    let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|c| c.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err(LoadError::MissingChunk("PRG0"));
+    }
+
+    // UNIF doesn't give RAM sizes: the board's if it's known to have more,
+    // else what iNES 1.0 assumes.
+    let chr_ram_size = if chr_rom.is_empty() { 8*1024 } else { 0 };
+    let ram_size = match (mapper_id, submapper_id) {
+        (1, 2) => 16*1024,
+        (1, 4) => 32*1024,
+        _ => 8*1024,
+    };
+    let (prg_ram_size, prg_nvram_size) = match (sram_enable, mapper_id, submapper_id) {
+        (false, ..) => (ram_size, 0),
+        // Only SOROM's second bank has the battery.
+        (true, 1, 2) => (8*1024, 8*1024),
+        (true, ..) => (0, ram_size),
+    };
+
+    let crc32 = checksum::crc32(&[&prg_rom, &chr_rom]);
+    let sha1 = checksum::sha1(&[&prg_rom, &chr_rom]);
+
+    Ok(CartData {
+        is_nes2: false,
+        prg_rom_page_count: prg_rom.len().div_ceil(16*1024) as u16,
+        chr_rom_page_count: chr_rom.len().div_ceil(8*1024) as u16,
+        mirroring,
+        sram_enable,
+        trainer_present: false,
+        four_screen_vram_layout,
+        mapper_id,
+        submapper_id,
+        is_vs_system: false,
+        console_type: ConsoleType::Nes,
+        ram_banks: (ram_size / (8*1024)) as u8,
+        tv_system: match timing {
+            Timing::Pal => TVSystem::PAL,
+            _ => TVSystem::NTSC,
+        },
+        timing,
+        prg_ram_size,
+        prg_nvram_size,
+        chr_ram_size,
+        chr_nvram_size: 0,
+        misc_rom_count: 0,
+        expansion_device: 0,
+        trainer: None,
+        prg_rom,
+        chr_rom,
+        extra_bytes: Vec::new(),
+        chr_ram: Vec::new(),
+        prg_ram: Vec::new(),
+        crc32,
+        sha1,
+        header_fixes: Vec::new(),
+        patches: Vec::new(),
+    })
+}
+
+/// Null-terminated string chunk.
+fn read_str(buf: &[u8]) -> String {
+    let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unif(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut buf = UNIF_MAGIC.to_vec();
+        buf.resize(HEADER_LEN, 0);
+        for (id, data) in chunks {
+            buf.extend_from_slice(*id);
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(data);
+        }
+        buf
+    }
+
+    #[test]
+    fn chunks() {
+        // Out of order, PRG and CHR still go by number.
+        let data = read_unif(&unif(&[
+            (b"PRG1", &[2; 0x4000]),
+            (b"MAPR", b"NES-SOROM\0"),
+            (b"CHR0", &[3; 0x2000]),
+            (b"PRG0", &[1; 0x4000]),
+            (b"BATR", &[1]),
+            (b"MIRR", &[1]),
+            (b"NAME", b"Test\0"),
+        ])).unwrap();
+
+        assert_eq!((data.mapper_id, data.submapper_id), (1, 2));
+        assert_eq!((data.prg_rom[0], data.prg_rom[0x4000], data.prg_rom.len()), (1, 2, 0x8000));
+        assert_eq!((data.chr_rom[0], data.chr_rom.len()), (3, 0x2000));
+        assert_eq!(data.mirroring, Mirroring::Vertical);
+        assert!(data.sram_enable);
+        assert_eq!((data.prg_ram_size, data.prg_nvram_size), (0x2000, 0x2000));
+    }
+
+    #[test]
+    fn defaults() {
+        let data = read_unif(&unif(&[(b"MAPR", b"NROM\0"), (b"PRG0", &[0; 0x4000])])).unwrap();
+
+        assert_eq!(data.mirroring, Mirroring::Horizontal);
+        assert!(!data.sram_enable);
+        assert_eq!((data.chr_rom.len(), data.chr_ram_size), (0, 0x2000));
+    }
+
+    #[test]
+    fn bad_files() {
+        let unknown = unif(&[(b"MAPR", b"UNL-NOTABOARD\0"), (b"PRG0", &[0; 0x4000])]);
+        assert!(matches!(
+            read_unif(&unknown),
+            Err(LoadError::UnsupportedBoard(board)) if board == "UNL-NOTABOARD"
+        ));
+
+        let no_board = unif(&[(b"PRG0", &[0; 0x4000])]);
+        assert!(matches!(read_unif(&no_board), Err(LoadError::MissingChunk("MAPR"))));
+
+        let mut truncated = unif(&[(b"MAPR", b"NROM\0"), (b"PRG0", &[0; 0x4000])]);
+        truncated.truncate(truncated.len() - 1);
+        assert!(matches!(
+            read_unif(&truncated),
+            Err(LoadError::TruncatedChunk { id, expected: 0x4000, found: 0x3fff }) if id == "PRG0"
+        ));
+    }
+}
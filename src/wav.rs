@@ -0,0 +1,87 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Sample encoding of a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFormat {
+    /// 16-bit signed integer PCM.
+    Pcm16,
+    /// 32-bit IEEE float.
+    Float32,
+}
+
+impl WavFormat {
+    fn bytes_per_sample(self) -> u32 {
+        match self {
+            WavFormat::Pcm16 => 2,
+            WavFormat::Float32 => 4,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 1,
+            WavFormat::Float32 => 3,
+        }
+    }
+}
+
+/// Mono RIFF/WAVE writer. Chunk sizes are patched in by `finalize`, so the
+/// target has to be seekable.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    format: WavFormat,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, sample_rate: u32, format: WavFormat) -> io::Result<Self> {
+        let block_align = format.bytes_per_sample();
+
+        inner.write_all(b"RIFF")?;
+        inner.write_all(&0u32.to_le_bytes())?;     // patched by finalize
+        inner.write_all(b"WAVE")?;
+
+        inner.write_all(b"fmt ")?;
+        inner.write_all(&16u32.to_le_bytes())?;
+        inner.write_all(&format.format_tag().to_le_bytes())?;
+        inner.write_all(&1u16.to_le_bytes())?;     // mono
+        inner.write_all(&sample_rate.to_le_bytes())?;
+        inner.write_all(&(sample_rate * block_align).to_le_bytes())?;
+        inner.write_all(&(block_align as u16).to_le_bytes())?;
+        inner.write_all(&(block_align as u16 * 8).to_le_bytes())?;
+
+        inner.write_all(b"data")?;
+        inner.write_all(&0u32.to_le_bytes())?;     // patched by finalize
+
+        Ok(Self { inner, format, data_len: 0 })
+    }
+
+    /// Append samples in `-1.0..=1.0`. Out of range samples are clipped
+    /// when writing 16-bit PCM.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &s in samples {
+            match self.format {
+                WavFormat::Pcm16 => {
+                    let v = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    self.inner.write_all(&v.to_le_bytes())?;
+                },
+                WavFormat::Float32 => self.inner.write_all(&s.to_le_bytes())?,
+            }
+        }
+
+        self.data_len += samples.len() as u32 * self.format.bytes_per_sample();
+        Ok(())
+    }
+
+    /// Write the final chunk sizes and hand back the underlying writer.
+    pub fn finalize(mut self) -> io::Result<W> {
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&self.data_len.to_le_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}